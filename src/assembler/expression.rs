@@ -0,0 +1,175 @@
+use assembler::lexer::{Spanned, Token};
+use assembler::Problem;
+use std::collections::HashMap;
+
+// Binary operators from the loosest binding to the tightest.
+const PRECEDENCE: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Here,
+    Symbol(String, usize),
+    Negate(Box<Expression>),
+    Complement(Box<Expression>),
+    // The column is the operator's, for division by zero.
+    Binary(&'static str, Box<Expression>, Box<Expression>, usize),
+}
+
+impl Expression {
+    // None while a symbol is still undefined.
+    pub fn evaluate(
+        &self,
+        symbols: &HashMap<String, i64>,
+        here: i64,
+    ) -> Result<Option<i64>, Problem> {
+        Ok(match *self {
+            Expression::Number(value) => Some(value),
+            Expression::Here => Some(here),
+            Expression::Symbol(ref name, _) => symbols.get(name).cloned(),
+            Expression::Negate(ref operand) => operand
+                .evaluate(symbols, here)?
+                .map(|value| value.wrapping_neg()),
+            Expression::Complement(ref operand) => {
+                operand.evaluate(symbols, here)?.map(|value| !value)
+            }
+            Expression::Binary(operator, ref left, ref right, column) => {
+                let left = left.evaluate(symbols, here)?;
+                let right = right.evaluate(symbols, here)?;
+                match (left, right) {
+                    (Some(left), Some(right)) => Some(apply(operator, left, right, column)?),
+                    _ => None,
+                }
+            }
+        })
+    }
+
+    // The first symbol that `symbols` does not define, with its column.
+    pub fn undefined(&self, symbols: &HashMap<String, i64>) -> Option<(&str, usize)> {
+        match *self {
+            Expression::Symbol(ref name, column) if !symbols.contains_key(name) => {
+                Some((name, column))
+            }
+            Expression::Negate(ref operand) | Expression::Complement(ref operand) => {
+                operand.undefined(symbols)
+            }
+            Expression::Binary(_, ref left, ref right, _) => {
+                left.undefined(symbols).or_else(|| right.undefined(symbols))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn apply(operator: &str, left: i64, right: i64, column: usize) -> Result<i64, Problem> {
+    Ok(match operator {
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => {
+            return Err(Problem::new(column, "division by zero".to_string()))
+        }
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        _ if !(0..64).contains(&right) => {
+            return Err(Problem::new(column, format!("cannot shift by {}", right)))
+        }
+        "<<" => left << right,
+        _ => left >> right,
+    })
+}
+
+struct Parser<'a> {
+    tokens: &'a [Spanned],
+    position: usize,
+    // Where to report a missing operand.
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Spanned> {
+        self.tokens.get(self.position)
+    }
+
+    fn column(&self) -> usize {
+        self.peek().map_or(self.end, |spanned| spanned.column)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, Problem> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(spanned) = self.peek() {
+            let operator = match spanned.token {
+                Token::Symbol(symbol) if PRECEDENCE[level].contains(&symbol) => symbol,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right), spanned.column);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, Problem> {
+        let spanned = match self.peek() {
+            Some(spanned) => spanned,
+            None => return Err(Problem::new(self.end, "expected an expression".to_string())),
+        };
+        self.position += 1;
+        Ok(match spanned.token {
+            Token::Symbol("-") => Expression::Negate(Box::new(self.unary()?)),
+            Token::Symbol("~") => Expression::Complement(Box::new(self.unary()?)),
+            Token::Symbol("+") => self.unary()?,
+            Token::Symbol("(") => {
+                let inner = self.binary(0)?;
+                match self.peek() {
+                    Some(close) if close.is_symbol(")") => self.position += 1,
+                    _ => return Err(Problem::new(self.column(), "expected `)`".to_string())),
+                }
+                inner
+            }
+            Token::Number(value) => Expression::Number(value),
+            Token::Here => Expression::Here,
+            Token::Identifier(ref name) => Expression::Symbol(name.clone(), spanned.column),
+            Token::Text(ref text) if text.chars().count() == 1 => {
+                Expression::Number(text.chars().next().unwrap_or('\0') as i64)
+            }
+            ref token => {
+                return Err(Problem::new(
+                    spanned.column,
+                    format!("expected an expression, found `{}`", token),
+                ))
+            }
+        })
+    }
+}
+
+// Parses all of `tokens` as one expression; `end` is the column just past
+// them.
+pub fn parse(tokens: &[Spanned], end: usize) -> Result<Expression, Problem> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        end,
+    };
+    let expression = parser.binary(0)?;
+    match parser.peek() {
+        Some(spanned) => Err(Problem::new(
+            spanned.column,
+            format!("unexpected `{}`", spanned.token),
+        )),
+        None => Ok(expression),
+    }
+}
@@ -0,0 +1,181 @@
+use assembler::Problem;
+use std::fmt;
+
+// Longer symbols first so that << is not read as two <.
+const SYMBOLS: [&str; 15] = [
+    "<<", ">>", ",", ":", "(", ")", "+", "-", "*", "/", "%", "&", "|", "^", "~",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Identifier(String),
+    Number(i64),
+    // Quoted with either ' or ". A single character is also a value.
+    Text(String),
+    // $ on its own: the address of the current line.
+    Here,
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Identifier(ref name) => write!(f, "{}", name),
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Text(ref text) => write!(f, "\"{}\"", text),
+            Token::Here => write!(f, "$"),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+// Columns count characters from 1; `end` is the column just past the
+// token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spanned {
+    pub token: Token,
+    pub column: usize,
+    pub end: usize,
+}
+
+impl Spanned {
+    pub fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.token, Token::Symbol(found) if found == symbol)
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// 42, 0x2A, 2Ah and 0b101010; $2A is handled by the caller.
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_suffix('h') {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        (digits, 2)
+    } else {
+        (&lower[..], 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+fn read_text(chars: &[char], start: usize) -> Result<(String, usize), Problem> {
+    let quote = chars[start];
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Ok((text, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                text.push(match chars[i + 1] {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    c => c,
+                });
+                i += 2;
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(Problem::new(start + 1, "unterminated string".to_string()))
+}
+
+// Splits one line of source, stopping at a ; comment.
+pub fn tokenize(line: &str) -> Result<Vec<Spanned>, Problem> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            match parse_number(&text) {
+                Some(value) => Token::Number(value),
+                None => return Err(Problem::new(column, format!("invalid number `{}`", text))),
+            }
+        } else if c == '$' {
+            i += 1;
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            if start == i {
+                Token::Here
+            } else {
+                let text: String = chars[start..i].iter().collect();
+                match i64::from_str_radix(&text, 16) {
+                    Ok(value) => Token::Number(value),
+                    Err(_) => {
+                        return Err(Problem::new(column, format!("invalid number `${}`", text)))
+                    }
+                }
+            }
+        } else if is_identifier_start(c) {
+            let start = i;
+            while i < chars.len() && is_identifier_char(chars[i]) {
+                i += 1;
+            }
+            let mut name: String = chars[start..i].iter().collect();
+            // The shadow accumulator pair, as in EX AF,AF'.
+            if i < chars.len() && chars[i] == '\'' && name.eq_ignore_ascii_case("AF") {
+                name.push('\'');
+                i += 1;
+            }
+            Token::Identifier(name)
+        } else if c == '"' || c == '\'' {
+            let (text, end) = read_text(&chars, i)?;
+            i = end;
+            Token::Text(text)
+        } else {
+            let symbol = SYMBOLS.iter().find(|symbol| {
+                symbol
+                    .chars()
+                    .enumerate()
+                    .all(|(offset, expected)| chars.get(i + offset) == Some(&expected))
+            });
+            match symbol {
+                Some(symbol) => {
+                    i += symbol.len();
+                    Token::Symbol(symbol)
+                }
+                None => {
+                    return Err(Problem::new(
+                        column,
+                        format!("unexpected character `{}`", c),
+                    ))
+                }
+            }
+        };
+        tokens.push(Spanned {
+            token,
+            column,
+            end: i + 1,
+        });
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,496 @@
+pub mod expression;
+pub mod lexer;
+pub mod parser;
+
+use assembler::expression::Expression;
+use assembler::parser::{Body, Datum, Operand, OperandKind, Statement};
+use disassembler::{self, Argument, Encoding, Slot};
+use program::Program;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// A two-pass assembler for the syntax the disassembler prints:
+//
+//     VDP_CONTROL EQU 0xBF
+//             ORG 0x0000
+//     start:  LD HL,message
+//             LD B,end - message
+//     loop:   LD A,(HL)
+//             OUT (VDP_CONTROL),A
+//             INC HL
+//             DJNZ loop
+//             HALT
+//     message DB "hi",0
+//     end:
+//
+// Numbers can be written 42, 0x2A, $2A, 2Ah or 0b101010, and $ on its own
+// is the address of the current line. The first pass sizes every line and
+// places the labels; the second encodes with every symbol known.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AssemblyError {}
+
+// An error within a line, before the line number is known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub column: usize,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(column: usize, message: String) -> Problem {
+        Problem { column, message }
+    }
+
+    fn at(self, line: usize) -> AssemblyError {
+        AssemblyError {
+            line,
+            column: self.column,
+            message: self.message,
+        }
+    }
+}
+
+// `program` holds the bytes from `origin` on and Machine::load puts them
+// there.
+pub struct Assembly {
+    pub origin: u16,
+    pub program: Program,
+    pub symbols: HashMap<String, i64>,
+}
+
+fn slot_size(slot: Slot) -> usize {
+    match slot {
+        Slot::Fixed(_) => 0,
+        Slot::Byte | Slot::Port | Slot::Indexed(_) | Slot::Relative => 1,
+        Slot::Word | Slot::Address => 2,
+    }
+}
+
+fn fits(slot: Slot, operand: &OperandKind, value: Option<i64>) -> bool {
+    match (slot, operand) {
+        (Slot::Fixed(Argument::Register(name)), &OperandKind::Register(found))
+        | (Slot::Fixed(Argument::Condition(name)), &OperandKind::Register(found))
+        | (Slot::Fixed(Argument::Indirect(name)), &OperandKind::Indirect(found))
+        | (Slot::Indexed(name), &OperandKind::Indexed(found, _))
+        | (Slot::Indexed(name), &OperandKind::Indirect(found)) => name == found,
+        (Slot::Fixed(Argument::Byte(fixed)), &OperandKind::Immediate(_))
+        | (Slot::Fixed(Argument::Number(fixed)), &OperandKind::Immediate(_)) => {
+            value.is_none_or(|value| value == fixed as i64)
+        }
+        (Slot::Byte, &OperandKind::Immediate(_))
+        | (Slot::Word, &OperandKind::Immediate(_))
+        | (Slot::Relative, &OperandKind::Immediate(_))
+        | (Slot::Address, &OperandKind::Memory(_))
+        | (Slot::Port, &OperandKind::Memory(_)) => true,
+        _ => false,
+    }
+}
+
+fn byte(value: i64, column: usize) -> Result<u8, Problem> {
+    if (-0x80..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(Problem::new(
+            column,
+            format!("{} does not fit in a byte", value),
+        ))
+    }
+}
+
+fn word(value: i64, column: usize) -> Result<[u8; 2], Problem> {
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok([value as u8, (value >> 8) as u8])
+    } else {
+        Err(Problem::new(
+            column,
+            format!("{} does not fit in a word", value),
+        ))
+    }
+}
+
+// The bytes assembled so far and where the next line goes.
+struct Section {
+    origin: u16,
+    location: i64,
+    bytes: Vec<u8>,
+}
+
+impl Section {
+    fn org(&mut self, address: i64, column: usize) -> Result<(), Problem> {
+        if !(0..=0xFFFF).contains(&address) {
+            return Err(Problem::new(
+                column,
+                format!("ORG {} is outside the address space", address),
+            ));
+        }
+        if self.bytes.is_empty() {
+            self.origin = address as u16;
+        } else if address < self.location {
+            return Err(Problem::new(
+                column,
+                format!(
+                    "ORG 0x{:04X} is below the current address 0x{:04X}",
+                    address, self.location
+                ),
+            ));
+        } else {
+            let gap = (address - self.location) as usize;
+            self.bytes.extend(vec![0x00; gap]);
+        }
+        self.location = address;
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8], column: usize) -> Result<(), Problem> {
+        if self.location + bytes.len() as i64 > 0x10000 {
+            return Err(Problem::new(
+                column,
+                "code runs past the end of the address space".to_string(),
+            ));
+        }
+        self.bytes.extend_from_slice(bytes);
+        self.location += bytes.len() as i64;
+        Ok(())
+    }
+}
+
+// An EQU that refers to a later label.
+struct Pending {
+    line: usize,
+    name: String,
+    column: usize,
+    expression: Expression,
+    here: i64,
+}
+
+struct Assembler {
+    encodings: Vec<Encoding>,
+    symbols: HashMap<String, i64>,
+    // Set for the second pass, where every symbol has to be defined.
+    final_pass: bool,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            encodings: disassembler::encodings(),
+            symbols: HashMap::new(),
+            final_pass: false,
+        }
+    }
+
+    fn is_mnemonic(&self, name: &str) -> bool {
+        self.encodings
+            .iter()
+            .any(|encoding| encoding.mnemonic.eq_ignore_ascii_case(name))
+    }
+
+    // None for an undefined symbol in the first pass.
+    fn value(&self, expression: &Expression, here: i64) -> Result<Option<i64>, Problem> {
+        match expression.evaluate(&self.symbols, here)? {
+            Some(value) => Ok(Some(value)),
+            None if !self.final_pass => Ok(None),
+            None => Err(self.undefined(expression, "undefined symbol `{}`")),
+        }
+    }
+
+    // For values that decide where the following lines go, which the first
+    // pass needs straight away.
+    fn known(&self, expression: &Expression, here: i64) -> Result<i64, Problem> {
+        match expression.evaluate(&self.symbols, here)? {
+            Some(value) => Ok(value),
+            None => Err(self.undefined(expression, "`{}` must be defined before this line")),
+        }
+    }
+
+    fn undefined(&self, expression: &Expression, message: &str) -> Problem {
+        let (name, column) = expression.undefined(&self.symbols).unwrap_or(("", 1));
+        Problem::new(column, message.replace("{}", name))
+    }
+
+    fn define(&mut self, name: &str, value: i64, column: usize) -> Result<(), Problem> {
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(Problem::new(
+                column,
+                format!("`{}` is already defined", name),
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        column: usize,
+        operands: &[Operand],
+        here: i64,
+    ) -> Result<Vec<u8>, Problem> {
+        let mut candidates: Vec<&Encoding> = self
+            .encodings
+            .iter()
+            .filter(|encoding| {
+                encoding.mnemonic.eq_ignore_ascii_case(mnemonic)
+                    && encoding.slots.len() == operands.len()
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(Problem::new(
+                column,
+                format!(
+                    "`{}` does not take {} operand{}",
+                    mnemonic.to_ascii_uppercase(),
+                    operands.len(),
+                    if operands.len() == 1 { "" } else { "s" }
+                ),
+            ));
+        }
+        let mut values = Vec::with_capacity(operands.len());
+        for operand in operands {
+            values.push(match operand.kind {
+                OperandKind::Indexed(_, ref expression)
+                | OperandKind::Immediate(ref expression)
+                | OperandKind::Memory(ref expression) => self.value(expression, here)?,
+                OperandKind::Indirect(_) => Some(0),
+                OperandKind::Register(_) => None,
+            });
+        }
+        for (i, operand) in operands.iter().enumerate() {
+            candidates.retain(|encoding| fits(encoding.slots[i], &operand.kind, values[i]));
+            if candidates.is_empty() {
+                return Err(Problem::new(
+                    operand.column,
+                    format!("invalid operand for `{}`", mnemonic.to_ascii_uppercase()),
+                ));
+            }
+        }
+        let encoding = candidates[0];
+        let length = encoding.prefix.len()
+            + encoding
+                .slots
+                .iter()
+                .map(|&slot| slot_size(slot))
+                .sum::<usize>()
+            + encoding.suffix.map_or(0, |_| 1);
+        let mut bytes = encoding.prefix.clone();
+        for ((&slot, operand), value) in encoding.slots.iter().zip(operands).zip(values) {
+            // Undefined symbols only reach here in the first pass, where
+            // just the length counts.
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    bytes.extend(vec![0x00; slot_size(slot)]);
+                    continue;
+                }
+            };
+            match slot {
+                Slot::Fixed(_) => {}
+                Slot::Byte | Slot::Port => bytes.push(byte(value, operand.column)?),
+                Slot::Word | Slot::Address => {
+                    bytes.extend_from_slice(&word(value, operand.column)?)
+                }
+                Slot::Indexed(_) => {
+                    if !(-0x80..=0x7F).contains(&value) {
+                        return Err(Problem::new(
+                            operand.column,
+                            format!("displacement {} is out of range (-128 to 127)", value),
+                        ));
+                    }
+                    bytes.push(value as u8);
+                }
+                Slot::Relative => {
+                    let offset = value - (here + length as i64);
+                    if self.final_pass && !(-0x80..=0x7F).contains(&offset) {
+                        return Err(Problem::new(
+                            operand.column,
+                            format!(
+                                "relative jump to 0x{:04X} is out of range (offset {})",
+                                value, offset
+                            ),
+                        ));
+                    }
+                    bytes.push(offset as u8);
+                }
+            }
+        }
+        if let Some(suffix) = encoding.suffix {
+            bytes.push(suffix);
+        }
+        Ok(bytes)
+    }
+
+    fn data(&self, data: &[Datum], here: i64) -> Result<Vec<u8>, Problem> {
+        let mut bytes = Vec::new();
+        for datum in data {
+            match *datum {
+                Datum::Text(ref text, column) => {
+                    for c in text.chars() {
+                        bytes.push(byte(c as i64, column)?);
+                    }
+                }
+                Datum::Value(ref expression, column) => match self.value(expression, here)? {
+                    Some(value) => bytes.push(byte(value, column)?),
+                    None => bytes.push(0x00),
+                },
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn words(&self, words: &[(Expression, usize)], here: i64) -> Result<Vec<u8>, Problem> {
+        let mut bytes = Vec::new();
+        for &(ref expression, column) in words {
+            let value = self.value(expression, here)?.unwrap_or(0);
+            bytes.extend_from_slice(&word(value, column)?);
+        }
+        Ok(bytes)
+    }
+
+    fn line(
+        &mut self,
+        number: usize,
+        statement: &Statement,
+        section: &mut Section,
+        pending: &mut Vec<Pending>,
+    ) -> Result<(), Problem> {
+        let here = section.location;
+        if let Some((ref name, column)) = statement.label {
+            let equ = matches!(statement.body, Some((Body::Equ(_), _)));
+            if !equ && !self.final_pass {
+                self.define(name, here, column)?;
+            }
+        }
+        let (body, column) = match statement.body {
+            Some((ref body, column)) => (body, column),
+            None => return Ok(()),
+        };
+        match *body {
+            Body::Equ(ref expression) => {
+                let (name, label_column) = match statement.label {
+                    Some((ref name, label_column)) => (name, label_column),
+                    None => return Err(Problem::new(column, "EQU needs a label".to_string())),
+                };
+                if !self.final_pass {
+                    match expression.evaluate(&self.symbols, here)? {
+                        Some(value) => self.define(name, value, label_column)?,
+                        None => pending.push(Pending {
+                            line: number,
+                            name: name.clone(),
+                            column: label_column,
+                            expression: expression.clone(),
+                            here,
+                        }),
+                    }
+                }
+            }
+            Body::Org(ref expression) => {
+                let address = self.known(expression, here)?;
+                section.org(address, column)?;
+            }
+            Body::Bytes(ref data) => {
+                let bytes = self.data(data, here)?;
+                section.emit(&bytes, column)?;
+            }
+            Body::Words(ref words) => {
+                let bytes = self.words(words, here)?;
+                section.emit(&bytes, column)?;
+            }
+            Body::Space(ref count, ref fill) => {
+                let count = self.known(count, here)?;
+                if !(0..=0x10000).contains(&count) {
+                    return Err(Problem::new(
+                        column,
+                        format!("DS {} is not a valid size", count),
+                    ));
+                }
+                let fill = match *fill {
+                    Some(ref fill) => self.value(fill, here)?.unwrap_or(0),
+                    None => 0,
+                };
+                section.emit(&vec![byte(fill, column)?; count as usize], column)?;
+            }
+            Body::Instruction(ref mnemonic, ref operands) => {
+                let bytes = self.encode(mnemonic, column, operands, here)?;
+                section.emit(&bytes, column)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pass(&mut self, lines: &[(usize, Statement)]) -> Result<Section, AssemblyError> {
+        let mut section = Section {
+            origin: 0,
+            location: 0,
+            bytes: Vec::new(),
+        };
+        let mut pending = Vec::new();
+        for &(number, ref statement) in lines {
+            self.line(number, statement, &mut section, &mut pending)
+                .map_err(|problem| problem.at(number))?;
+        }
+        // Each round defines the EQUs whose symbols are all known, until
+        // a round makes no progress.
+        while !pending.is_empty() {
+            let before = pending.len();
+            let mut unresolved = Vec::new();
+            for equ in pending {
+                let at = |problem: Problem| problem.at(equ.line);
+                match equ
+                    .expression
+                    .evaluate(&self.symbols, equ.here)
+                    .map_err(at)?
+                {
+                    Some(value) => self.define(&equ.name, value, equ.column).map_err(at)?,
+                    None => unresolved.push(equ),
+                }
+            }
+            if unresolved.len() == before {
+                let equ = &unresolved[0];
+                let problem = self.undefined(&equ.expression, "undefined symbol `{}`");
+                return Err(problem.at(equ.line));
+            }
+            pending = unresolved;
+        }
+        Ok(section)
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Assembly, AssemblyError> {
+    let mut assembler = Assembler::new();
+    let mut lines = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let tokens = lexer::tokenize(text).map_err(|problem| problem.at(number))?;
+        let end = text.chars().count() + 1;
+        let statement = parser::parse_line(&tokens, end, &|name| assembler.is_mnemonic(name))
+            .map_err(|problem| problem.at(number))?;
+        lines.push((number, statement));
+    }
+    assembler.pass(&lines)?;
+    assembler.final_pass = true;
+    let section = assembler.pass(&lines)?;
+    let mut program = Program::with_origin(section.origin);
+    program.add_vector(section.bytes);
+    Ok(Assembly {
+        origin: section.origin,
+        program,
+        symbols: assembler.symbols,
+    })
+}
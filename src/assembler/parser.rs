@@ -0,0 +1,285 @@
+use assembler::expression::{self, Expression};
+use assembler::lexer::{Spanned, Token};
+use assembler::Problem;
+
+// Names that are operands rather than symbols. C is both a register and a
+// condition; the encoding decides which.
+const REGISTERS: [&str; 28] = [
+    "A", "B", "C", "D", "E", "H", "L", "I", "R", "AF", "AF'", "BC", "DE", "HL", "SP", "IX", "IY",
+    "IXH", "IXL", "IYH", "IYL", "NZ", "Z", "NC", "PO", "PE", "P", "M",
+];
+
+pub fn register(name: &str) -> Option<&'static str> {
+    REGISTERS
+        .iter()
+        .find(|register| register.eq_ignore_ascii_case(name))
+        .cloned()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    Register(&'static str),
+    // (HL), (C), (IX) and the like.
+    Indirect(&'static str),
+    Indexed(&'static str, Expression),
+    Immediate(Expression),
+    // A parenthesised expression: an address or a port.
+    Memory(Expression),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operand {
+    pub kind: OperandKind,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Datum {
+    Text(String, usize),
+    Value(Expression, usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Body {
+    Org(Expression),
+    Equ(Expression),
+    Bytes(Vec<Datum>),
+    Words(Vec<(Expression, usize)>),
+    Space(Expression, Option<Expression>),
+    Instruction(String, Vec<Operand>),
+}
+
+// Either half may be missing: a line can hold just a label, just an
+// instruction or nothing at all.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Statement {
+    pub label: Option<(String, usize)>,
+    // With the column of the mnemonic or directive.
+    pub body: Option<(Body, usize)>,
+}
+
+fn directive(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches('.').to_ascii_uppercase();
+    Some(match name.as_str() {
+        "ORG" => "ORG",
+        "EQU" => "EQU",
+        "DB" | "DEFB" | "DEFM" => "DB",
+        "DW" | "DEFW" => "DW",
+        "DS" | "DEFS" => "DS",
+        _ => return None,
+    })
+}
+
+fn identifier(spanned: Option<&Spanned>) -> Option<&str> {
+    match spanned {
+        Some(&Spanned {
+            token: Token::Identifier(ref name),
+            ..
+        }) => Some(name),
+        _ => None,
+    }
+}
+
+// Splits on commas outside parentheses, keeping the column after each
+// comma for an empty operand.
+fn split_operands(tokens: &[Spanned], end: usize) -> Vec<(&[Spanned], usize)> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut column = tokens.first().map_or(end, |spanned| spanned.column);
+    for (i, spanned) in tokens.iter().enumerate() {
+        if spanned.is_symbol("(") {
+            depth += 1;
+        } else if spanned.is_symbol(")") {
+            depth -= 1;
+        } else if spanned.is_symbol(",") && depth == 0 {
+            operands.push((&tokens[start..i], column));
+            start = i + 1;
+            column = spanned.column + 1;
+        }
+    }
+    operands.push((&tokens[start..], column));
+    operands
+}
+
+// The index of the parenthesis closing the one at tokens[0].
+fn closing(tokens: &[Spanned]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, spanned) in tokens.iter().enumerate() {
+        if spanned.is_symbol("(") {
+            depth += 1;
+        } else if spanned.is_symbol(")") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn end_of(tokens: &[Spanned], fallback: usize) -> usize {
+    tokens.last().map_or(fallback, |spanned| spanned.end)
+}
+
+fn parse_operand(tokens: &[Spanned], column: usize) -> Result<Operand, Problem> {
+    let end = end_of(tokens, column);
+    if tokens.is_empty() {
+        return Err(Problem::new(column, "missing operand".to_string()));
+    }
+    let kind = if let (1, Some(name)) = (tokens.len(), identifier(tokens.first())) {
+        match register(name) {
+            Some(name) => OperandKind::Register(name),
+            None => OperandKind::Immediate(expression::parse(tokens, end)?),
+        }
+    } else if tokens[0].is_symbol("(") && closing(tokens) == Some(tokens.len() - 1) {
+        let inner = &tokens[1..tokens.len() - 1];
+        let inner_end = tokens[tokens.len() - 1].column;
+        match identifier(inner.first()).and_then(register) {
+            Some(name) if inner.len() == 1 => OperandKind::Indirect(name),
+            Some(name)
+                if (name == "IX" || name == "IY")
+                    && (inner[1].is_symbol("+") || inner[1].is_symbol("-")) =>
+            {
+                OperandKind::Indexed(name, expression::parse(&inner[1..], inner_end)?)
+            }
+            _ => OperandKind::Memory(expression::parse(inner, inner_end)?),
+        }
+    } else {
+        OperandKind::Immediate(expression::parse(tokens, end)?)
+    };
+    Ok(Operand {
+        kind,
+        column: tokens[0].column,
+    })
+}
+
+fn parse_body(name: &str, tokens: &[Spanned], end: usize) -> Result<Body, Problem> {
+    let operands = if tokens.is_empty() {
+        Vec::new()
+    } else {
+        split_operands(tokens, end)
+    };
+    let single = |what: &str| -> Result<Expression, Problem> {
+        match operands.len() {
+            1 => expression::parse(operands[0].0, end_of(operands[0].0, operands[0].1)),
+            0 => Err(Problem::new(end, format!("{} needs a value", what))),
+            _ => Err(Problem::new(
+                operands[1].1,
+                format!("{} takes one value", what),
+            )),
+        }
+    };
+    Ok(match directive(name) {
+        Some("ORG") => Body::Org(single("ORG")?),
+        Some("EQU") => Body::Equ(single("EQU")?),
+        Some("DB") => {
+            let mut data = Vec::new();
+            for &(tokens, column) in &operands {
+                data.push(match tokens {
+                    [Spanned {
+                        token: Token::Text(text),
+                        column,
+                        ..
+                    }] if text.chars().count() != 1 => Datum::Text(text.clone(), *column),
+                    _ => {
+                        let value = expression::parse(tokens, end_of(tokens, column))?;
+                        Datum::Value(value, tokens.first().map_or(column, |t| t.column))
+                    }
+                });
+            }
+            if data.is_empty() {
+                return Err(Problem::new(end, "DB needs at least one value".to_string()));
+            }
+            Body::Bytes(data)
+        }
+        Some("DW") => {
+            let mut words = Vec::new();
+            for &(tokens, column) in &operands {
+                let value = expression::parse(tokens, end_of(tokens, column))?;
+                words.push((value, tokens.first().map_or(column, |t| t.column)));
+            }
+            if words.is_empty() {
+                return Err(Problem::new(end, "DW needs at least one value".to_string()));
+            }
+            Body::Words(words)
+        }
+        Some(_) => {
+            if operands.len() > 2 {
+                return Err(Problem::new(
+                    operands[2].1,
+                    "DS takes a size and a fill byte".to_string(),
+                ));
+            }
+            let mut values = Vec::new();
+            for &(tokens, column) in &operands {
+                values.push(expression::parse(tokens, end_of(tokens, column))?);
+            }
+            let fill = if values.len() == 2 {
+                values.pop()
+            } else {
+                None
+            };
+            match values.pop() {
+                Some(count) => Body::Space(count, fill),
+                None => return Err(Problem::new(end, "DS needs a size".to_string())),
+            }
+        }
+        None => {
+            let mut parsed = Vec::new();
+            for &(tokens, column) in &operands {
+                parsed.push(parse_operand(tokens, column)?);
+            }
+            Body::Instruction(name.to_string(), parsed)
+        }
+    })
+}
+
+// A label ends in a colon, comes before EQU or starts in the first column
+// without being a known mnemonic.
+pub fn parse_line(
+    tokens: &[Spanned],
+    end: usize,
+    is_mnemonic: &dyn Fn(&str) -> bool,
+) -> Result<Statement, Problem> {
+    let mut statement = Statement::default();
+    let mut rest = tokens;
+    if let Some(name) = identifier(tokens.first()) {
+        let column = tokens[0].column;
+        let colon = tokens.get(1).is_some_and(|spanned| spanned.is_symbol(":"));
+        let equ = identifier(tokens.get(1)).and_then(directive) == Some("EQU");
+        let bare = column == 1 && directive(name).is_none() && !is_mnemonic(name);
+        if colon || equ || bare {
+            if register(name).is_some() {
+                return Err(Problem::new(
+                    column,
+                    format!("`{}` is a register and cannot be a label", name),
+                ));
+            }
+            statement.label = Some((name.to_string(), column));
+            rest = &tokens[if colon { 2 } else { 1 }..];
+        }
+    }
+    let first = match rest.first() {
+        Some(first) => first,
+        None => return Ok(statement),
+    };
+    let name = match first.token {
+        Token::Identifier(ref name) => name,
+        ref token => {
+            return Err(Problem::new(
+                first.column,
+                format!("expected an instruction, found `{}`", token),
+            ))
+        }
+    };
+    if directive(name).is_none() && !is_mnemonic(name) {
+        return Err(Problem::new(
+            first.column,
+            format!("unknown instruction `{}`", name),
+        ));
+    }
+    let body = parse_body(name, &rest[1..], end)?;
+    statement.body = Some((body, first.column));
+    Ok(statement)
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fmt;
+use vm::io::joypad::{Buttons, Player};
+use vm::machine::Machine;
+
+// One event per line, applied before the numbered frame runs:
+//
+//     # frame  target  buttons
+//     0        1       -
+//     60       1       right+1
+//     90       2       up+left+2
+//     120      pause
+//     180      reset
+//
+// A pad keeps its buttons until its next event; pause and reset are
+// pressed for their frame only.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Pad(Player, Buttons),
+    Pause,
+    Reset,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input script line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ScriptError {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, ScriptError> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let frame = fields[0]
+                .parse()
+                .map_err(|_| error(format!("bad frame number `{}`", fields[0])))?;
+            let action = match fields[1..] {
+                ["pause"] => Action::Pause,
+                ["reset"] => Action::Reset,
+                [player @ "1"] | [player @ "2"] => {
+                    return Err(error(format!("missing buttons for pad {}", player)))
+                }
+                ["1", buttons] => Action::Pad(Player::One, parse_buttons(buttons).map_err(error)?),
+                ["2", buttons] => Action::Pad(Player::Two, parse_buttons(buttons).map_err(error)?),
+                _ => return Err(error(format!("cannot parse `{}`", line))),
+            };
+            events.push(InputEvent { frame, action });
+        }
+        // Stable, so events for the same frame keep their order.
+        events.sort_by_key(|event| event.frame);
+        Ok(InputScript { events })
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn apply(&self, frame: u64, machine: &mut Machine) {
+        let mut reset = false;
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            match event.action {
+                Action::Pad(player, buttons) => machine.set_input(player, buttons),
+                Action::Pause => machine.press_pause(),
+                Action::Reset => reset = true,
+            }
+        }
+        machine.set_reset_button(reset);
+    }
+}
+
+fn parse_buttons(text: &str) -> Result<Buttons, String> {
+    let mut buttons = Buttons::default();
+    if text == "-" {
+        return Ok(buttons);
+    }
+    for name in text.split('+') {
+        match name {
+            "up" => buttons.up = true,
+            "down" => buttons.down = true,
+            "left" => buttons.left = true,
+            "right" => buttons.right = true,
+            "1" => buttons.button1 = true,
+            "2" => buttons.button2 = true,
+            _ => return Err(format!("unknown button `{}`", name)),
+        }
+    }
+    Ok(buttons)
+}
@@ -0,0 +1,222 @@
+pub mod image;
+pub mod input;
+pub mod wav;
+
+use cli::input::{InputScript, ScriptError};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use vm::cartridge::{Cartridge, CartridgeError};
+use vm::instructions::error::ExecError;
+use vm::machine::{Machine, MachineConfig, Region};
+use vm::video::timing::TvSystem;
+use vm::video::vdp::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const USAGE: &str = "\
+usage: rusty_sms ROM [options]
+
+Runs ROM without a display and writes what it produced.
+
+options:
+    --frames N                run for N frames (default 60)
+    --input FILE              apply the scripted joypad input in FILE
+    --screenshot FILE         save the last frame as PNG, or PPM for .ppm
+    --screenshot-at N:FILE    save the screen after N >= 1 frames; repeatable
+    --wav FILE                save the audio as 16-bit mono WAV
+    --region japan|export     override the region from the ROM header
+    --pal                     run at 50 Hz instead of 60 Hz
+    --fm                      fit the YM2413 FM sound unit
+    --sample-rate HZ          audio sample rate, 8000 to 192000 (default 44100)
+    -h, --help                print this message";
+
+const DEFAULT_FRAMES: u64 = 60;
+const SAMPLE_RATES: RangeInclusive<u32> = 8_000..=192_000;
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Io(PathBuf, io::Error),
+    Cartridge(CartridgeError),
+    Script(ScriptError),
+    Exec(u64, ExecError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Usage(ref message) => write!(f, "{}", message),
+            CliError::Io(ref path, ref error) => write!(f, "{}: {}", path.display(), error),
+            CliError::Cartridge(ref error) => write!(f, "{}", error),
+            CliError::Script(ref error) => write!(f, "{}", error),
+            CliError::Exec(frame, ref error) => write!(f, "frame {}: {}", frame, error),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub frames: u64,
+    pub input: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    pub screenshots_at: Vec<(u64, PathBuf)>,
+    pub wav: Option<PathBuf>,
+    pub config: MachineConfig,
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+        let mut args = args.into_iter();
+        let mut rom = None;
+        let mut options = Options {
+            rom: PathBuf::new(),
+            frames: DEFAULT_FRAMES,
+            input: None,
+            screenshot: None,
+            screenshots_at: Vec::new(),
+            wav: None,
+            config: MachineConfig::default(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| CliError::Usage(format!("{} needs a value", arg)))
+            };
+            match arg.as_str() {
+                "--frames" => options.frames = parse_number(&value()?)?,
+                "--input" => options.input = Some(value()?.into()),
+                "--screenshot" => options.screenshot = Some(value()?.into()),
+                "--screenshot-at" => {
+                    let value = value()?;
+                    let mut parts = value.splitn(2, ':');
+                    let frame = parse_number(parts.next().unwrap_or(""))?;
+                    // Nothing has been drawn before the first frame.
+                    if frame == 0 {
+                        return Err(CliError::Usage(
+                            "--screenshot-at needs a frame of at least 1".to_string(),
+                        ));
+                    }
+                    match parts.next() {
+                        Some(path) if !path.is_empty() => {
+                            options.screenshots_at.push((frame, path.into()))
+                        }
+                        _ => {
+                            return Err(CliError::Usage(format!(
+                                "expected N:FILE, got `{}`",
+                                value
+                            )))
+                        }
+                    }
+                }
+                "--wav" => options.wav = Some(value()?.into()),
+                "--region" => {
+                    options.config.region = Some(match value()?.as_str() {
+                        "japan" => Region::Japan,
+                        "export" => Region::Export,
+                        other => {
+                            return Err(CliError::Usage(format!("unknown region `{}`", other)))
+                        }
+                    })
+                }
+                "--pal" => options.config.tv_system = TvSystem::Pal,
+                "--fm" => options.config.fm_sound = true,
+                "--sample-rate" => {
+                    let rate = parse_number(&value()?)?;
+                    if !SAMPLE_RATES.contains(&rate) {
+                        return Err(CliError::Usage(format!(
+                            "sample rate {} is not between {} and {}",
+                            rate,
+                            SAMPLE_RATES.start(),
+                            SAMPLE_RATES.end()
+                        )));
+                    }
+                    options.config.sample_rate = rate;
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(CliError::Usage(format!("unknown option `{}`", arg)))
+                }
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(CliError::Usage(format!("unexpected argument `{}`", arg))),
+            }
+        }
+        options.rom = rom.ok_or_else(|| CliError::Usage("no ROM given".to_string()))?;
+        Ok(options)
+    }
+}
+
+fn parse_number<T: ::std::str::FromStr>(text: &str) -> Result<T, CliError> {
+    text.parse()
+        .map_err(|_| CliError::Usage(format!("`{}` is not a valid number", text)))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|error| CliError::Io(path.to_path_buf(), error))
+}
+
+fn create_file<F>(path: &Path, write: F) -> Result<(), CliError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let result = File::create(path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()
+    });
+    result.map_err(|error| CliError::Io(path.to_path_buf(), error))
+}
+
+pub fn save_screenshot(path: &Path, pixels: &[u32]) -> Result<(), CliError> {
+    let ppm = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ppm"));
+    create_file(path, |out| {
+        if ppm {
+            image::write_ppm(out, SCREEN_WIDTH, SCREEN_HEIGHT, pixels)
+        } else {
+            image::write_png(out, SCREEN_WIDTH, SCREEN_HEIGHT, pixels)
+        }
+    })
+}
+
+pub fn run(options: &Options) -> Result<(), CliError> {
+    let cartridge = Cartridge::new(read_file(&options.rom)?).map_err(CliError::Cartridge)?;
+    for warning in cartridge.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    let script = match options.input {
+        Some(ref path) => {
+            let text = String::from_utf8_lossy(&read_file(path)?).into_owned();
+            InputScript::parse(&text).map_err(CliError::Script)?
+        }
+        None => InputScript::default(),
+    };
+
+    let mut machine = Machine::with_config(cartridge, options.config);
+    let mut audio = Vec::new();
+    for frame in 0..options.frames {
+        script.apply(frame, &mut machine);
+        machine
+            .run_frame()
+            .map_err(|error| CliError::Exec(frame, error))?;
+        audio.extend(machine.take_audio());
+        for (at, path) in &options.screenshots_at {
+            if *at == frame + 1 {
+                save_screenshot(path, machine.vdp().framebuffer())?;
+            }
+        }
+    }
+
+    if let Some(ref path) = options.screenshot {
+        save_screenshot(path, machine.vdp().framebuffer())?;
+    }
+    if let Some(ref path) = options.wav {
+        let sample_rate = options.config.sample_rate;
+        create_file(path, |out| wav::write_wav(out, sample_rate, &audio))?;
+    }
+    Ok(())
+}
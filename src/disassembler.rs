@@ -0,0 +1,833 @@
+use std::convert::TryFrom;
+use std::fmt;
+use vm::instructions::opcodes::{BitOpcode, ExtendedOpcode, Opcode, Operand};
+use vm::ram::bus::Bus;
+
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const STACK_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Argument {
+    Register(&'static str),
+    Indirect(&'static str),
+    Indexed(&'static str, i8),
+    Byte(u8),
+    Word(u16),
+    Address(u16),
+    Port(u8),
+    Condition(&'static str),
+    // Bit numbers and interrupt modes.
+    Number(u8),
+}
+
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Argument::Register(name) | Argument::Condition(name) => write!(f, "{}", name),
+            Argument::Indirect(name) => write!(f, "({})", name),
+            Argument::Indexed(name, offset) if offset < 0 => {
+                write!(f, "({}-0x{:02X})", name, -(offset as i16))
+            }
+            Argument::Indexed(name, offset) => write!(f, "({}+0x{:02X})", name, offset),
+            Argument::Byte(value) => write!(f, "0x{:02X}", value),
+            Argument::Word(value) => write!(f, "0x{:04X}", value),
+            Argument::Address(value) => write!(f, "(0x{:04X})", value),
+            Argument::Port(value) => write!(f, "(0x{:02X})", value),
+            Argument::Number(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// Bytes that do not start a known instruction come out as a one byte DB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Argument>,
+    pub length: usize,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            let separator = if i == 0 { " " } else { "," };
+            write!(f, "{}{}", separator, operand)?;
+        }
+        Ok(())
+    }
+}
+
+// What a template still has to read from the instruction stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Slot {
+    Fixed(Argument),
+    Byte,
+    Word,
+    Address,
+    Port,
+    // (IX+d) or (IY+d), reading the displacement.
+    Indexed(&'static str),
+    // A signed offset from the end of the instruction, as JR and DJNZ use.
+    Relative,
+}
+
+// One way to encode an instruction, which the assembler matches operands
+// against. The immediates follow `prefix` in operand order, then `suffix`
+// for the DD CB d op forms.
+pub(crate) struct Encoding {
+    pub mnemonic: &'static str,
+    pub slots: Vec<Slot>,
+    pub prefix: Vec<u8>,
+    pub suffix: Option<u8>,
+}
+
+struct Index {
+    prefix: u8,
+    pair: &'static str,
+    high: &'static str,
+    low: &'static str,
+}
+
+const IX: Index = Index {
+    prefix: 0xDD,
+    pair: "IX",
+    high: "IXH",
+    low: "IXL",
+};
+
+const IY: Index = Index {
+    prefix: 0xFD,
+    pair: "IY",
+    high: "IYH",
+    low: "IYL",
+};
+
+struct Reader<'a> {
+    fetch: &'a dyn Fn(u16) -> Option<u8>,
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> Option<u8> {
+        let address = self.address.wrapping_add(self.bytes.len() as u16);
+        let byte = (self.fetch)(address)?;
+        self.bytes.push(byte);
+        Some(byte)
+    }
+
+    fn next_word(&mut self) -> Option<u16> {
+        let low = self.next()? as u16;
+        let high = self.next()? as u16;
+        Some((high << 8) | low)
+    }
+
+    fn build(&self, mnemonic: &'static str, operands: Vec<Argument>) -> Instruction {
+        Instruction {
+            address: self.address,
+            bytes: self.bytes.clone(),
+            mnemonic,
+            operands,
+            length: self.bytes.len(),
+        }
+    }
+
+    // Reads the template's immediates in order.
+    fn finish(&mut self, mnemonic: &'static str, slots: &[Slot]) -> Option<Instruction> {
+        let mut operands = Vec::with_capacity(slots.len());
+        for &slot in slots {
+            let operand = match slot {
+                Slot::Fixed(operand) => operand,
+                Slot::Byte => Argument::Byte(self.next()?),
+                Slot::Word => Argument::Word(self.next_word()?),
+                Slot::Address => Argument::Address(self.next_word()?),
+                Slot::Port => Argument::Port(self.next()?),
+                Slot::Indexed(pair) => Argument::Indexed(pair, self.next()? as i8),
+                Slot::Relative => {
+                    let offset = self.next()? as i8;
+                    let end = self.address.wrapping_add(self.bytes.len() as u16);
+                    Argument::Word(end.wrapping_add(offset as u16))
+                }
+            };
+            operands.push(operand);
+        }
+        Some(self.build(mnemonic, operands))
+    }
+}
+
+fn data(address: u16, byte: u8) -> Instruction {
+    Instruction {
+        address,
+        bytes: vec![byte],
+        mnemonic: "DB",
+        operands: vec![Argument::Byte(byte)],
+        length: 1,
+    }
+}
+
+fn operand(operand: Operand) -> Slot {
+    Slot::Fixed(match operand {
+        Operand::A => Argument::Register("A"),
+        Operand::B => Argument::Register("B"),
+        Operand::C => Argument::Register("C"),
+        Operand::D => Argument::Register("D"),
+        Operand::E => Argument::Register("E"),
+        Operand::H => Argument::Register("H"),
+        Operand::L => Argument::Register("L"),
+        Operand::IndirectHL => Argument::Indirect("HL"),
+    })
+}
+
+fn register(name: &'static str) -> Slot {
+    Slot::Fixed(Argument::Register(name))
+}
+
+fn indirect(name: &'static str) -> Slot {
+    Slot::Fixed(Argument::Indirect(name))
+}
+
+fn condition(byte: u8) -> Slot {
+    Slot::Fixed(Argument::Condition(CONDITIONS[(byte >> 3) as usize & 0x07]))
+}
+
+fn register_pair(byte: u8) -> Slot {
+    register(REGISTER_PAIRS[(byte >> 4) as usize & 0x03])
+}
+
+// With an index register, HL becomes IX or IY, (HL) takes a displacement
+// and H and L become the halves of the index register unless (HL) is also
+// used.
+fn index_slots(slots: &[Slot], index: &Index) -> Vec<Slot> {
+    let displaced = slots.contains(&indirect("HL"));
+    slots
+        .iter()
+        .map(|&slot| match slot {
+            Slot::Fixed(Argument::Register("HL")) => register(index.pair),
+            Slot::Fixed(Argument::Register("H")) if !displaced => register(index.high),
+            Slot::Fixed(Argument::Register("L")) if !displaced => register(index.low),
+            Slot::Fixed(Argument::Indirect("HL")) => Slot::Indexed(index.pair),
+            slot => slot,
+        })
+        .collect()
+}
+
+fn uses_hl(slots: &[Slot]) -> bool {
+    slots.iter().any(|slot| match *slot {
+        Slot::Fixed(Argument::Register(name)) => name == "HL" || name == "H" || name == "L",
+        Slot::Fixed(Argument::Indirect(name)) => name == "HL",
+        _ => false,
+    })
+}
+
+// ADD, ADC, SUB, SBC, AND, XOR, OR and CP share a layout in both the
+// register and the immediate forms.
+fn arithmetic(byte: u8, source: Slot) -> (&'static str, Vec<Slot>) {
+    let accumulator = register("A");
+    match (byte >> 3) & 0x07 {
+        0 => ("ADD", vec![accumulator, source]),
+        1 => ("ADC", vec![accumulator, source]),
+        2 => ("SUB", vec![source]),
+        3 => ("SBC", vec![accumulator, source]),
+        4 => ("AND", vec![source]),
+        5 => ("XOR", vec![source]),
+        6 => ("OR", vec![source]),
+        _ => ("CP", vec![source]),
+    }
+}
+
+fn base_template(opcode: Opcode, byte: u8) -> (&'static str, Vec<Slot>) {
+    match opcode {
+        Opcode::Nop => ("NOP", vec![]),
+        Opcode::Halt => ("HALT", vec![]),
+
+        Opcode::LdBB
+        | Opcode::LdBC
+        | Opcode::LdBD
+        | Opcode::LdBE
+        | Opcode::LdBH
+        | Opcode::LdBL
+        | Opcode::LdBHL
+        | Opcode::LdBA
+        | Opcode::LdCB
+        | Opcode::LdCC
+        | Opcode::LdCD
+        | Opcode::LdCE
+        | Opcode::LdCH
+        | Opcode::LdCL
+        | Opcode::LdCHL
+        | Opcode::LdCA
+        | Opcode::LdDB
+        | Opcode::LdDC
+        | Opcode::LdDD
+        | Opcode::LdDE
+        | Opcode::LdDH
+        | Opcode::LdDL
+        | Opcode::LdDHL
+        | Opcode::LdDA
+        | Opcode::LdEB
+        | Opcode::LdEC
+        | Opcode::LdED
+        | Opcode::LdEE
+        | Opcode::LdEH
+        | Opcode::LdEL
+        | Opcode::LdEHL
+        | Opcode::LdEA
+        | Opcode::LdHB
+        | Opcode::LdHC
+        | Opcode::LdHD
+        | Opcode::LdHE
+        | Opcode::LdHH
+        | Opcode::LdHL
+        | Opcode::LdHHL
+        | Opcode::LdHA
+        | Opcode::LdLB
+        | Opcode::LdLC
+        | Opcode::LdLD
+        | Opcode::LdLE
+        | Opcode::LdLH
+        | Opcode::LdLL
+        | Opcode::LdLHL
+        | Opcode::LdLA
+        | Opcode::LdHLB
+        | Opcode::LdHLC
+        | Opcode::LdHLD
+        | Opcode::LdHLE
+        | Opcode::LdHLH
+        | Opcode::LdHLL
+        | Opcode::LdHLA
+        | Opcode::LdAB
+        | Opcode::LdAC
+        | Opcode::LdAD
+        | Opcode::LdAE
+        | Opcode::LdAH
+        | Opcode::LdAL
+        | Opcode::LdAHL
+        | Opcode::LdAA => (
+            "LD",
+            vec![
+                operand(Operand::from_bits(byte >> 3)),
+                operand(Operand::from_bits(byte)),
+            ],
+        ),
+
+        Opcode::AddB
+        | Opcode::AddC
+        | Opcode::AddD
+        | Opcode::AddE
+        | Opcode::AddH
+        | Opcode::AddL
+        | Opcode::AddVHL
+        | Opcode::AddA
+        | Opcode::AdcB
+        | Opcode::AdcC
+        | Opcode::AdcD
+        | Opcode::AdcE
+        | Opcode::AdcH
+        | Opcode::AdcL
+        | Opcode::AdcVHL
+        | Opcode::AdcA
+        | Opcode::SubB
+        | Opcode::SubC
+        | Opcode::SubD
+        | Opcode::SubE
+        | Opcode::SubH
+        | Opcode::SubL
+        | Opcode::SubVHL
+        | Opcode::SubA
+        | Opcode::SbcB
+        | Opcode::SbcC
+        | Opcode::SbcD
+        | Opcode::SbcE
+        | Opcode::SbcH
+        | Opcode::SbcL
+        | Opcode::SbcVHL
+        | Opcode::SbcA
+        | Opcode::AndB
+        | Opcode::AndC
+        | Opcode::AndD
+        | Opcode::AndE
+        | Opcode::AndH
+        | Opcode::AndL
+        | Opcode::AndVHL
+        | Opcode::AndA
+        | Opcode::XorB
+        | Opcode::XorC
+        | Opcode::XorD
+        | Opcode::XorE
+        | Opcode::XorH
+        | Opcode::XorL
+        | Opcode::XorVHL
+        | Opcode::XorA
+        | Opcode::OrB
+        | Opcode::OrC
+        | Opcode::OrD
+        | Opcode::OrE
+        | Opcode::OrH
+        | Opcode::OrL
+        | Opcode::OrVHL
+        | Opcode::OrA
+        | Opcode::CpB
+        | Opcode::CpC
+        | Opcode::CpD
+        | Opcode::CpE
+        | Opcode::CpH
+        | Opcode::CpL
+        | Opcode::CpVHL
+        | Opcode::CpA => arithmetic(byte, operand(Operand::from_bits(byte))),
+
+        Opcode::AddX
+        | Opcode::AdcX
+        | Opcode::SubX
+        | Opcode::SbcX
+        | Opcode::AndX
+        | Opcode::XorX
+        | Opcode::OrX
+        | Opcode::CpX => arithmetic(byte, Slot::Byte),
+
+        Opcode::IncB
+        | Opcode::IncC
+        | Opcode::IncD
+        | Opcode::IncE
+        | Opcode::IncH
+        | Opcode::IncL
+        | Opcode::IncVHL
+        | Opcode::IncA => ("INC", vec![operand(Operand::from_bits(byte >> 3))]),
+        Opcode::DecB
+        | Opcode::DecC
+        | Opcode::DecD
+        | Opcode::DecE
+        | Opcode::DecH
+        | Opcode::DecL
+        | Opcode::DecVHL
+        | Opcode::DecA => ("DEC", vec![operand(Operand::from_bits(byte >> 3))]),
+        Opcode::LdBX
+        | Opcode::LdCX
+        | Opcode::LdDX
+        | Opcode::LdEX
+        | Opcode::LdHX
+        | Opcode::LdLX
+        | Opcode::LdVHLX
+        | Opcode::LdAX => (
+            "LD",
+            vec![operand(Operand::from_bits(byte >> 3)), Slot::Byte],
+        ),
+
+        Opcode::LdBCXX | Opcode::LdDEXX | Opcode::LdHLXX | Opcode::LdSPXX => {
+            ("LD", vec![register_pair(byte), Slot::Word])
+        }
+        Opcode::IncBC | Opcode::IncDE | Opcode::IncHL | Opcode::IncSP => {
+            ("INC", vec![register_pair(byte)])
+        }
+        Opcode::DecBC | Opcode::DecDE | Opcode::DecHL | Opcode::DecSP => {
+            ("DEC", vec![register_pair(byte)])
+        }
+        Opcode::AddHLBC | Opcode::AddHLDE | Opcode::AddHLHL | Opcode::AddHLSP => {
+            ("ADD", vec![register("HL"), register_pair(byte)])
+        }
+
+        Opcode::LdVBCA => ("LD", vec![indirect("BC"), register("A")]),
+        Opcode::LdVDEA => ("LD", vec![indirect("DE"), register("A")]),
+        Opcode::LdAVBC => ("LD", vec![register("A"), indirect("BC")]),
+        Opcode::LdAVDE => ("LD", vec![register("A"), indirect("DE")]),
+        Opcode::LdVXXHL => ("LD", vec![Slot::Address, register("HL")]),
+        Opcode::LdHLVXX => ("LD", vec![register("HL"), Slot::Address]),
+        Opcode::LdVXXA => ("LD", vec![Slot::Address, register("A")]),
+        Opcode::LdAVXX => ("LD", vec![register("A"), Slot::Address]),
+        Opcode::LdSPHL => ("LD", vec![register("SP"), register("HL")]),
+
+        Opcode::RLCA => ("RLCA", vec![]),
+        Opcode::RRCA => ("RRCA", vec![]),
+        Opcode::RLA => ("RLA", vec![]),
+        Opcode::RRA => ("RRA", vec![]),
+        Opcode::CPL => ("CPL", vec![]),
+        Opcode::DAA => ("DAA", vec![]),
+        Opcode::SCF => ("SCF", vec![]),
+        Opcode::CCF => ("CCF", vec![]),
+
+        Opcode::ExAFAF => ("EX", vec![register("AF"), register("AF'")]),
+        Opcode::Exx => ("EXX", vec![]),
+        Opcode::ExDEHL => ("EX", vec![register("DE"), register("HL")]),
+        Opcode::ExVSPHL => ("EX", vec![indirect("SP"), register("HL")]),
+
+        Opcode::PopBC | Opcode::PopDE | Opcode::PopHL | Opcode::PopAF => (
+            "POP",
+            vec![register(STACK_PAIRS[(byte >> 4) as usize & 0x03])],
+        ),
+        Opcode::PushBC | Opcode::PushDE | Opcode::PushHL | Opcode::PushAF => (
+            "PUSH",
+            vec![register(STACK_PAIRS[(byte >> 4) as usize & 0x03])],
+        ),
+
+        Opcode::JpXX => ("JP", vec![Slot::Word]),
+        Opcode::JpHL => ("JP", vec![indirect("HL")]),
+        Opcode::JrX => ("JR", vec![Slot::Relative]),
+        // Only NZ, Z, NC and C, numbered from 0x20.
+        Opcode::JrNZX | Opcode::JrZX | Opcode::JrNCX | Opcode::JrCX => {
+            ("JR", vec![condition(byte & 0x18), Slot::Relative])
+        }
+        Opcode::DjnzX => ("DJNZ", vec![Slot::Relative]),
+        Opcode::JpNZXX
+        | Opcode::JpZXX
+        | Opcode::JpNCXX
+        | Opcode::JpCXX
+        | Opcode::JpPOXX
+        | Opcode::JpPEXX
+        | Opcode::JpPXX
+        | Opcode::JpMXX => ("JP", vec![condition(byte), Slot::Word]),
+        Opcode::CallXX => ("CALL", vec![Slot::Word]),
+        Opcode::CallNZXX
+        | Opcode::CallZXX
+        | Opcode::CallNCXX
+        | Opcode::CallCXX
+        | Opcode::CallPOXX
+        | Opcode::CallPEXX
+        | Opcode::CallPXX
+        | Opcode::CallMXX => ("CALL", vec![condition(byte), Slot::Word]),
+        Opcode::Ret => ("RET", vec![]),
+        Opcode::RetNZ
+        | Opcode::RetZ
+        | Opcode::RetNC
+        | Opcode::RetC
+        | Opcode::RetPO
+        | Opcode::RetPE
+        | Opcode::RetP
+        | Opcode::RetM => ("RET", vec![condition(byte)]),
+        Opcode::Rst00
+        | Opcode::Rst08
+        | Opcode::Rst10
+        | Opcode::Rst18
+        | Opcode::Rst20
+        | Opcode::Rst28
+        | Opcode::Rst30
+        | Opcode::Rst38 => ("RST", vec![Slot::Fixed(Argument::Byte(byte & 0x38))]),
+
+        Opcode::OutVXA => ("OUT", vec![Slot::Port, register("A")]),
+        Opcode::InAVX => ("IN", vec![register("A"), Slot::Port]),
+        Opcode::Di => ("DI", vec![]),
+        Opcode::Ei => ("EI", vec![]),
+
+        // Handled by their pages before a template is needed.
+        Opcode::PrefixCB | Opcode::PrefixDD | Opcode::PrefixED | Opcode::PrefixFD => {
+            ("DB", vec![Slot::Fixed(Argument::Byte(byte))])
+        }
+    }
+}
+
+fn extended_template(opcode: ExtendedOpcode, byte: u8) -> (&'static str, Vec<Slot>) {
+    let pair = register_pair(byte);
+    match opcode {
+        ExtendedOpcode::InBVC
+        | ExtendedOpcode::InCVC
+        | ExtendedOpcode::InDVC
+        | ExtendedOpcode::InEVC
+        | ExtendedOpcode::InHVC
+        | ExtendedOpcode::InLVC
+        | ExtendedOpcode::InAVC => (
+            "IN",
+            vec![operand(Operand::from_bits(byte >> 3)), indirect("C")],
+        ),
+        ExtendedOpcode::InVC => ("IN", vec![indirect("C")]),
+        ExtendedOpcode::OutVCB
+        | ExtendedOpcode::OutVCC
+        | ExtendedOpcode::OutVCD
+        | ExtendedOpcode::OutVCE
+        | ExtendedOpcode::OutVCH
+        | ExtendedOpcode::OutVCL
+        | ExtendedOpcode::OutVCA => (
+            "OUT",
+            vec![indirect("C"), operand(Operand::from_bits(byte >> 3))],
+        ),
+        ExtendedOpcode::OutVC0 => ("OUT", vec![indirect("C"), Slot::Fixed(Argument::Number(0))]),
+
+        ExtendedOpcode::SbcHLBC
+        | ExtendedOpcode::SbcHLDE
+        | ExtendedOpcode::SbcHLHL
+        | ExtendedOpcode::SbcHLSP => ("SBC", vec![register("HL"), pair]),
+        ExtendedOpcode::AdcHLBC
+        | ExtendedOpcode::AdcHLDE
+        | ExtendedOpcode::AdcHLHL
+        | ExtendedOpcode::AdcHLSP => ("ADC", vec![register("HL"), pair]),
+        ExtendedOpcode::LdVXXBC
+        | ExtendedOpcode::LdVXXDE
+        | ExtendedOpcode::LdVXXHL
+        | ExtendedOpcode::LdVXXSP => ("LD", vec![Slot::Address, pair]),
+        ExtendedOpcode::LdBCVXX
+        | ExtendedOpcode::LdDEVXX
+        | ExtendedOpcode::LdHLVXX
+        | ExtendedOpcode::LdSPVXX => ("LD", vec![pair, Slot::Address]),
+
+        ExtendedOpcode::LdIA => ("LD", vec![register("I"), register("A")]),
+        ExtendedOpcode::LdRA => ("LD", vec![register("R"), register("A")]),
+        ExtendedOpcode::LdAI => ("LD", vec![register("A"), register("I")]),
+        ExtendedOpcode::LdAR => ("LD", vec![register("A"), register("R")]),
+
+        ExtendedOpcode::Neg => ("NEG", vec![]),
+        ExtendedOpcode::Retn => ("RETN", vec![]),
+        ExtendedOpcode::Reti => ("RETI", vec![]),
+        ExtendedOpcode::Im0 => ("IM", vec![Slot::Fixed(Argument::Number(0))]),
+        ExtendedOpcode::Im1 => ("IM", vec![Slot::Fixed(Argument::Number(1))]),
+        ExtendedOpcode::Im2 => ("IM", vec![Slot::Fixed(Argument::Number(2))]),
+        ExtendedOpcode::Rrd => ("RRD", vec![]),
+        ExtendedOpcode::Rld => ("RLD", vec![]),
+
+        ExtendedOpcode::Ldi => ("LDI", vec![]),
+        ExtendedOpcode::Cpi => ("CPI", vec![]),
+        ExtendedOpcode::Ini => ("INI", vec![]),
+        ExtendedOpcode::Outi => ("OUTI", vec![]),
+        ExtendedOpcode::Ldd => ("LDD", vec![]),
+        ExtendedOpcode::Cpd => ("CPD", vec![]),
+        ExtendedOpcode::Ind => ("IND", vec![]),
+        ExtendedOpcode::Outd => ("OUTD", vec![]),
+        ExtendedOpcode::Ldir => ("LDIR", vec![]),
+        ExtendedOpcode::Cpir => ("CPIR", vec![]),
+        ExtendedOpcode::Inir => ("INIR", vec![]),
+        ExtendedOpcode::Otir => ("OTIR", vec![]),
+        ExtendedOpcode::Lddr => ("LDDR", vec![]),
+        ExtendedOpcode::Cpdr => ("CPDR", vec![]),
+        ExtendedOpcode::Indr => ("INDR", vec![]),
+        ExtendedOpcode::Otdr => ("OTDR", vec![]),
+    }
+}
+
+fn bit_template(opcode: BitOpcode) -> (&'static str, Option<u8>, Operand) {
+    match opcode {
+        BitOpcode::Rlc(operand) => ("RLC", None, operand),
+        BitOpcode::Rrc(operand) => ("RRC", None, operand),
+        BitOpcode::Rl(operand) => ("RL", None, operand),
+        BitOpcode::Rr(operand) => ("RR", None, operand),
+        BitOpcode::Sla(operand) => ("SLA", None, operand),
+        BitOpcode::Sra(operand) => ("SRA", None, operand),
+        BitOpcode::Sll(operand) => ("SLL", None, operand),
+        BitOpcode::Srl(operand) => ("SRL", None, operand),
+        BitOpcode::Bit(bit, operand) => ("BIT", Some(bit), operand),
+        BitOpcode::Res(bit, operand) => ("RES", Some(bit), operand),
+        BitOpcode::Set(bit, operand) => ("SET", Some(bit), operand),
+    }
+}
+
+fn bit_slots(bit: Option<u8>, target: Slot) -> Vec<Slot> {
+    let mut slots: Vec<Slot> = bit
+        .map(|bit| Slot::Fixed(Argument::Number(bit)))
+        .into_iter()
+        .collect();
+    slots.push(target);
+    slots
+}
+
+// Apart from BIT, a register operand names where the undocumented forms
+// copy the result.
+fn index_bit_template(opcode: BitOpcode, index: &Index) -> (&'static str, Vec<Slot>) {
+    let (mnemonic, bit, copy) = bit_template(opcode);
+    let mut slots = bit_slots(bit, Slot::Indexed(index.pair));
+    if copy != Operand::IndirectHL && !matches!(opcode, BitOpcode::Bit(_, _)) {
+        slots.push(operand(copy));
+    }
+    (mnemonic, slots)
+}
+
+fn decode_bit_page(reader: &mut Reader) -> Option<Instruction> {
+    let (mnemonic, bit, target) = bit_template(BitOpcode::from(reader.next()?));
+    reader.finish(mnemonic, &bit_slots(bit, operand(target)))
+}
+
+// DD CB d op: the displacement comes before the operation.
+fn decode_index_bit_page(reader: &mut Reader, index: &Index) -> Option<Instruction> {
+    let displacement = reader.next()? as i8;
+    let (mnemonic, slots) = index_bit_template(BitOpcode::from(reader.next()?), index);
+    let operands = slots
+        .into_iter()
+        .filter_map(|slot| match slot {
+            Slot::Fixed(operand) => Some(operand),
+            Slot::Indexed(pair) => Some(Argument::Indexed(pair, displacement)),
+            _ => None,
+        })
+        .collect();
+    Some(reader.build(mnemonic, operands))
+}
+
+fn decode_extended_page(reader: &mut Reader) -> Option<Instruction> {
+    let byte = reader.next()?;
+    match ExtendedOpcode::try_from(byte) {
+        Ok(opcode) => {
+            let (mnemonic, slots) = extended_template(opcode, byte);
+            reader.finish(mnemonic, &slots)
+        }
+        // The CPU runs these as two NOPs, so both bytes are data.
+        Err(byte) => Some(reader.build("DB", vec![Argument::Byte(0xED), Argument::Byte(byte)])),
+    }
+}
+
+// A prefix that does not affect the next instruction is listed on its own,
+// since the CPU treats it as a four T-state no-op.
+fn decode_index_page(reader: &mut Reader, address: u16, index: &Index) -> Option<Instruction> {
+    let prefix = reader.bytes[0];
+    let byte = reader.next()?;
+    let opcode = match Opcode::try_from(byte) {
+        Ok(opcode) => opcode,
+        Err(_) => return Some(data(address, prefix)),
+    };
+    match opcode {
+        Opcode::PrefixCB => decode_index_bit_page(reader, index),
+        Opcode::JpHL => Some(reader.build("JP", vec![Argument::Indirect(index.pair)])),
+        Opcode::ExDEHL | Opcode::PrefixDD | Opcode::PrefixED | Opcode::PrefixFD => {
+            Some(data(address, prefix))
+        }
+        _ => {
+            let (mnemonic, slots) = base_template(opcode, byte);
+            if uses_hl(&slots) {
+                reader.finish(mnemonic, &index_slots(&slots, index))
+            } else {
+                Some(data(address, prefix))
+            }
+        }
+    }
+}
+
+// Decodes the instruction at `address`, with `fetch` returning None past
+// the end of the input. An instruction cut short by the end comes out as a
+// DB of its first byte.
+pub fn decode(fetch: &dyn Fn(u16) -> Option<u8>, address: u16) -> Option<Instruction> {
+    let first = fetch(address)?;
+    let mut reader = Reader {
+        fetch,
+        address,
+        bytes: Vec::with_capacity(4),
+    };
+    let decoded = reader.next().and_then(|byte| match Opcode::try_from(byte) {
+        Ok(Opcode::PrefixCB) => decode_bit_page(&mut reader),
+        Ok(Opcode::PrefixED) => decode_extended_page(&mut reader),
+        Ok(Opcode::PrefixDD) => decode_index_page(&mut reader, address, &IX),
+        Ok(Opcode::PrefixFD) => decode_index_page(&mut reader, address, &IY),
+        Ok(opcode) => {
+            let (mnemonic, slots) = base_template(opcode, byte);
+            reader.finish(mnemonic, &slots)
+        }
+        Err(_) => None,
+    });
+    Some(decoded.unwrap_or_else(|| data(address, first)))
+}
+
+// Every instruction the decoder knows, for the assembler. Earlier entries
+// win, so shorter encodings and the first of several duplicate opcodes come
+// first.
+pub(crate) fn encodings() -> Vec<Encoding> {
+    let mut encodings = Vec::new();
+    let mut add = |mnemonic, slots, prefix, suffix| {
+        encodings.push(Encoding {
+            mnemonic,
+            slots,
+            prefix,
+            suffix,
+        })
+    };
+    for byte in 0..=0xFF {
+        match Opcode::try_from(byte) {
+            Ok(Opcode::PrefixCB) | Ok(Opcode::PrefixDD) | Ok(Opcode::PrefixED)
+            | Ok(Opcode::PrefixFD) | Err(_) => {}
+            Ok(opcode) => {
+                let (mnemonic, slots) = base_template(opcode, byte);
+                add(mnemonic, slots, vec![byte], None);
+            }
+        }
+    }
+    for byte in 0..=0xFF {
+        if let Ok(opcode) = ExtendedOpcode::try_from(byte) {
+            let (mnemonic, slots) = extended_template(opcode, byte);
+            add(mnemonic, slots, vec![0xED, byte], None);
+        }
+    }
+    for byte in 0..=0xFF {
+        let (mnemonic, bit, target) = bit_template(BitOpcode::from(byte));
+        add(
+            mnemonic,
+            bit_slots(bit, operand(target)),
+            vec![0xCB, byte],
+            None,
+        );
+    }
+    for index in &[IX, IY] {
+        for byte in 0..=0xFF {
+            match Opcode::try_from(byte) {
+                Ok(Opcode::JpHL) => add(
+                    "JP",
+                    vec![indirect(index.pair)],
+                    vec![index.prefix, byte],
+                    None,
+                ),
+                Ok(Opcode::ExDEHL) | Ok(Opcode::PrefixCB) | Ok(Opcode::PrefixDD)
+                | Ok(Opcode::PrefixED) | Ok(Opcode::PrefixFD) | Err(_) => {}
+                Ok(opcode) => {
+                    let (mnemonic, slots) = base_template(opcode, byte);
+                    if uses_hl(&slots) {
+                        add(
+                            mnemonic,
+                            index_slots(&slots, index),
+                            vec![index.prefix, byte],
+                            None,
+                        );
+                    }
+                }
+            }
+        }
+        for byte in 0..=0xFF {
+            let (mnemonic, slots) = index_bit_template(BitOpcode::from(byte), index);
+            add(mnemonic, slots, vec![index.prefix, 0xCB], Some(byte));
+        }
+    }
+    encodings
+}
+
+// Treats `bytes` as loaded at `origin`, e.g. Program::raw() at 0x0000.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Instruction> {
+    let fetch = |address: u16| bytes.get(address.wrapping_sub(origin) as usize).cloned();
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        match decode(&fetch, address) {
+            Some(instruction) => {
+                offset += instruction.length;
+                instructions.push(instruction);
+            }
+            None => break,
+        }
+    }
+    instructions
+}
+
+// Reads `count` instructions from a bus, such as Memory or the machine's
+// memory map, wrapping at the top of the address space.
+pub fn disassemble_bus(bus: &dyn Bus, start: u16, count: usize) -> Vec<Instruction> {
+    let fetch = |address: u16| Some(bus.read_u8(address));
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = start;
+    for _ in 0..count {
+        if let Some(instruction) = decode(&fetch, address) {
+            address = address.wrapping_add(instruction.length as u16);
+            instructions.push(instruction);
+        }
+    }
+    instructions
+}
+
+// One line per instruction: address, bytes and the instruction itself.
+pub fn listing(instructions: &[Instruction]) -> String {
+    let mut text = String::new();
+    for instruction in instructions {
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        text.push_str(&format!(
+            "{:04X}  {:<12} {}\n",
+            instruction.address,
+            bytes.join(" "),
+            instruction
+        ));
+    }
+    text
+}
@@ -2,8 +2,32 @@
 
 extern crate num;
 
+mod assembler;
+mod cli;
+mod disassembler;
 mod program;
 mod tests;
 mod vm;
 
-fn main() {}
+use cli::Options;
+use std::env;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = cli::run(&options) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
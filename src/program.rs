@@ -1,12 +1,136 @@
+use assembler::{self, AssemblyError};
+use disassembler::{self, Instruction};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use vm::instructions::opcodes::Opcode;
 
+// Gives every Program its own id, so that labels can name their owner.
+static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+// A place in the program that instructions can refer to before it is bound.
+// Only the Program that made it accepts it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    program: usize,
+    index: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgramError {
+    // A label that some other Program created.
+    UnknownLabel(Label),
+    AlreadyBound(Label),
+    UnboundLabel(Label),
+    // add_param_label takes JP and CALL, add_relative JR and DJNZ.
+    NotAJump(Opcode),
+    // A JR or DJNZ whose label is further than a signed byte can reach.
+    OutOfRange { label: Label, offset: i32 },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProgramError::UnknownLabel(label) => {
+                write!(f, "label {} does not belong to this program", label.index)
+            }
+            ProgramError::AlreadyBound(label) => {
+                write!(f, "label {} is already bound", label.index)
+            }
+            ProgramError::UnboundLabel(label) => {
+                write!(f, "label {} was never bound", label.index)
+            }
+            ProgramError::NotAJump(opcode) => {
+                write!(f, "{:?} cannot take a label", opcode)
+            }
+            ProgramError::OutOfRange { label, offset } => write!(
+                f,
+                "label {} is {} bytes away, out of relative jump range",
+                label.index, offset
+            ),
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FixupKind {
+    Absolute,
+    Relative,
+}
+
+// Bytes in `bin` that wait for a label's address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Fixup {
+    label: Label,
+    position: usize,
+    kind: FixupKind,
+}
+
 pub struct Program {
+    id: usize,
     bin: Vec<u8>,
+    origin: u16,
+    labels: Vec<Option<u16>>,
+    fixups: Vec<Fixup>,
 }
 
 impl Program {
     pub fn new() -> Program {
-        Program { bin: Vec::new() }
+        Program::with_origin(0x0000)
+    }
+
+    // For a program that will be loaded at `origin`, so that labels get
+    // the right addresses.
+    pub fn with_origin(origin: u16) -> Program {
+        Program {
+            id: PROGRAMS.fetch_add(1, Ordering::Relaxed),
+            bin: Vec::new(),
+            origin,
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    // The program starts at the source's first ORG, or 0x0000 without one.
+    pub fn assemble(source: &str) -> Result<Program, AssemblyError> {
+        assembler::assemble(source).map(|assembly| assembly.program)
+    }
+
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    // Where the next byte will go.
+    pub fn address(&self) -> u16 {
+        self.origin.wrapping_add(self.bin.len() as u16)
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label {
+            program: self.id,
+            index: self.labels.len() - 1,
+        }
+    }
+
+    pub fn bind(&mut self, label: Label) -> Result<(), ProgramError> {
+        self.check_label(label)?;
+        let address = self.address();
+        match self.labels[label.index] {
+            Some(_) => Err(ProgramError::AlreadyBound(label)),
+            None => {
+                self.labels[label.index] = Some(address);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn label_here(&mut self) -> Result<Label, ProgramError> {
+        let label = self.label();
+        self.bind(label)?;
+        Ok(label)
     }
 
     pub fn raw(&self) -> &Vec<u8> {
@@ -39,4 +163,108 @@ impl Program {
     pub fn add_vector(&mut self, mut parameters: Vec<u8>) {
         self.bin.append(&mut parameters);
     }
+
+    // Like add_param_word for JP and CALL, with the label's address filled
+    // in by finish().
+    pub fn add_param_label(&mut self, opcode: Opcode, label: Label) -> Result<(), ProgramError> {
+        match opcode {
+            Opcode::JpXX
+            | Opcode::JpNZXX
+            | Opcode::JpZXX
+            | Opcode::JpNCXX
+            | Opcode::JpCXX
+            | Opcode::JpPOXX
+            | Opcode::JpPEXX
+            | Opcode::JpPXX
+            | Opcode::JpMXX
+            | Opcode::CallXX
+            | Opcode::CallNZXX
+            | Opcode::CallZXX
+            | Opcode::CallNCXX
+            | Opcode::CallCXX
+            | Opcode::CallPOXX
+            | Opcode::CallPEXX
+            | Opcode::CallPXX
+            | Opcode::CallMXX => {}
+            _ => return Err(ProgramError::NotAJump(opcode)),
+        }
+        self.check_label(label)?;
+        self.add_param_word(opcode, 0x0000);
+        self.fix(label, self.bin.len() - 2, FixupKind::Absolute);
+        Ok(())
+    }
+
+    // For JR and DJNZ: the displacement from the end of the instruction.
+    pub fn add_relative(&mut self, opcode: Opcode, label: Label) -> Result<(), ProgramError> {
+        match opcode {
+            Opcode::JrX
+            | Opcode::JrNZX
+            | Opcode::JrZX
+            | Opcode::JrNCX
+            | Opcode::JrCX
+            | Opcode::DjnzX => {}
+            _ => return Err(ProgramError::NotAJump(opcode)),
+        }
+        self.check_label(label)?;
+        self.add_param(opcode, 0x00);
+        self.fix(label, self.bin.len() - 1, FixupKind::Relative);
+        Ok(())
+    }
+
+    fn check_label(&self, label: Label) -> Result<(), ProgramError> {
+        if label.program == self.id {
+            Ok(())
+        } else {
+            Err(ProgramError::UnknownLabel(label))
+        }
+    }
+
+    fn fix(&mut self, label: Label, position: usize, kind: FixupKind) {
+        self.fixups.push(Fixup {
+            label,
+            position,
+            kind,
+        });
+    }
+
+    // Writes every label reference. Until then they read as zero, and they
+    // stay that way if any of them fails.
+    pub fn finish(&mut self) -> Result<(), ProgramError> {
+        let mut patches = Vec::with_capacity(self.fixups.len());
+        for fixup in &self.fixups {
+            let address = match self.labels[fixup.label.index] {
+                Some(address) => address,
+                None => return Err(ProgramError::UnboundLabel(fixup.label)),
+            };
+            match fixup.kind {
+                FixupKind::Absolute => {
+                    patches.push((fixup.position, address as u8));
+                    patches.push((fixup.position + 1, (address >> 8) as u8));
+                }
+                FixupKind::Relative => {
+                    // Addresses wrap round at 0xFFFF like the program counter.
+                    let end = self
+                        .origin
+                        .wrapping_add(fixup.position as u16)
+                        .wrapping_add(1);
+                    let offset = address.wrapping_sub(end) as i16;
+                    if !(-0x80..=0x7F).contains(&offset) {
+                        return Err(ProgramError::OutOfRange {
+                            label: fixup.label,
+                            offset: offset as i32,
+                        });
+                    }
+                    patches.push((fixup.position, offset as u8));
+                }
+            }
+        }
+        for (position, byte) in patches {
+            self.bin[position] = byte;
+        }
+        Ok(())
+    }
+
+    pub fn disassemble(&self) -> Vec<Instruction> {
+        disassembler::disassemble(&self.bin, self.origin)
+    }
 }
@@ -1,11 +1,43 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use program::Program;
+    use assembler::{self, AssemblyError};
+    use cli::image::{self, Crc32};
+    use cli::input::{Action, InputScript};
+    use cli::wav;
+    use cli::{CliError, Options};
+    use disassembler::{self, Argument};
+    use program::{Program, ProgramError};
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+    use std::rc::Rc;
+    use vm::audio::opll::Opll;
+    use vm::audio::psg::{Psg, NTSC_CLOCK};
+    use vm::audio::resampler::Resampler;
+    use vm::cartridge::header::{RegionCode, RomHeader, RomWarning};
+    use vm::cartridge::mapper::MapperKind;
+    use vm::cartridge::{Cartridge, CartridgeError, MAX_ROM_SIZE};
     use vm::cpu::alu;
     use vm::cpu::flags::Flag;
     use vm::cpu::registers::Registers;
-    use vm::instructions::opcodes::Opcode;
-    use vm::machine::Machine;
+    use vm::cpu::state::InterruptMode;
+    use vm::instructions::error::{ExecError, ExecErrorReason};
+    use vm::instructions::opcodes::{BitOpcode, ExtendedOpcode, Opcode, Operand};
+    use vm::io::joypad::{Buttons, Player};
+    use vm::io::port::{Access, PortDevice, PortRange};
+    use vm::machine::{Machine, MachineConfig, Region};
+    use vm::ram::bus::Bus;
+    use vm::ram::memory::Memory;
+    use vm::ram::rom::Rom;
+    use vm::ram::system::SystemBus;
+    use vm::rewind::{self, Period, RewindBuffer, RewindConfig};
+    use vm::state::{self, Snapshot, StateError, StateReader, StateWriter};
+    use vm::video::palette;
+    use vm::video::timing::{self, TvSystem};
+    use vm::video::vdp::{
+        AccessCode, DisplayMode, Vdp, SCREEN_WIDTH, STATUS_FRAME_INTERRUPT,
+        STATUS_SPRITE_COLLISION, STATUS_SPRITE_OVERFLOW,
+    };
 
     fn new_vm(regs: fn(&mut Registers), stream: Vec<Opcode>, start: u16) -> Machine {
         let mut vm = Machine::new();
@@ -19,7 +51,7 @@ mod tests {
 
     fn run_program(regs: fn(&mut Registers), stream: Vec<Opcode>) -> Machine {
         let mut vm = new_vm(regs, stream, 0);
-        vm.start();
+        vm.start().unwrap();
         vm
     }
 
@@ -67,7 +99,7 @@ mod tests {
             if i > 0 {
                 assert_eq!(i & 0x0F == 0, h, "At value {}.", i);
             }
-            vm.execute();
+            vm.execute().unwrap();
         }
     }
 
@@ -79,7 +111,7 @@ mod tests {
             let i = iteration as u16;
             let bc = vm.cpu.get_register_pair(|regs| (regs.b, regs.c));
             assert_eq!(i, bc);
-            vm.execute();
+            vm.execute().unwrap();
         }
     }
 
@@ -98,20 +130,20 @@ mod tests {
         assert!(!Flag::Sign.get(&vm.cpu.state.status));
         assert!(!Flag::Carry.get(&vm.cpu.state.status));
 
-        vm.start_at(0);
+        vm.start_at(0).unwrap();
         assert_eq!(vm.cpu.get_register(|regs| regs.a), 0x80);
         assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
         assert!(Flag::Sign.get(&vm.cpu.state.status));
         assert!(!Flag::Carry.get(&vm.cpu.state.status));
 
-        vm.start_at(0);
+        vm.start_at(0).unwrap();
         assert_eq!(vm.cpu.get_register(|regs| regs.a), 0x81);
         assert!(!Flag::ParityOverflow.get(&vm.cpu.state.status));
         assert!(Flag::Sign.get(&vm.cpu.state.status));
         assert!(!Flag::Carry.get(&vm.cpu.state.status));
 
         vm.cpu.state.registers.a = 0xFF;
-        vm.start_at(0);
+        vm.start_at(0).unwrap();
         assert_eq!(vm.cpu.get_register(|regs| regs.a), 0x00);
         assert!(!Flag::ParityOverflow.get(&vm.cpu.state.status));
         assert!(!Flag::Sign.get(&vm.cpu.state.status));
@@ -130,7 +162,7 @@ mod tests {
         assert_eq!(vm.cpu.get_register(|regs| regs.b), 0x00);
         assert_eq!(vm.cpu.get_register(|regs| regs.c), 0xFF);
 
-        vm.start_at(0);
+        vm.start_at(0).unwrap();
         assert_eq!(vm.cpu.get_register(|regs| regs.b), 0x01);
         assert_eq!(vm.cpu.get_register(|regs| regs.c), 0x00);
     }
@@ -147,7 +179,7 @@ mod tests {
         assert_eq!(vm.cpu.get_register(|regs| regs.b), 0x00);
         assert_eq!(vm.cpu.get_register(|regs| regs.c), 0xFF);
 
-        vm.start_at(0);
+        vm.start_at(0).unwrap();
         assert_eq!(vm.cpu.get_register(|regs| regs.b), 0x00);
         assert_eq!(vm.cpu.get_register(|regs| regs.c), 0xFE);
     }
@@ -160,7 +192,7 @@ mod tests {
         p.add(Opcode::Halt);
         vm.load(&p);
         flag.set(&mut vm.cpu.state.status, flag_value);
-        vm.start();
+        vm.start().unwrap();
         assert_eq!(vm.cpu.state.program_counter, expected);
     }
 
@@ -195,7 +227,7 @@ mod tests {
         vm.cpu.state.registers.b = 0;
         vm.cpu.state.registers.c = 20;
 
-        vm.start();
+        vm.start().unwrap();
 
         assert_eq!(vm.cpu.state.registers.b, 20);
     }
@@ -209,8 +241,3075 @@ mod tests {
 
         vm.cpu.state.registers.b = 0;
 
-        vm.start();
+        vm.start().unwrap();
 
         assert_eq!(vm.cpu.state.registers.b, 42);
     }
+
+    #[test]
+    fn decode() {
+        for iteration in 0..256 {
+            let i = iteration as u8;
+            assert_eq!(Opcode::try_from(i).map(|opcode| opcode as u8), Ok(i));
+        }
+    }
+
+    #[test]
+    fn unimplemented_opcode() {
+        // Every base opcode decodes now, so this only checks the message.
+        let error = ExecError::new(
+            0x0001,
+            vec![0xDD, 0x27],
+            ExecErrorReason::UnimplementedOpcode,
+        );
+        assert_eq!(error.to_string(), "unimplemented opcode at 0x0001: DD 27");
+    }
+
+    #[test]
+    fn relative_jumps() {
+        let mut p = Program::new();
+        p.add(Opcode::XorA);
+        p.add_param(Opcode::LdBX, 0x02);
+        p.add_param(Opcode::DjnzX, 0xFE);
+        p.add_param(Opcode::JrNZX, 0x03);
+        p.add_param(Opcode::JrZX, 0x01);
+        p.add(Opcode::IncA);
+        p.add(Opcode::Halt);
+        assert_eq!(cycles_of(&p), vec![4, 7, 13, 8, 7, 12, 4]);
+
+        let mut vm = Machine::new();
+        vm.load(&p);
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.registers.a, 0x00);
+        assert_eq!(vm.cpu.state.registers.b, 0x00);
+        assert_eq!(vm.cpu.state.program_counter, 0x000B);
+        assert_eq!(
+            p.disassemble()[2..5]
+                .iter()
+                .map(|instruction| instruction.to_string())
+                .collect::<Vec<_>>(),
+            vec!["DJNZ 0x0003", "JR NZ,0x000A", "JR Z,0x000A"]
+        );
+
+        // Offsets are signed and wrap around the address space.
+        let mut p = Program::new();
+        p.add_param(Opcode::JrX, 0x80);
+        p.add(Opcode::Halt);
+        let mut vm = Machine::new();
+        vm.load(&p);
+        vm.bus.write_u8(0xFF82, Opcode::Halt as u8);
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0xFF83);
+    }
+
+    #[test]
+    fn alu_regressions() {
+        // ADC and SBC used to add the carry to the operand, which overflowed
+        // for 0xFF.
+        let vm = run_program(
+            |regs| regs.b = 0xFF,
+            vec![Opcode::SCF, Opcode::AdcB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x00);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(!Flag::AddSubtract.get(&vm.cpu.state.status));
+
+        let vm = run_program(
+            |regs| regs.b = 0xFF,
+            vec![Opcode::SCF, Opcode::SbcB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x00);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(Flag::AddSubtract.get(&vm.cpu.state.status));
+
+        // Subtraction added the negated operand, so carry and half carry
+        // came out inverted.
+        let vm = run_program(
+            |regs| {
+                regs.a = 0x05;
+                regs.b = 0x03;
+            },
+            vec![Opcode::SubB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x02);
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+        assert!(!Flag::HalfCarry.get(&vm.cpu.state.status));
+
+        let vm = run_program(
+            |regs| {
+                regs.a = 0x10;
+                regs.b = 0x01;
+            },
+            vec![Opcode::SubB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x0F);
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+
+        let vm = run_program(|regs| regs.b = 0x10, vec![Opcode::DecB, Opcode::Halt]);
+        assert_eq!(vm.cpu.state.registers.b, 0x0F);
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+        assert!(Flag::AddSubtract.get(&vm.cpu.state.status));
+
+        // AND, OR and XOR set the flags but never stored the result in A.
+        let vm = run_program(
+            |regs| {
+                regs.a = 0xF0;
+                regs.b = 0x0C;
+            },
+            vec![Opcode::OrB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0xFC);
+        let vm = run_program(
+            |regs| {
+                regs.a = 0xF0;
+                regs.b = 0x3C;
+            },
+            vec![Opcode::XorB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0xCC);
+
+        // CPL wrote A back unchanged.
+        let vm = run_program(
+            |regs| regs.a = 0x5A,
+            vec![Opcode::SCF, Opcode::CPL, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0xA5);
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+        assert!(Flag::AddSubtract.get(&vm.cpu.state.status));
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+    }
+
+    fn run_bit_page(regs: fn(&mut Registers), opcode: BitOpcode) -> Machine {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param(Opcode::PrefixCB, opcode.into());
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        regs(&mut vm.cpu.state.registers);
+        vm.start().unwrap();
+        vm
+    }
+
+    #[test]
+    fn bit_page_decode() {
+        for iteration in 0..256 {
+            let i = iteration as u8;
+            assert_eq!(u8::from(BitOpcode::from(i)), i);
+        }
+        assert_eq!(
+            BitOpcode::from(0x7E),
+            BitOpcode::Bit(7, Operand::IndirectHL)
+        );
+        assert_eq!(BitOpcode::from(0x38), BitOpcode::Srl(Operand::B));
+    }
+
+    #[test]
+    fn rotate_accumulator() {
+        let vm = run_program(|regs| regs.a = 0x81, vec![Opcode::RLCA, Opcode::Halt]);
+        assert_eq!(vm.cpu.state.registers.a, 0x03);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+
+        let vm = run_program(|regs| regs.a = 0x01, vec![Opcode::RRA, Opcode::Halt]);
+        assert_eq!(vm.cpu.state.registers.a, 0x00);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(!Flag::Zero.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn rotate_and_shift() {
+        let vm = run_bit_page(|regs| regs.b = 0x81, BitOpcode::Rlc(Operand::B));
+        assert_eq!(vm.cpu.state.registers.b, 0x03);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+
+        let vm = run_bit_page(|regs| regs.c = 0x80, BitOpcode::Sra(Operand::C));
+        assert_eq!(vm.cpu.state.registers.c, 0xC0);
+        assert!(Flag::Sign.get(&vm.cpu.state.status));
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+
+        let vm = run_bit_page(|regs| regs.d = 0x01, BitOpcode::Srl(Operand::D));
+        assert_eq!(vm.cpu.state.registers.d, 0x00);
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param(Opcode::PrefixCB, BitOpcode::Sla(Operand::IndirectHL).into());
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x4000, 0x41);
+        vm.cpu.state.registers.h = 0x40;
+        vm.start().unwrap();
+        assert_eq!(vm.bus.read_u8(0x4000), 0x82);
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn bit_manipulation() {
+        let vm = run_bit_page(|regs| regs.a = 0x80, BitOpcode::Bit(7, Operand::A));
+        assert!(!Flag::Zero.get(&vm.cpu.state.status));
+        assert!(Flag::Sign.get(&vm.cpu.state.status));
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+
+        let vm = run_bit_page(|regs| regs.e = 0xFE, BitOpcode::Bit(0, Operand::E));
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+
+        let vm = run_bit_page(|regs| regs.h = 0x00, BitOpcode::Set(5, Operand::H));
+        assert_eq!(vm.cpu.state.registers.h, 0x20);
+
+        let vm = run_bit_page(|regs| regs.l = 0xFF, BitOpcode::Res(3, Operand::L));
+        assert_eq!(vm.cpu.state.registers.l, 0xF7);
+    }
+
+    fn run_extended_page(regs: fn(&mut Registers), opcode: ExtendedOpcode) -> Machine {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param(Opcode::PrefixED, opcode as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        regs(&mut vm.cpu.state.registers);
+        vm.start().unwrap();
+        vm
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x8000);
+        p.add_param_word(Opcode::CallXX, 0x0100);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        let mut routine = Program::new();
+        routine.add_param_word(Opcode::LdBCXX, 0x1234);
+        routine.add(Opcode::PushBC);
+        routine.add(Opcode::PopDE);
+        routine.add(Opcode::Ret);
+        vm.load_at(&routine, 0x0100);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.program_counter, 0x0007);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.d, regs.e)), 0x1234);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.s, regs.p)), 0x8000);
+    }
+
+    #[test]
+    fn undefined_extended_opcodes() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        for &byte in &[0x00, 0x3F, 0x77, 0x7F, 0xC0, 0xFF] {
+            p.add_param(Opcode::PrefixED, byte);
+        }
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        for _ in 0..6 {
+            assert_eq!(vm.step().unwrap(), 8);
+        }
+
+        assert_eq!(vm.cpu.state.program_counter, 0x000C);
+        vm.start_at(0x0000).unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x000D);
+    }
+
+    #[test]
+    fn block_load() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x1000);
+        p.add_param_word(Opcode::LdDEXX, 0x2000);
+        p.add_param_word(Opcode::LdBCXX, 0x0003);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Ldir as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x1000, 0x11);
+        vm.bus.write_u8(0x1001, 0x22);
+        vm.bus.write_u8(0x1002, 0x33);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.bus.read_u8(0x2000), 0x11);
+        assert_eq!(vm.bus.read_u8(0x2001), 0x22);
+        assert_eq!(vm.bus.read_u8(0x2002), 0x33);
+        assert_eq!(vm.bus.read_u8(0x2003), 0x00);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.h, regs.l)), 0x1003);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.d, regs.e)), 0x2003);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.b, regs.c)), 0x0000);
+        assert!(!Flag::ParityOverflow.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn block_compare() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x1000);
+        p.add_param_word(Opcode::LdBCXX, 0x0010);
+        p.add_param(Opcode::LdAX, 0x42);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Cpir as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x1004, 0x42);
+
+        vm.start().unwrap();
+
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.h, regs.l)), 0x1005);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.b, regs.c)), 0x000B);
+    }
+
+    #[test]
+    fn negate() {
+        let vm = run_extended_page(|regs| regs.a = 0x01, ExtendedOpcode::Neg);
+        assert_eq!(vm.cpu.state.registers.a, 0xFF);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::AddSubtract.get(&vm.cpu.state.status));
+
+        let vm = run_extended_page(|regs| regs.a = 0x80, ExtendedOpcode::Neg);
+        assert_eq!(vm.cpu.state.registers.a, 0x80);
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn arithmetic_with_carry_wide() {
+        let vm = run_extended_page(
+            |regs| {
+                regs.h = 0x7F;
+                regs.l = 0xFF;
+                regs.b = 0x00;
+                regs.c = 0x01;
+            },
+            ExtendedOpcode::AdcHLBC,
+        );
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.h, regs.l)), 0x8000);
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+
+        let vm = run_extended_page(
+            |regs| {
+                regs.h = 0x00;
+                regs.l = 0x00;
+                regs.d = 0x00;
+                regs.e = 0x01;
+            },
+            ExtendedOpcode::SbcHLDE,
+        );
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.h, regs.l)), 0xFFFF);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::Sign.get(&vm.cpu.state.status));
+        assert!(!Flag::Zero.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn rotate_digit() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Rld as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x1000, 0x34);
+        vm.cpu.state.registers.a = 0x12;
+        vm.cpu.state.registers.h = 0x10;
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.registers.a, 0x13);
+        assert_eq!(vm.bus.read_u8(0x1000), 0x42);
+    }
+
+    #[test]
+    fn interrupt_registers() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x80);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::LdIA as u8);
+        p.add(Opcode::XorA);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Im2 as u8);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::LdAI as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.cpu.state.iff2 = true;
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.interrupt_vector, 0x80);
+        assert_eq!(vm.cpu.state.registers.a, 0x80);
+        assert_eq!(vm.cpu.state.interrupt_mode, InterruptMode::Mode2);
+        assert!(Flag::Sign.get(&vm.cpu.state.status));
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn return_from_interrupt() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x8000);
+        p.add_param_word(Opcode::CallXX, 0x0100);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        let mut handler = Program::new();
+        handler.add_param(Opcode::PrefixED, ExtendedOpcode::Retn as u8);
+        vm.load_at(&handler, 0x0100);
+        vm.cpu.state.iff2 = true;
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.program_counter, 0x0007);
+        assert!(vm.cpu.state.iff1);
+    }
+
+    #[test]
+    fn subtract_and_compare() {
+        let vm = run_program(
+            |regs| {
+                regs.a = 0x10;
+                regs.b = 0x20;
+            },
+            vec![Opcode::SubB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0xF0);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(Flag::AddSubtract.get(&vm.cpu.state.status));
+
+        let vm = run_program(
+            |regs| {
+                regs.a = 0x42;
+                regs.c = 0x42;
+            },
+            vec![Opcode::CpC, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x42);
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn bitwise() {
+        let vm = run_program(
+            |regs| {
+                regs.a = 0xF0;
+                regs.b = 0x3C;
+            },
+            vec![Opcode::AndB, Opcode::Halt],
+        );
+        assert_eq!(vm.cpu.state.registers.a, 0x30);
+        assert!(Flag::HalfCarry.get(&vm.cpu.state.status));
+        assert!(Flag::ParityOverflow.get(&vm.cpu.state.status));
+
+        let vm = run_program(|regs| regs.a = 0x5A, vec![Opcode::XorA, Opcode::Halt]);
+        assert_eq!(vm.cpu.state.registers.a, 0x00);
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+
+        let vm = run_program(|regs| regs.a = 0x5A, vec![Opcode::CPL, Opcode::Halt]);
+        assert_eq!(vm.cpu.state.registers.a, 0xA5);
+    }
+
+    #[test]
+    fn memory_operands() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add(Opcode::IncVHL);
+        p.add_param(Opcode::LdAX, 0x01);
+        p.add(Opcode::AddVHL);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x4000, 0x0F);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.bus.read_u8(0x4000), 0x10);
+        assert_eq!(vm.cpu.state.registers.a, 0x11);
+    }
+
+    #[test]
+    fn index_registers() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add(Opcode::PrefixDD);
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add(Opcode::PrefixFD);
+        p.add_param_word(Opcode::LdHLXX, 0x5000);
+        p.add_param_word(Opcode::LdHLXX, 0x1234);
+        p.add(Opcode::PrefixDD);
+        p.add_params(Opcode::LdVHLX, 0x05, 0x99);
+        p.add(Opcode::PrefixFD);
+        p.add_param(Opcode::LdHLA, 0xFF);
+        p.add(Opcode::PrefixDD);
+        p.add_param(Opcode::LdBHL, 0x05);
+        p.add(Opcode::PrefixDD);
+        p.add(Opcode::IncH);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.cpu.state.registers.a = 0x77;
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.ix, 0x4100);
+        assert_eq!(vm.cpu.state.iy, 0x5000);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.h, regs.l)), 0x1234);
+        assert_eq!(vm.bus.read_u8(0x4005), 0x99);
+        assert_eq!(vm.bus.read_u8(0x4FFF), 0x77);
+        assert_eq!(vm.cpu.state.registers.b, 0x99);
+    }
+
+    #[test]
+    fn index_register_stack() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x8000);
+        p.add_param_word(Opcode::LdBCXX, 0xBEEF);
+        p.add(Opcode::PushBC);
+        p.add(Opcode::PrefixFD);
+        p.add_param_word(Opcode::LdHLXX, 0x1234);
+        p.add(Opcode::PrefixFD);
+        p.add(Opcode::ExVSPHL);
+        p.add(Opcode::PopDE);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.iy, 0xBEEF);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.d, regs.e)), 0x1234);
+    }
+
+    #[test]
+    fn index_bit_page() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add(Opcode::PrefixDD);
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add(Opcode::PrefixDD);
+        p.add_params(
+            Opcode::PrefixCB,
+            0x02,
+            BitOpcode::Set(7, Operand::IndirectHL).into(),
+        );
+        p.add(Opcode::PrefixDD);
+        p.add_params(Opcode::PrefixCB, 0x02, BitOpcode::Rlc(Operand::C).into());
+        p.add(Opcode::PrefixDD);
+        p.add_params(
+            Opcode::PrefixCB,
+            0x02,
+            BitOpcode::Bit(0, Operand::IndirectHL).into(),
+        );
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.bus.read_u8(0x4002), 0x01);
+        assert_eq!(vm.cpu.state.registers.c, 0x01);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert!(!Flag::Zero.get(&vm.cpu.state.status));
+    }
+
+    fn interrupt_test_machine(mode: ExtendedOpcode) -> Machine {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x8000);
+        p.add_param(Opcode::PrefixED, mode as u8);
+        p.add(Opcode::Ei);
+        p.add(Opcode::Nop);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm
+    }
+
+    #[test]
+    fn maskable_interrupt() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im1);
+        vm.raise_irq();
+        vm.execute().unwrap();
+        vm.execute().unwrap();
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0006);
+
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0007);
+        assert!(vm.cpu.state.iff1);
+
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0038);
+        assert!(!vm.cpu.state.iff1);
+        assert!(!vm.cpu.state.iff2);
+        assert_eq!(vm.bus.read_u16(0x7FFE), 0x0007);
+    }
+
+    #[test]
+    fn interrupt_wakes_from_halt() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im1);
+        vm.start().unwrap();
+        assert!(vm.cpu.is_halted());
+        assert_eq!(vm.cpu.state.program_counter, 0x0008);
+
+        vm.execute().unwrap();
+        assert!(vm.cpu.is_halted());
+        assert_eq!(vm.cpu.state.program_counter, 0x0008);
+
+        vm.raise_irq();
+        vm.execute().unwrap();
+        assert!(!vm.cpu.is_halted());
+        assert_eq!(vm.cpu.state.program_counter, 0x0038);
+        assert_eq!(vm.bus.read_u16(0x7FFE), 0x0008);
+    }
+
+    #[test]
+    fn disabled_interrupts() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im1);
+        vm.bus.write_u8(0x0005, Opcode::Di as u8);
+        vm.raise_irq();
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0008);
+    }
+
+    #[test]
+    fn vectored_interrupt() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im2);
+        vm.cpu.state.interrupt_vector = 0x12;
+        vm.bus.write_u16(0x12FF, 0x4321);
+        vm.start().unwrap();
+        vm.raise_irq();
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x4321);
+    }
+
+    #[test]
+    fn non_maskable_interrupt() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im1);
+        vm.start().unwrap();
+        vm.raise_nmi();
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0066);
+        assert!(!vm.cpu.state.iff1);
+        assert!(vm.cpu.state.iff2);
+
+        vm.bus.write_u8(0x0066, 0xED);
+        vm.bus.write_u8(0x0067, ExtendedOpcode::Retn as u8);
+        vm.execute().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0008);
+        assert!(vm.cpu.state.iff1);
+    }
+
+    #[test]
+    fn restart() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x8000);
+        p.add(Opcode::Rst28);
+        vm.load(&p);
+        vm.bus.write_u8(0x0028, Opcode::Halt as u8);
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0029);
+        assert_eq!(vm.bus.read_u16(0x7FFE), 0x0004);
+    }
+
+    fn cycles_of(program: &Program) -> Vec<u32> {
+        let mut vm = Machine::new();
+        vm.load(program);
+        vm.cpu.state.registers.c = 0x02;
+        vm.cpu.state.registers.d = 0x50;
+        let mut cycles = Vec::new();
+        while !vm.cpu.is_halted() {
+            cycles.push(vm.step().unwrap());
+        }
+        cycles
+    }
+
+    #[test]
+    fn instruction_timing() {
+        let mut p = Program::new();
+        p.add(Opcode::Nop);
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add(Opcode::IncVHL);
+        p.add_param(
+            Opcode::PrefixCB,
+            BitOpcode::Bit(0, Operand::IndirectHL).into(),
+        );
+        p.add_param(Opcode::PrefixCB, BitOpcode::Rl(Operand::IndirectHL).into());
+        p.add(Opcode::PrefixDD);
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add(Opcode::PrefixDD);
+        p.add_param(Opcode::LdAHL, 0x01);
+        p.add(Opcode::PrefixFD);
+        p.add_params(
+            Opcode::PrefixCB,
+            0x00,
+            BitOpcode::Set(1, Operand::IndirectHL).into(),
+        );
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Ldir as u8);
+        p.add(Opcode::Halt);
+        assert_eq!(
+            cycles_of(&p),
+            vec![4, 10, 11, 12, 15, 14, 19, 23, 21, 16, 4]
+        );
+    }
+
+    #[test]
+    fn interrupt_timing() {
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im1);
+        vm.start().unwrap();
+        vm.raise_irq();
+        assert_eq!(vm.step().unwrap(), 13);
+
+        let mut vm = interrupt_test_machine(ExtendedOpcode::Im2);
+        vm.start().unwrap();
+        vm.raise_irq();
+        assert_eq!(vm.step().unwrap(), 19);
+
+        vm.raise_nmi();
+        assert_eq!(vm.step().unwrap(), 11);
+    }
+
+    #[test]
+    fn run_for_cycles() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x0000);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        assert_eq!(vm.run_for_cycles(12).unwrap(), 14);
+        assert!(vm.cpu.is_halted());
+        assert_eq!(vm.run_for_cycles(10).unwrap(), 12);
+        assert_eq!(vm.cycles(), 26);
+    }
+
+    struct Latch {
+        value: u8,
+        writes: Vec<(u8, u8)>,
+    }
+
+    impl PortDevice for Latch {
+        fn read(&mut self, _port: u8) -> u8 {
+            self.value
+        }
+
+        fn write(&mut self, port: u8, value: u8) {
+            self.writes.push((port, value));
+        }
+    }
+
+    fn attach_latch(
+        vm: &mut Machine,
+        range: PortRange,
+        access: Access,
+        value: u8,
+    ) -> Rc<RefCell<Latch>> {
+        let latch = Rc::new(RefCell::new(Latch {
+            value,
+            writes: Vec::new(),
+        }));
+        vm.io.attach(range, access, Box::new(latch.clone()));
+        latch
+    }
+
+    #[test]
+    fn port_decoding() {
+        let mut vm = Machine::new();
+        let counter = attach_latch(&mut vm, PortRange::sms(0x7E), Access::Read, 0x42);
+        let sound = attach_latch(
+            &mut vm,
+            PortRange::Masked {
+                mask: 0xC0,
+                value: 0x40,
+            },
+            Access::Write,
+            0x00,
+        );
+        let fm = attach_latch(
+            &mut vm,
+            PortRange::Span(0xF0, 0xF2),
+            Access::ReadWrite,
+            0x01,
+        );
+        let pad = attach_latch(&mut vm, PortRange::sms(0xDC), Access::Read, 0xFE);
+
+        assert_eq!(vm.io.read(0x40), 0x42);
+        assert_eq!(vm.io.read(0x7C), 0x42);
+        assert_eq!(vm.io.read(0x7F), 0xFF);
+        assert_eq!(vm.io.read(0xF0), 0xFE);
+        assert_eq!(vm.io.read(0x00), 0xFF);
+        vm.io.write(0x7F, 0x9F);
+        vm.io.write(0xF1, 0x10);
+
+        assert!(counter.borrow().writes.is_empty());
+        assert_eq!(sound.borrow().writes, vec![(0x7F, 0x9F)]);
+        assert_eq!(fm.borrow().writes, vec![(0xF1, 0x10)]);
+        assert!(pad.borrow().writes.is_empty());
+    }
+
+    #[test]
+    fn port_instructions() {
+        let mut vm = Machine::new();
+        let device = attach_latch(&mut vm, PortRange::exact(0xBF), Access::ReadWrite, 0x80);
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x12);
+        p.add_param(Opcode::OutVXA, 0xBF);
+        p.add_param(Opcode::InAVX, 0xBF);
+        p.add_param(Opcode::LdCX, 0xBF);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::InEVC as u8);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::OutVCE as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.registers.a, 0x80);
+        assert_eq!(vm.cpu.state.registers.e, 0x80);
+        assert!(Flag::Sign.get(&vm.cpu.state.status));
+        assert!(!Flag::ParityOverflow.get(&vm.cpu.state.status));
+        assert_eq!(device.borrow().writes, vec![(0xBF, 0x12), (0xBF, 0x80)]);
+    }
+
+    #[test]
+    fn block_output() {
+        let mut vm = Machine::new();
+        let device = attach_latch(&mut vm, PortRange::exact(0xBE), Access::Write, 0x00);
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add_param_word(Opcode::LdBCXX, 0x03BE);
+        p.add_param(Opcode::PrefixED, ExtendedOpcode::Otir as u8);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.bus.write_u8(0x4000, 0x01);
+        vm.bus.write_u8(0x4001, 0x02);
+        vm.bus.write_u8(0x4002, 0x03);
+
+        vm.start().unwrap();
+
+        assert_eq!(
+            device.borrow().writes,
+            vec![(0xBE, 0x01), (0xBE, 0x02), (0xBE, 0x03)]
+        );
+        assert_eq!(vm.cpu.state.registers.b, 0x00);
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+    }
+
+    #[test]
+    fn system_bus_mapping() {
+        let mut rom = vec![0x00; 0x100];
+        rom[0x10] = 0x5A;
+        let mut bus = SystemBus::new(Box::new(Rom::new(rom)));
+
+        bus.write_u8(0x0010, 0xFF);
+        assert_eq!(bus.read_u8(0x0010), 0x5A);
+        assert_eq!(bus.read_u8(0x8000), 0xFF);
+
+        bus.write_u8(0xC123, 0x42);
+        assert_eq!(bus.read_u8(0xC123), 0x42);
+        assert_eq!(bus.read_u8(0xE123), 0x42);
+
+        bus.write_u16(0xDFFF, 0xBEEF);
+        assert_eq!(bus.read_u8(0xFFFF), 0xEF);
+        assert_eq!(bus.read_u8(0xC000), 0xBE);
+        assert_eq!(bus.read_u16(0xFFFF), 0x00EF);
+    }
+
+    #[test]
+    fn program_on_system_bus() {
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0xE000);
+        p.add_param(Opcode::LdAX, 0x99);
+        p.add_param_word(Opcode::LdVXXA, 0x0000);
+        p.add_param_word(Opcode::LdBCXX, 0x1234);
+        p.add(Opcode::PushBC);
+        p.add(Opcode::Halt);
+        let mut vm = Machine::with_bus(Box::new(SystemBus::new(Box::new(Rom::new(
+            p.raw().clone(),
+        )))));
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.bus.read_u8(0x0000), Opcode::LdSPXX as u8);
+        assert_eq!(vm.bus.read_u16(0xDFFE), 0x1234);
+        assert_eq!(vm.bus.read_u16(0xFFFE), 0x1234);
+    }
+
+    fn paged_rom(pages: usize) -> Vec<u8> {
+        let mut rom = vec![0x00; pages * 0x4000];
+        for page in 0..pages {
+            for byte in rom[page * 0x4000..(page + 1) * 0x4000].iter_mut() {
+                *byte = page as u8;
+            }
+        }
+        rom
+    }
+
+    #[test]
+    fn sega_mapper_paging() {
+        let mut cartridge = Cartridge::new(paged_rom(8)).unwrap();
+        assert_eq!(cartridge.page_count(), 8);
+        assert_eq!(cartridge.read_u8(0x0000), 0);
+        assert_eq!(cartridge.read_u8(0x4000), 1);
+        assert_eq!(cartridge.read_u8(0x8000), 2);
+
+        cartridge.write_u8(0xFFFD, 5);
+        cartridge.write_u8(0xFFFE, 6);
+        cartridge.write_u8(0xFFFF, 7);
+        assert_eq!(cartridge.read_u8(0x03FF), 0);
+        assert_eq!(cartridge.read_u8(0x0400), 5);
+        assert_eq!(cartridge.read_u8(0x7FFF), 6);
+        assert_eq!(cartridge.read_u8(0xBFFF), 7);
+
+        // Out of range banks wrap around the image.
+        cartridge.write_u8(0xFFFF, 9);
+        assert_eq!(cartridge.read_u8(0x8000), 1);
+    }
+
+    #[test]
+    fn sega_mapper_sram() {
+        let mut cartridge = Cartridge::new(paged_rom(4)).unwrap();
+        cartridge.write_u8(0x8000, 0x55);
+        assert_eq!(cartridge.read_u8(0x8000), 2);
+        assert!(!cartridge.has_save_data());
+
+        cartridge.write_u8(0xFFFC, 0x08);
+        cartridge.write_u8(0x8000, 0x55);
+        cartridge.write_u8(0xFFFC, 0x0C);
+        cartridge.write_u8(0x8000, 0xAA);
+        assert_eq!(cartridge.read_u8(0x8000), 0xAA);
+        cartridge.write_u8(0xFFFC, 0x08);
+        assert_eq!(cartridge.read_u8(0x8000), 0x55);
+        cartridge.write_u8(0xFFFC, 0x00);
+        assert_eq!(cartridge.read_u8(0x8000), 2);
+
+        assert!(cartridge.has_save_data());
+        assert_eq!(cartridge.sram()[0x0000], 0x55);
+        assert_eq!(cartridge.sram()[0x4000], 0xAA);
+    }
+
+    #[test]
+    fn cartridge_loading() {
+        assert_eq!(Cartridge::new(vec![]).err(), Some(CartridgeError::Empty));
+        assert_eq!(
+            Cartridge::new(vec![0; MAX_ROM_SIZE + 0x4000]).err(),
+            Some(CartridgeError::TooLarge(MAX_ROM_SIZE + 0x4000))
+        );
+
+        let mut image = vec![0xEE; 512];
+        image.extend(paged_rom(2));
+        let cartridge = Cartridge::new(image).unwrap();
+        assert_eq!(cartridge.rom().len(), 0x8000);
+        assert_eq!(cartridge.read_u8(0x0000), 0);
+    }
+
+    #[test]
+    fn program_on_cartridge() {
+        let mut rom = paged_rom(4);
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 3);
+        p.add_param_word(Opcode::LdVXXA, 0xFFFF);
+        p.add_param_word(Opcode::LdAVXX, 0x8000);
+        p.add(Opcode::Halt);
+        rom[..p.raw().len()].copy_from_slice(p.raw());
+        let mut vm = Machine::with_cartridge(Cartridge::new(rom).unwrap());
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.cpu.state.registers.a, 3);
+        assert_eq!(vm.bus.read_u8(0xDFFF), 3);
+        assert_eq!(vm.cartridge().unwrap().borrow().read_u8(0xA000), 3);
+    }
+
+    #[test]
+    fn mapper_detection() {
+        let mut codemasters = paged_rom(8);
+        codemasters[0x7FE6] = 0x34;
+        codemasters[0x7FE7] = 0x12;
+        codemasters[0x7FE8] = 0xCC;
+        codemasters[0x7FE9] = 0xED;
+        assert_eq!(
+            MapperKind::detect(&codemasters, None),
+            MapperKind::Codemasters
+        );
+
+        let mut korean = paged_rom(8);
+        korean[0x100..0x103].copy_from_slice(&[0x32, 0x00, 0xA0]);
+        korean[0x200..0x203].copy_from_slice(&[0x32, 0x00, 0xA0]);
+        korean[0x300..0x303].copy_from_slice(&[0x32, 0xFF, 0xFF]);
+        assert_eq!(MapperKind::detect(&korean, None), MapperKind::Korean);
+
+        assert_eq!(MapperKind::detect(&paged_rom(8), None), MapperKind::Sega);
+
+        let cartridge = Cartridge::new(korean.clone()).unwrap();
+        assert_eq!(cartridge.mapper_kind(), MapperKind::Korean);
+        let cartridge = Cartridge::with_mapper(korean, MapperKind::Sega).unwrap();
+        assert_eq!(cartridge.mapper_kind(), MapperKind::Sega);
+    }
+
+    #[test]
+    fn codemasters_mapper_paging() {
+        let mut cartridge = Cartridge::with_mapper(paged_rom(8), MapperKind::Codemasters).unwrap();
+        assert_eq!(cartridge.read_u8(0x0000), 0);
+        assert_eq!(cartridge.read_u8(0x4000), 1);
+        assert_eq!(cartridge.read_u8(0x8000), 0);
+
+        cartridge.write_u8(0x0000, 3);
+        cartridge.write_u8(0x4000, 4);
+        cartridge.write_u8(0x8000, 5);
+        assert_eq!(cartridge.read_u8(0x0000), 3);
+        assert_eq!(cartridge.read_u8(0x7FFF), 4);
+        assert_eq!(cartridge.read_u8(0xBFFF), 5);
+
+        // Sega mapper registers are plain RAM on these boards.
+        cartridge.write_u8(0xFFFF, 7);
+        assert_eq!(cartridge.read_u8(0x8000), 5);
+    }
+
+    #[test]
+    fn korean_mapper_paging() {
+        let mut cartridge = Cartridge::with_mapper(paged_rom(8), MapperKind::Korean).unwrap();
+        assert_eq!(cartridge.read_u8(0x8000), 2);
+
+        cartridge.write_u8(0xA000, 6);
+        assert_eq!(cartridge.read_u8(0x0400), 0);
+        assert_eq!(cartridge.read_u8(0x4000), 1);
+        assert_eq!(cartridge.read_u8(0x8000), 6);
+
+        cartridge.write_u8(0xFFFF, 3);
+        assert_eq!(cartridge.read_u8(0x8000), 6);
+    }
+
+    fn with_header(mut rom: Vec<u8>, region_and_size: u8) -> Vec<u8> {
+        rom[0x7FF0..0x7FF8].copy_from_slice(b"TMR SEGA");
+        rom[0x7FFC] = 0x26;
+        rom[0x7FFD] = 0x70;
+        rom[0x7FFE] = 0x21;
+        rom[0x7FFF] = region_and_size;
+        let header = RomHeader::parse(&rom).unwrap();
+        let checksum = header.compute_checksum(&rom).unwrap();
+        rom[0x7FFA] = checksum as u8;
+        rom[0x7FFB] = (checksum >> 8) as u8;
+        rom
+    }
+
+    #[test]
+    fn rom_header() {
+        let rom = with_header(paged_rom(8), 0x4F);
+        let header = RomHeader::parse(&rom).unwrap();
+        assert_eq!(header.offset, 0x7FF0);
+        assert_eq!(header.product_code, 27026);
+        assert_eq!(header.version, 1);
+        assert_eq!(header.region, RegionCode::SmsExport);
+        assert_eq!(header.declared_size(), Some(0x20000));
+        assert_eq!(header.compute_checksum(&rom), Some(0xFFF0));
+        assert!(header.verify(&rom));
+
+        let cartridge = Cartridge::new(rom.clone()).unwrap();
+        assert!(cartridge.warnings().is_empty());
+
+        let mut bad_dump = rom.clone();
+        bad_dump[0x1234] ^= 0xFF;
+        let cartridge = Cartridge::new(bad_dump).unwrap();
+        assert_eq!(
+            cartridge.warnings(),
+            vec![RomWarning::ChecksumMismatch {
+                declared: header.checksum,
+                computed: header.checksum.wrapping_add(0xFF),
+            }]
+        );
+
+        let cartridge = Cartridge::new(rom[..0x10000].to_vec()).unwrap();
+        assert_eq!(
+            cartridge.warnings(),
+            vec![RomWarning::Truncated {
+                declared: 0x20000,
+                actual: 0x10000,
+            }]
+        );
+
+        let cartridge = Cartridge::new(paged_rom(2)).unwrap();
+        assert!(cartridge.header().is_none());
+        assert_eq!(cartridge.warnings(), vec![RomWarning::MissingHeader]);
+    }
+
+    #[test]
+    fn region_from_header() {
+        let machine =
+            Machine::with_cartridge(Cartridge::new(with_header(paged_rom(8), 0x3F)).unwrap());
+        assert_eq!(machine.region(), Region::Japan);
+        let machine =
+            Machine::with_cartridge(Cartridge::new(with_header(paged_rom(8), 0x4F)).unwrap());
+        assert_eq!(machine.region(), Region::Export);
+
+        // A verified header outweighs the Korean bank switch heuristic.
+        let mut rom = paged_rom(8);
+        rom[0x100..0x103].copy_from_slice(&[0x32, 0x00, 0xA0]);
+        let cartridge = Cartridge::new(with_header(rom, 0x4F)).unwrap();
+        assert_eq!(cartridge.mapper_kind(), MapperKind::Sega);
+    }
+
+    fn cartridge_machine(p: &Program) -> Machine {
+        let mut rom = vec![0x00; 0x8000];
+        rom[..p.raw().len()].copy_from_slice(p.raw());
+        Machine::with_cartridge(Cartridge::new(rom).unwrap())
+    }
+
+    fn set_vdp_address(vdp: &mut Vdp, address: u16, code: u8) {
+        vdp.write_control(address as u8);
+        vdp.write_control((code << 6) | (address >> 8) as u8);
+    }
+
+    #[test]
+    fn vdp_control_port() {
+        let mut vdp = Vdp::new();
+        vdp.write_control(0xA0);
+        vdp.write_control(0x81);
+        assert_eq!(vdp.register(1), 0xA0);
+        assert_eq!(vdp.code(), AccessCode::RegisterWrite);
+
+        // Registers beyond the eleventh are ignored.
+        vdp.write_control(0x55);
+        vdp.write_control(0x8F);
+        assert_eq!(vdp.register(10), 0x00);
+
+        // A data port access resets the control latch.
+        vdp.write_control(0x34);
+        vdp.write_data(0x00);
+        vdp.write_control(0x12);
+        vdp.write_control(0x7F);
+        assert_eq!(vdp.address(), 0x3F12);
+        assert_eq!(vdp.code(), AccessCode::VramWrite);
+    }
+
+    #[test]
+    fn vdp_vram_access() {
+        let mut vdp = Vdp::new();
+        set_vdp_address(&mut vdp, 0x3FFE, 1);
+        vdp.write_data(0x11);
+        vdp.write_data(0x22);
+        vdp.write_data(0x33);
+        assert_eq!(vdp.vram()[0x3FFE], 0x11);
+        assert_eq!(vdp.vram()[0x3FFF], 0x22);
+        assert_eq!(vdp.vram()[0x0000], 0x33);
+        assert_eq!(vdp.address(), 0x0001);
+
+        // Reads come from the buffer filled when the address was set.
+        set_vdp_address(&mut vdp, 0x3FFE, 0);
+        assert_eq!(vdp.read_data(), 0x11);
+        assert_eq!(vdp.read_data(), 0x22);
+        assert_eq!(vdp.read_data(), 0x33);
+
+        // Writes refill the buffer with the written value.
+        vdp.write_data(0x44);
+        assert_eq!(vdp.read_data(), 0x44);
+    }
+
+    #[test]
+    fn vdp_cram_and_status() {
+        let mut vdp = Vdp::new();
+        set_vdp_address(&mut vdp, 0x001F, 3);
+        vdp.write_data(0x3F);
+        vdp.write_data(0x15);
+        assert_eq!(vdp.cram()[0x1F], 0x3F);
+        assert_eq!(vdp.cram()[0x00], 0x15);
+        assert_eq!(vdp.vram()[0x001F], 0x00);
+
+        vdp.set_status(STATUS_FRAME_INTERRUPT | 0x1F);
+        vdp.write_control(0x00);
+        assert_eq!(vdp.read_control(), 0x9F);
+        assert_eq!(vdp.read_control(), 0x1F);
+
+        // The status read also dropped the half-written control word.
+        vdp.write_control(0x02);
+        vdp.write_control(0x82);
+        assert_eq!(vdp.register(2), 0x02);
+    }
+
+    #[test]
+    fn vdp_on_io_bus() {
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x00);
+        p.add_param(Opcode::OutVXA, 0xBF);
+        p.add_param(Opcode::LdAX, 0x40);
+        p.add_param(Opcode::OutVXA, 0xBF);
+        p.add_param(Opcode::LdAX, 0x5A);
+        p.add_param(Opcode::OutVXA, 0xBE);
+        p.add_param(Opcode::LdAX, 0xFF);
+        p.add_param(Opcode::OutVXA, 0xBF);
+        p.add_param(Opcode::LdAX, 0x87);
+        p.add_param(Opcode::OutVXA, 0xBD);
+        p.add(Opcode::Halt);
+        let mut vm = cartridge_machine(&p);
+
+        vm.start().unwrap();
+
+        assert_eq!(vm.vdp().vram()[0x0000], 0x5A);
+        assert_eq!(vm.vdp().register(7), 0xFF);
+    }
+
+    fn write_vram(vdp: &mut Vdp, address: u16, data: &[u8]) {
+        set_vdp_address(vdp, address, 1);
+        for &value in data {
+            vdp.write_data(value);
+        }
+    }
+
+    fn write_vdp_register(vdp: &mut Vdp, index: u8, value: u8) {
+        vdp.write_control(value);
+        vdp.write_control(0x80 | index);
+    }
+
+    // Tile 1 has a single pixel of color 1 at its top left corner and
+    // tile 2 is solid color 2; CRAM 1 and 2 are red and green, CRAM 17
+    // blue and the backdrop (CRAM 16) white.
+    fn mode4_vdp() -> Vdp {
+        let mut vdp = Vdp::new();
+        write_vdp_register(&mut vdp, 0, 0x04);
+        write_vdp_register(&mut vdp, 1, 0x40);
+        write_vdp_register(&mut vdp, 2, 0xFF);
+        write_vram(&mut vdp, 0x0020, &[0x80, 0x00, 0x00, 0x00]);
+        write_vram(&mut vdp, 0x0040, &[0x00, 0xFF, 0x00, 0x00].repeat(8));
+        set_vdp_address(&mut vdp, 0x0000, 3);
+        for &value in &[0x00, 0x03, 0x0C] {
+            vdp.write_data(value);
+        }
+        set_vdp_address(&mut vdp, 0x0010, 3);
+        vdp.write_data(0x3F);
+        vdp.write_data(0x30);
+        vdp
+    }
+
+    const RED: u32 = 0xFF0000;
+    const GREEN: u32 = 0x00FF00;
+    const BLUE: u32 = 0x0000FF;
+    const WHITE: u32 = 0xFFFFFF;
+    const BLACK: u32 = 0x000000;
+
+    fn pixel(vdp: &Vdp, x: usize, y: usize) -> u32 {
+        vdp.framebuffer()[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn sms_palette() {
+        assert_eq!(palette::sms_color(0x00), BLACK);
+        assert_eq!(palette::sms_color(0x03), RED);
+        assert_eq!(palette::sms_color(0x0C), GREEN);
+        assert_eq!(palette::sms_color(0x30), BLUE);
+        assert_eq!(palette::sms_color(0x15), 0x555555);
+    }
+
+    #[test]
+    fn mode4_tiles() {
+        let mut vdp = mode4_vdp();
+        // Row 0: plain, h-flipped, v-flipped, sprite palette.
+        write_vram(
+            &mut vdp,
+            0x3800,
+            &[0x01, 0x00, 0x01, 0x02, 0x01, 0x04, 0x01, 0x08],
+        );
+        vdp.render_frame();
+
+        assert_eq!(pixel(&vdp, 0, 0), RED);
+        assert_eq!(pixel(&vdp, 1, 0), BLACK);
+        assert_eq!(pixel(&vdp, 15, 0), RED);
+        assert_eq!(pixel(&vdp, 8, 0), BLACK);
+        assert_eq!(pixel(&vdp, 16, 0), BLACK);
+        assert_eq!(pixel(&vdp, 16, 7), RED);
+        assert_eq!(pixel(&vdp, 24, 0), BLUE);
+
+        write_vdp_register(&mut vdp, 1, 0x00);
+        vdp.render_frame();
+        assert!(vdp.framebuffer().iter().all(|&pixel| pixel == WHITE));
+    }
+
+    #[test]
+    fn mode4_scrolling() {
+        let mut vdp = mode4_vdp();
+        write_vram(&mut vdp, 0x3800, &[0x01, 0x00]);
+        write_vdp_register(&mut vdp, 8, 0x03);
+        write_vdp_register(&mut vdp, 9, 0x01);
+        vdp.render_frame();
+        // Shifted right by three and up by one, wrapping at 224 lines.
+        assert_eq!(pixel(&vdp, 3, 0), BLACK);
+
+        write_vram(&mut vdp, 0x3800 + 64, &[0x01, 0x00]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 3, 7), RED);
+
+        // With the top two rows locked they ignore the horizontal scroll.
+        write_vdp_register(&mut vdp, 0, 0x44);
+        write_vdp_register(&mut vdp, 9, 0x00);
+        write_vram(&mut vdp, 0x3800 + 64 * 2, &[0x01, 0x00]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 8), RED);
+        assert_eq!(pixel(&vdp, 3, 16), RED);
+    }
+
+    #[test]
+    fn mode4_right_column_lock() {
+        let mut vdp = mode4_vdp();
+        write_vram(&mut vdp, 0x3800 + 64 + 23 * 2, &[0x01, 0x00, 0x01, 0x00]);
+        write_vdp_register(&mut vdp, 9, 0x08);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 184, 0), RED);
+        assert_eq!(pixel(&vdp, 192, 0), RED);
+
+        // Columns 24 and up ignore the vertical scroll once locked.
+        write_vdp_register(&mut vdp, 0, 0x84);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 184, 0), RED);
+        assert_eq!(pixel(&vdp, 192, 0), BLACK);
+        assert_eq!(pixel(&vdp, 192, 8), RED);
+    }
+
+    #[test]
+    fn mode4_priority_and_left_column() {
+        let mut vdp = mode4_vdp();
+        write_vram(&mut vdp, 0x3800, &[0x02, 0x10, 0x01, 0x10]);
+        let mut pixels = [0; SCREEN_WIDTH];
+        let mut priority = [false; SCREEN_WIDTH];
+        vdp.render_background(0, &mut pixels, &mut priority);
+        assert_eq!(&pixels[6..10], &[2, 2, 1, 0]);
+        assert_eq!(&priority[6..10], &[true, true, true, false]);
+
+        write_vdp_register(&mut vdp, 0, 0x24);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 7, 0), WHITE);
+        assert_eq!(pixel(&vdp, 8, 0), RED);
+    }
+
+    // Places sprites given as (y, x, tile) in a table at 0x3F00.
+    fn sprite_vdp(sprites: &[(u8, u8, u8)]) -> Vdp {
+        let mut vdp = mode4_vdp();
+        write_vdp_register(&mut vdp, 5, 0xFF);
+        for (index, &(y, x, tile)) in sprites.iter().enumerate() {
+            write_vram(&mut vdp, 0x3F00 + index as u16, &[y]);
+            write_vram(&mut vdp, 0x3F80 + index as u16 * 2, &[x, tile]);
+        }
+        write_vram(&mut vdp, 0x3F00 + sprites.len() as u16, &[0xD0]);
+        set_vdp_address(&mut vdp, 0x0012, 3);
+        vdp.write_data(0x0F);
+        vdp
+    }
+
+    const YELLOW: u32 = 0xFFFF00;
+
+    #[test]
+    fn mode4_sprites() {
+        let mut vdp = sprite_vdp(&[(9, 20, 2), (9, 24, 1), (0xFF, 40, 1)]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 20, 9), BLACK);
+        assert_eq!(pixel(&vdp, 20, 10), YELLOW);
+        assert_eq!(pixel(&vdp, 27, 17), YELLOW);
+        assert_eq!(pixel(&vdp, 28, 17), BLACK);
+        assert_eq!(pixel(&vdp, 20, 18), BLACK);
+        // A Y near the bottom of the range wraps to the top of the screen.
+        assert_eq!(pixel(&vdp, 40, 0), BLUE);
+        // The second sprite lost the overlap but still collided.
+        assert_eq!(
+            vdp.status() & STATUS_SPRITE_COLLISION,
+            STATUS_SPRITE_COLLISION
+        );
+        assert_eq!(vdp.status() & STATUS_SPRITE_OVERFLOW, 0);
+
+        write_vdp_register(&mut vdp, 0, 0x0C);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 12, 10), YELLOW);
+        assert_eq!(pixel(&vdp, 20, 10), BLACK);
+    }
+
+    #[test]
+    fn mode4_tall_and_zoomed_sprites() {
+        // Tall sprites ignore bit 0 of the tile: tile 2 on top, 3 below.
+        let mut vdp = sprite_vdp(&[(0x1F, 0, 3)]);
+        write_vram(&mut vdp, 0x0060, &[0x80, 0x00, 0x00, 0x00]);
+        write_vdp_register(&mut vdp, 1, 0x42);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 31), BLACK);
+        assert_eq!(pixel(&vdp, 7, 39), YELLOW);
+        assert_eq!(pixel(&vdp, 0, 40), BLUE);
+        assert_eq!(pixel(&vdp, 1, 40), BLACK);
+        assert_eq!(pixel(&vdp, 0, 48), BLACK);
+
+        write_vdp_register(&mut vdp, 1, 0x41);
+        write_vram(&mut vdp, 0x3F81, &[0x01]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 32), BLUE);
+        assert_eq!(pixel(&vdp, 1, 33), BLUE);
+        assert_eq!(pixel(&vdp, 2, 32), BLACK);
+        assert_eq!(pixel(&vdp, 0, 34), BLACK);
+    }
+
+    #[test]
+    fn mode4_sprite_limit() {
+        let sprites: Vec<(u8, u8, u8)> = (0..9).map(|i| (49, i * 16, 2)).collect();
+        let mut vdp = sprite_vdp(&sprites);
+        vdp.render_line(49);
+        assert_eq!(vdp.status() & STATUS_SPRITE_OVERFLOW, 0);
+        vdp.render_line(50);
+        assert_eq!(
+            vdp.status() & STATUS_SPRITE_OVERFLOW,
+            STATUS_SPRITE_OVERFLOW
+        );
+        assert_eq!(vdp.status() & STATUS_SPRITE_COLLISION, 0);
+        assert_eq!(pixel(&vdp, 7 * 16, 50), YELLOW);
+        assert_eq!(pixel(&vdp, 8 * 16, 50), BLACK);
+
+        // The terminator hides everything after it.
+        write_vram(&mut vdp, 0x3F00 + 4, &[0xD0]);
+        vdp.read_control();
+        vdp.render_line(50);
+        assert_eq!(vdp.status() & STATUS_SPRITE_OVERFLOW, 0);
+        assert_eq!(pixel(&vdp, 4 * 16, 50), BLACK);
+    }
+
+    #[test]
+    fn mode4_sprite_behind_background() {
+        let mut vdp = sprite_vdp(&[(0xFF, 0, 2)]);
+        write_vram(&mut vdp, 0x3800, &[0x01, 0x10]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 0), RED);
+        assert_eq!(pixel(&vdp, 1, 0), YELLOW);
+    }
+
+    #[test]
+    fn vdp_counters() {
+        assert_eq!(TvSystem::Ntsc.v_counter(0xDA), 0xDA);
+        assert_eq!(TvSystem::Ntsc.v_counter(0xDB), 0xD5);
+        assert_eq!(TvSystem::Ntsc.v_counter(261), 0xFF);
+        assert_eq!(TvSystem::Pal.v_counter(0xF2), 0xF2);
+        assert_eq!(TvSystem::Pal.v_counter(0xF3), 0xBA);
+        assert_eq!(TvSystem::Pal.v_counter(312), 0xFF);
+        assert_eq!(timing::h_counter(0), 0x00);
+        assert_eq!(timing::h_counter(197), 0x93);
+        assert_eq!(timing::h_counter(198), 0xE9);
+        assert_eq!(timing::h_counter(227), 0xFF);
+
+        let mut vdp = Vdp::new();
+        vdp.tick(228 * 100 + 114);
+        vdp.latch_h_counter();
+        assert_eq!(vdp.read(0x7E), 100);
+        assert_eq!(vdp.read(0x7F), 0x55);
+        vdp.tick(228 * 162);
+        assert_eq!(vdp.line(), 0);
+        assert_eq!(vdp.frame(), 1);
+    }
+
+    #[test]
+    fn vdp_line_interrupts() {
+        let mut vdp = Vdp::new();
+        write_vdp_register(&mut vdp, 0, 0x14);
+        write_vdp_register(&mut vdp, 10, 0x03);
+        vdp.tick(228 * 262);
+        vdp.read_control();
+
+        let mut lines = Vec::new();
+        for line in 0..262 {
+            vdp.tick(228);
+            if vdp.interrupt_asserted() {
+                lines.push(line);
+                vdp.read_control();
+            }
+        }
+        let expected: Vec<usize> = (0..48).map(|i| i * 4 + 3).collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn vdp_frame_interrupt() {
+        let mut vdp = Vdp::new();
+        vdp.tick(228 * 192);
+        assert_eq!(vdp.status() & STATUS_FRAME_INTERRUPT, 0);
+        vdp.tick(228);
+        assert_eq!(
+            vdp.status() & STATUS_FRAME_INTERRUPT,
+            STATUS_FRAME_INTERRUPT
+        );
+        assert!(!vdp.interrupt_asserted());
+        write_vdp_register(&mut vdp, 1, 0x20);
+        assert!(vdp.interrupt_asserted());
+        vdp.read_control();
+        assert!(!vdp.interrupt_asserted());
+    }
+
+    #[test]
+    fn frame_interrupt_drives_cpu() {
+        let mut main = Program::new();
+        main.add_param_word(Opcode::LdSPXX, 0xDFF0);
+        main.add_param(Opcode::PrefixED, ExtendedOpcode::Im1 as u8);
+        main.add_param(Opcode::LdAX, 0x20);
+        main.add_param(Opcode::OutVXA, 0xBF);
+        main.add_param(Opcode::LdAX, 0x81);
+        main.add_param(Opcode::OutVXA, 0xBF);
+        main.add(Opcode::Ei);
+        let spin = main.raw().len() as u16;
+        main.add_param_word(Opcode::JpXX, spin);
+        let mut handler = Program::new();
+        handler.add_param(Opcode::InAVX, 0xBF);
+        handler.add_param_word(Opcode::LdHLXX, 0xC000);
+        handler.add(Opcode::IncVHL);
+        handler.add(Opcode::Ei);
+        handler.add_param(Opcode::PrefixED, ExtendedOpcode::Reti as u8);
+        let mut rom = vec![0x00; 0x8000];
+        rom[..main.raw().len()].copy_from_slice(main.raw());
+        rom[0x38..0x38 + handler.raw().len()].copy_from_slice(handler.raw());
+        let mut vm = Machine::with_cartridge(Cartridge::new(rom).unwrap());
+
+        let cycles = vm.run_frame().unwrap();
+        assert!((228 * 262..228 * 262 + 32).contains(&cycles));
+        vm.run_frame().unwrap();
+        assert_eq!(vm.bus.read_u8(0xC000), 2);
+        assert!(!vm.cpu.irq_asserted());
+
+        vm.set_tv_system(TvSystem::Pal);
+        let cycles = vm.run_frame().unwrap();
+        assert!(cycles > 228 * 312);
+        assert_eq!(vm.bus.read_u8(0xC000), 3);
+    }
+
+    // Name table at 0x3800, color table at 0x2000, patterns at 0x0000,
+    // sprites at 0x3B00 with patterns at 0x1800; pattern 1 is a vertical
+    // bar in the leftmost column and the backdrop is dark blue.
+    fn tms_vdp(reg0: u8, reg1: u8) -> Vdp {
+        let mut vdp = Vdp::new();
+        write_vdp_register(&mut vdp, 0, reg0);
+        write_vdp_register(&mut vdp, 1, 0x40 | reg1);
+        write_vdp_register(&mut vdp, 2, 0x0E);
+        write_vdp_register(&mut vdp, 3, 0x80);
+        write_vdp_register(&mut vdp, 4, 0x00);
+        write_vdp_register(&mut vdp, 5, 0x76);
+        write_vdp_register(&mut vdp, 6, 0x03);
+        write_vdp_register(&mut vdp, 7, 0xF4);
+        write_vram(&mut vdp, 0x0008, &[0x80; 8]);
+        write_vram(&mut vdp, 0x3B00, &[0xD0]);
+        vdp
+    }
+
+    fn tms(index: u8) -> u32 {
+        palette::tms_color(index)
+    }
+
+    #[test]
+    fn tms_display_modes() {
+        assert_eq!(tms_vdp(0x00, 0x00).display_mode(), DisplayMode::Graphics1);
+        assert_eq!(tms_vdp(0x02, 0x00).display_mode(), DisplayMode::Graphics2);
+        assert_eq!(tms_vdp(0x00, 0x10).display_mode(), DisplayMode::Text);
+        assert_eq!(tms_vdp(0x00, 0x08).display_mode(), DisplayMode::Multicolor);
+        assert_eq!(tms_vdp(0x06, 0x18).display_mode(), DisplayMode::Mode4);
+        assert_eq!(tms(0), tms(1));
+        assert_eq!(tms(15), WHITE);
+    }
+
+    #[test]
+    fn tms_graphics1() {
+        let mut vdp = tms_vdp(0x00, 0x00);
+        write_vram(&mut vdp, 0x3800, &[0x01, 0x00, 0x01]);
+        write_vram(&mut vdp, 0x2000, &[0x6F]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 0), tms(6));
+        assert_eq!(pixel(&vdp, 1, 0), tms(15));
+        assert_eq!(pixel(&vdp, 8, 0), tms(15));
+        assert_eq!(pixel(&vdp, 16, 7), tms(6));
+        assert_eq!(pixel(&vdp, 0, 8), tms(15));
+
+        // Transparent colors show the backdrop.
+        write_vram(&mut vdp, 0x2000, &[0x60]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 1, 0), tms(4));
+    }
+
+    #[test]
+    fn tms_graphics2() {
+        let mut vdp = tms_vdp(0x02, 0x00);
+        write_vdp_register(&mut vdp, 3, 0xFF);
+        write_vdp_register(&mut vdp, 4, 0x03);
+        write_vram(&mut vdp, 0x3800, &[0x01]);
+        write_vram(&mut vdp, 0x3800 + 256, &[0x01]);
+        write_vram(&mut vdp, 0x0808, &[0xFF; 8]);
+        write_vram(&mut vdp, 0x2008, &[0x2A; 8]);
+        write_vram(&mut vdp, 0x2808, &[0xA2; 8]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 0), tms(2));
+        assert_eq!(pixel(&vdp, 1, 0), tms(10));
+        assert_eq!(pixel(&vdp, 0, 64), tms(10));
+        assert_eq!(pixel(&vdp, 1, 64), tms(10));
+
+        // Clearing the mask bits folds the lower thirds onto the first.
+        write_vdp_register(&mut vdp, 3, 0x9F);
+        write_vdp_register(&mut vdp, 4, 0x00);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 64), tms(2));
+        assert_eq!(pixel(&vdp, 1, 64), tms(10));
+    }
+
+    #[test]
+    fn tms_text_and_multicolor() {
+        let mut vdp = tms_vdp(0x00, 0x10);
+        write_vram(&mut vdp, 0x3800, &[0x01, 0x01]);
+        write_vram(&mut vdp, 0x3800 + 40, &[0x01]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 7, 0), tms(4));
+        assert_eq!(pixel(&vdp, 8, 0), tms(15));
+        assert_eq!(pixel(&vdp, 9, 0), tms(4));
+        assert_eq!(pixel(&vdp, 14, 0), tms(15));
+        assert_eq!(pixel(&vdp, 8, 8), tms(15));
+        assert_eq!(pixel(&vdp, 248, 0), tms(4));
+
+        let mut vdp = tms_vdp(0x00, 0x08);
+        write_vram(&mut vdp, 0x3800, &[0x01]);
+        write_vram(&mut vdp, 0x0008, &[0x2A, 0x6F]);
+        vdp.render_frame();
+        assert_eq!(pixel(&vdp, 0, 0), tms(2));
+        assert_eq!(pixel(&vdp, 4, 3), tms(10));
+        assert_eq!(pixel(&vdp, 0, 4), tms(6));
+        assert_eq!(pixel(&vdp, 7, 7), tms(15));
+    }
+
+    #[test]
+    fn tms_sprites() {
+        let mut vdp = tms_vdp(0x00, 0x02);
+        write_vram(&mut vdp, 0x1800 + 8 * 4, &[0xFF; 32]);
+        let mut attributes = Vec::new();
+        for sprite in 0..5 {
+            attributes.extend(&[0x1F, sprite * 12, 4, 0x09]);
+        }
+        attributes.extend(&[0xFF, 200, 4, 0x8D]);
+        attributes.push(0xD0);
+        write_vram(&mut vdp, 0x3B00, &attributes);
+        vdp.render_frame();
+
+        assert_eq!(pixel(&vdp, 0, 31), tms(4));
+        assert_eq!(pixel(&vdp, 15, 47), tms(9));
+        assert_eq!(pixel(&vdp, 51, 32), tms(9));
+        assert_eq!(pixel(&vdp, 52, 32), tms(4));
+        assert_eq!(pixel(&vdp, 168, 0), tms(13));
+        assert_eq!(pixel(&vdp, 184, 0), tms(4));
+        let status = vdp.read_control();
+        assert_eq!(status & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
+        assert_eq!(status & 0x1F, 4);
+        assert_eq!(status & STATUS_SPRITE_COLLISION, STATUS_SPRITE_COLLISION);
+    }
+
+    #[test]
+    fn psg_registers() {
+        let mut psg = Psg::new(NTSC_CLOCK, 44_100);
+        psg.write(0x8E);
+        psg.write(0x0F);
+        assert_eq!(psg.tone(0), 0x0FE);
+        psg.write(0xA5);
+        psg.write(0x3F);
+        assert_eq!(psg.tone(1), 0x3F5);
+        psg.write(0xC3);
+        assert_eq!(psg.tone(2), 0x003);
+        // Data bytes keep updating the latched register.
+        psg.write(0x01);
+        assert_eq!(psg.tone(2), 0x013);
+        psg.write(0x90);
+        psg.write(0xBF);
+        assert_eq!(psg.volume(0), 0x0);
+        assert_eq!(psg.volume(1), 0xF);
+        psg.write(0xD4);
+        psg.write(0x07);
+        assert_eq!(psg.volume(2), 0x7);
+        psg.write(0xE5);
+        assert_eq!(psg.noise(), 0x5);
+        psg.write(0xFA);
+        assert_eq!(psg.volume(3), 0xA);
+    }
+
+    #[test]
+    fn psg_square_wave() {
+        // One step per sample: each output sample is a single PSG step.
+        let mut psg = Psg::new(16, 1);
+        psg.write(0x84);
+        psg.write(0x90);
+        psg.tick(16 * 16);
+        let samples = psg.take_samples();
+        assert_eq!(samples.len(), 16);
+        let high: Vec<bool> = samples.iter().map(|&s| s > 0).collect();
+        assert_eq!(
+            high,
+            vec![
+                false, false, false, false, true, true, true, true, false, false, false, false,
+                true, true, true, true
+            ]
+        );
+        assert!(samples.iter().all(|&s| s.abs() == 8191));
+        assert!(psg.take_samples().is_empty());
+
+        // A period of one holds the output high.
+        psg.write(0x81);
+        psg.write(0x00);
+        psg.tick(16 * 8);
+        assert!(psg.take_samples().iter().all(|&s| s > 0));
+    }
+
+    #[test]
+    fn psg_noise() {
+        let mut psg = Psg::new(16, 1);
+        psg.write(0x9F);
+        psg.write(0xF0);
+        psg.write(0xE0);
+        // Periodic noise cycles the single set bit through all 16 places.
+        psg.tick(16 * 0x10 * 2 * 16);
+        let ones = psg.take_samples().iter().filter(|&&s| s > 0).count();
+        assert_eq!(ones, 0x10 * 2);
+    }
+
+    #[test]
+    fn psg_resampling() {
+        let mut psg = Psg::new(NTSC_CLOCK, 44_100);
+        psg.tick(NTSC_CLOCK);
+        let samples = psg.take_samples();
+        assert!((44_099..=44_100).contains(&samples.len()));
+
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x9A);
+        p.add_param(Opcode::OutVXA, 0x7F);
+        p.add(Opcode::Halt);
+        let mut vm = cartridge_machine(&p);
+        vm.set_sample_rate(22_050);
+        vm.start().unwrap();
+        assert_eq!(vm.psg().volume(0), 0xA);
+        vm.run_frame().unwrap();
+        let samples = vm.take_audio();
+        assert!((360..=370).contains(&samples.len()));
+    }
+
+    fn sign_changes(samples: &[i16]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count()
+    }
+
+    // A sustained user voice whose modulator is fully attenuated, keyed
+    // on at 440 Hz: F-number 290 in block 4.
+    fn sine_opll() -> Opll {
+        let mut opll = Opll::new(NTSC_CLOCK, 44_100);
+        for (register, &value) in [0x01, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x00, 0x0F]
+            .iter()
+            .enumerate()
+        {
+            opll.write_register(register as u8, value);
+        }
+        opll.write_register(0x30, 0x00);
+        opll.write_register(0x10, 0x22);
+        opll.write_register(0x20, 0x19);
+        opll
+    }
+
+    #[test]
+    fn opll_tone() {
+        let mut opll = sine_opll();
+        opll.tick(NTSC_CLOCK);
+        let samples = opll.take_samples();
+        assert!((44_099..=44_100).contains(&samples.len()));
+        assert!((870..=890).contains(&sign_changes(&samples)));
+        let peak = samples.iter().map(|&s| (s as i32).abs()).max().unwrap();
+        assert!(peak > 2000);
+
+        // Quieter at a lower volume, and silent once released.
+        opll.write_register(0x30, 0x04);
+        opll.tick(NTSC_CLOCK / 10);
+        let peak = opll
+            .take_samples()
+            .iter()
+            .map(|&s| (s as i32).abs())
+            .max()
+            .unwrap();
+        assert!(peak < 2000 / 3);
+        opll.write_register(0x20, 0x09);
+        opll.tick(NTSC_CLOCK / 2);
+        opll.take_samples();
+        opll.tick(NTSC_CLOCK / 10);
+        assert!(opll.take_samples().iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn opll_instruments_and_rhythm() {
+        let mut opll = Opll::new(NTSC_CLOCK, 44_100);
+        for instrument in 1..16 {
+            opll.write_register(0x30, instrument << 4);
+            opll.write_register(0x10, 0x22);
+            opll.write_register(0x20, 0x19);
+            opll.tick(NTSC_CLOCK / 20);
+            let samples = opll.take_samples();
+            assert!(samples.iter().any(|&s| s != 0), "instrument {}", instrument);
+            opll.write_register(0x20, 0x09);
+        }
+
+        let mut opll = Opll::new(NTSC_CLOCK, 44_100);
+        opll.write_register(0x16, 0x20);
+        opll.write_register(0x26, 0x05);
+        opll.write_register(0x36, 0x00);
+        opll.write_register(0x0E, 0x30);
+        assert!(opll.rhythm_mode());
+        opll.tick(NTSC_CLOCK / 20);
+        assert!(opll.take_samples().iter().any(|&s| s != 0));
+        opll.write_register(0x0E, 0x20);
+        opll.tick(NTSC_CLOCK * 2);
+        opll.take_samples();
+        opll.tick(NTSC_CLOCK / 20);
+        assert!(opll.take_samples().iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn fm_unit_detection_and_mixing() {
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x01);
+        p.add_param(Opcode::OutVXA, 0xF2);
+        p.add_param(Opcode::InAVX, 0xF2);
+        p.add(Opcode::Halt);
+        let mut rom = vec![0x00; 0x8000];
+        rom[..p.raw().len()].copy_from_slice(p.raw());
+
+        let config = MachineConfig {
+            fm_sound: true,
+            ..MachineConfig::default()
+        };
+        let mut vm = Machine::with_config(Cartridge::new(rom.clone()).unwrap(), config);
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.registers.a, 0x01);
+        assert_eq!(vm.opll().unwrap().audio_control(), 0x01);
+
+        // FM only: the idle FM unit is silent even with a loud PSG.
+        vm.psg_mut().write(0x90);
+        vm.run_frame().unwrap();
+        let samples = vm.take_audio();
+        assert!((730..=740).contains(&samples.len()));
+        assert!(samples.iter().all(|&s| s == 0));
+
+        let mut vm = Machine::with_cartridge(Cartridge::new(rom).unwrap());
+        vm.start().unwrap();
+        assert!(vm.opll().is_none());
+        assert_eq!(vm.cpu.state.registers.a, 0xFF);
+    }
+
+    fn joypad_machine() -> Machine {
+        Machine::with_cartridge(Cartridge::new(vec![0x00; 0x8000]).unwrap())
+    }
+
+    #[test]
+    fn joypad_ports() {
+        let mut vm = joypad_machine();
+        assert_eq!(vm.io.read(0xDC), 0xFF);
+        assert_eq!(vm.io.read(0xDD), 0xFF);
+        vm.set_input(
+            Player::One,
+            Buttons {
+                up: true,
+                button1: true,
+                ..Buttons::default()
+            },
+        );
+        vm.set_input(
+            Player::Two,
+            Buttons {
+                down: true,
+                left: true,
+                button2: true,
+                ..Buttons::default()
+            },
+        );
+        assert_eq!(vm.io.read(0xDC), 0x6E);
+        assert_eq!(vm.io.read(0xC0), 0x6E);
+        assert_eq!(vm.io.read(0xDD), 0xF6);
+        assert_eq!(vm.io.read(0xC1), 0xF6);
+        vm.set_reset_button(true);
+        assert_eq!(vm.io.read(0xDD), 0xE6);
+        assert!(vm.input(Player::Two).left);
+    }
+
+    #[test]
+    fn joypad_tr_output() {
+        let mut vm = joypad_machine();
+        vm.io.write(0x3F, 0xFE);
+        assert_eq!(vm.io.read(0xDC), 0xFF);
+        vm.io.write(0x3F, 0xEE);
+        assert_eq!(vm.io.read(0xDC), 0xDF);
+        vm.io.write(0x3F, 0xBB);
+        assert_eq!(vm.io.read(0xDD), 0xF7);
+    }
+
+    #[test]
+    fn joypad_region_detection() {
+        let mut vm = joypad_machine();
+        vm.io.write(0x3F, 0xF5);
+        assert_eq!(vm.io.read(0xDD) & 0xC0, 0xC0);
+        vm.io.write(0x3F, 0x55);
+        assert_eq!(vm.io.read(0xDD) & 0xC0, 0x00);
+
+        vm.set_region(Region::Japan);
+        vm.io.write(0x3F, 0xF5);
+        assert_eq!(vm.io.read(0xDD) & 0xC0, 0x00);
+        vm.io.write(0x3F, 0x55);
+        assert_eq!(vm.io.read(0xDD) & 0xC0, 0xC0);
+        vm.io.write(0x3F, 0xFF);
+        assert_eq!(vm.io.read(0xDD) & 0xC0, 0xC0);
+    }
+
+    #[test]
+    fn joypad_th_latches_h_counter() {
+        let mut vm = joypad_machine();
+        vm.io.write(0x3F, 0xDD);
+        vm.vdp_mut().tick(100);
+        assert_eq!(vm.vdp().h_counter(), 0);
+        vm.io.write(0x3F, 0xDD);
+        assert_eq!(vm.vdp().h_counter(), 0);
+        vm.io.write(0x3F, 0xFF);
+        assert_eq!(vm.vdp().h_counter(), timing::h_counter(100));
+        assert_eq!(vm.io.read(0x7F), timing::h_counter(100));
+    }
+
+    #[test]
+    fn joypad_read_by_program() {
+        let mut p = Program::new();
+        p.add_param(Opcode::InAVX, 0xDC);
+        p.add(Opcode::Halt);
+        let mut vm = cartridge_machine(&p);
+        vm.set_input(
+            Player::One,
+            Buttons {
+                right: true,
+                ..Buttons::default()
+            },
+        );
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.registers.a, 0xF7);
+    }
+
+    #[test]
+    fn pause_button_raises_nmi() {
+        let mut vm = cartridge_machine(&Program::new());
+        vm.press_pause();
+        vm.step().unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x0066);
+    }
+
+    #[test]
+    fn png_checksums() {
+        let mut crc = Crc32::new();
+        crc.update(b"IEND");
+        assert_eq!(crc.finish(), 0xAE42_6082);
+        assert_eq!(image::adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let stored = image::zlib_stored(&[0xAB; 70_000]);
+        assert_eq!(&stored[..7], &[0x78, 0x01, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        let second = 2 + 5 + 0xFFFF;
+        assert_eq!(stored[second], 0x01);
+        assert_eq!(stored.len(), 2 + 2 * 5 + 70_000 + 4);
+        assert_eq!(
+            image::zlib_stored(&[]),
+            vec![0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn screenshot_encoders() {
+        let pixels = [RED, GREEN, BLUE, WHITE];
+        let mut ppm = Vec::new();
+        image::write_ppm(&mut ppm, 2, 2, &pixels).unwrap();
+        assert_eq!(&ppm[..11], b"P6\n2 2\n255\n");
+        assert_eq!(&ppm[11..14], &[0xFF, 0x00, 0x00]);
+        assert_eq!(ppm.len(), 11 + 12);
+
+        let mut png = Vec::new();
+        image::write_png(&mut png, 2, 2, &pixels).unwrap();
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 2]);
+        assert_eq!(
+            &png[png.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+        // Two filtered scanlines of two RGB pixels.
+        let idat = &png[33 + 8..];
+        assert_eq!(&idat[..7], &[0x78, 0x01, 0x01, 14, 0, !14, 0xFF]);
+        assert_eq!(&idat[7..14], &[0, 0xFF, 0, 0, 0, 0xFF, 0]);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut out = Vec::new();
+        wav::write_wav(&mut out, 22_050, &[1, -2]).unwrap();
+        assert_eq!(out.len(), 44 + 4);
+        assert_eq!(&out[..4], b"RIFF");
+        assert_eq!(&out[4..8], &40u32.to_le_bytes());
+        assert_eq!(&out[24..28], &22_050u32.to_le_bytes());
+        assert_eq!(&out[28..32], &44_100u32.to_le_bytes());
+        assert_eq!(&out[40..44], &4u32.to_le_bytes());
+        assert_eq!(&out[44..], &[0x01, 0x00, 0xFE, 0xFF]);
+    }
+
+    #[test]
+    fn input_script() {
+        let script = InputScript::parse(
+            "# frame target buttons\n\n60 1 right+1\n10 2 up+left+2 # comment\n90 1 -\n90 reset\n",
+        )
+        .unwrap();
+        let events = script.events();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].frame, 10);
+        assert_eq!(
+            events[0].action,
+            Action::Pad(
+                Player::Two,
+                Buttons {
+                    up: true,
+                    left: true,
+                    button2: true,
+                    ..Buttons::default()
+                }
+            )
+        );
+        assert_eq!(events[3].action, Action::Reset);
+
+        let mut vm = joypad_machine();
+        script.apply(60, &mut vm);
+        assert_eq!(vm.io.read(0xDC), 0xE7);
+        script.apply(90, &mut vm);
+        assert_eq!(vm.io.read(0xDC), 0xFF);
+        assert_eq!(vm.io.read(0xDD) & 0x10, 0x00);
+        script.apply(91, &mut vm);
+        assert_eq!(vm.io.read(0xDD) & 0x10, 0x10);
+
+        let error = InputScript::parse("0 1 -\n5 1 jump\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            error.to_string(),
+            "input script line 2: unknown button `jump`"
+        );
+        assert!(InputScript::parse("x pause").is_err());
+        assert!(InputScript::parse("3 1").is_err());
+        assert!(InputScript::parse("3 3 up").is_err());
+    }
+
+    #[test]
+    fn cli_options() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        let options = Options::parse(args(
+            "game.sms --frames 120 --screenshot-at 30:a.png --screenshot-at 60:b.ppm --wav out.wav --region japan --pal --fm",
+        ))
+        .unwrap();
+        assert_eq!(options.rom.to_str(), Some("game.sms"));
+        assert_eq!(options.frames, 120);
+        assert_eq!(options.screenshots_at.len(), 2);
+        assert_eq!(options.screenshots_at[1].0, 60);
+        assert_eq!(
+            options.wav.as_ref().and_then(|p| p.to_str()),
+            Some("out.wav")
+        );
+        assert_eq!(options.config.region, Some(Region::Japan));
+        assert_eq!(options.config.tv_system, TvSystem::Pal);
+        assert!(options.config.fm_sound);
+        assert!(options.screenshot.is_none());
+
+        let defaults = Options::parse(args("game.sms")).unwrap();
+        assert_eq!(defaults.frames, 60);
+        assert_eq!(defaults.config, MachineConfig::default());
+
+        for &rate in &[8_000, 22_050, 192_000] {
+            let options = Options::parse(args(&format!("game.sms --sample-rate {}", rate)));
+            assert_eq!(options.unwrap().config.sample_rate, rate);
+        }
+
+        for bad in &[
+            "",
+            "game.sms --frames",
+            "game.sms --frames many",
+            "game.sms --screenshot-at 5",
+            "game.sms --screenshot-at 0:start.png",
+            "game.sms --sample-rate 0",
+            "game.sms --sample-rate 7999",
+            "game.sms --sample-rate 192001",
+            "game.sms --sample-rate 4294967295",
+            "game.sms other.sms",
+            "game.sms --turbo",
+        ] {
+            match Options::parse(args(bad).into_iter().filter(|arg| !arg.is_empty())) {
+                Err(CliError::Usage(_)) => {}
+                _ => panic!("accepted `{}`", bad),
+            }
+        }
+    }
+
+    // Enables the frame interrupt, keys an FM channel and then keeps
+    // feeding a counter to the PSG and VRAM.
+    fn state_test_machine(fm_sound: bool) -> Machine {
+        let mut rom = vec![0x00; 0x8000];
+        let setup = [
+            0xF3, 0xED, 0x56, 0x3E, 0x60, 0xD3, 0xBF, 0x3E, 0x81, 0xD3, 0xBF, 0x3E, 0x01, 0xD3,
+            0xF2, 0x3E, 0x10, 0xD3, 0xF0, 0x3E, 0xAA, 0xD3, 0xF1, 0x3E, 0x20, 0xD3, 0xF0, 0x3E,
+            0x1C, 0xD3, 0xF1, 0xFB, 0xC3, 0x40, 0x00,
+        ];
+        rom[..setup.len()].copy_from_slice(&setup);
+        rom[0x38..0x3C].copy_from_slice(&[0xDB, 0xBF, 0xFB, 0xC9]);
+        let main_loop = [
+            0x04, 0x78, 0xD3, 0x7F, 0xD3, 0xBE, 0xD9, 0x08, 0xC3, 0x40, 0x00,
+        ];
+        rom[0x40..0x40 + main_loop.len()].copy_from_slice(&main_loop);
+        let config = MachineConfig {
+            fm_sound,
+            ..MachineConfig::default()
+        };
+        Machine::with_config(Cartridge::new(rom).unwrap(), config)
+    }
+
+    fn run_frames(vm: &mut Machine, frames: usize) -> (Vec<u32>, Vec<i16>) {
+        let mut audio = Vec::new();
+        for _ in 0..frames {
+            vm.run_frame().unwrap();
+            audio.extend(vm.take_audio());
+        }
+        (vm.vdp().framebuffer().to_vec(), audio)
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut vm = Machine::new();
+        vm.cpu.state.registers.a = 0x12;
+        vm.cpu.state.alt_registers.h = 0x34;
+        vm.cpu.state.program_counter = 0x4321;
+        vm.cpu.state.ix = 0x1111;
+        vm.cpu.state.interrupt_mode = InterruptMode::Mode2;
+        vm.cpu.state.iff2 = true;
+        vm.cpu.halt();
+        vm.bus.write_u8(0x8000, 0x56);
+        let snapshot = vm.save_state();
+        assert_eq!(&snapshot[..4], &state::MAGIC);
+
+        vm.cpu.state.registers.a = 0x00;
+        vm.cpu.state.alt_registers.h = 0x00;
+        vm.cpu.state.program_counter = 0x0000;
+        vm.cpu.state.interrupt_mode = InterruptMode::Mode0;
+        vm.cpu.unhalt();
+        vm.bus.write_u8(0x8000, 0x00);
+        vm.load_state(&snapshot).unwrap();
+
+        assert_eq!(vm.cpu.state.registers.a, 0x12);
+        assert_eq!(vm.cpu.state.alt_registers.h, 0x34);
+        assert_eq!(vm.cpu.state.program_counter, 0x4321);
+        assert_eq!(vm.cpu.state.ix, 0x1111);
+        assert_eq!(vm.cpu.state.interrupt_mode, InterruptMode::Mode2);
+        assert!(vm.cpu.state.iff2);
+        assert!(vm.cpu.is_halted());
+        assert_eq!(vm.bus.read_u8(0x8000), 0x56);
+        assert_eq!(vm.save_state(), snapshot);
+    }
+
+    #[test]
+    fn save_state_resumes_identically() {
+        for &fm_sound in &[false, true] {
+            let mut vm = state_test_machine(fm_sound);
+            vm.set_input(
+                Player::One,
+                Buttons {
+                    up: true,
+                    ..Buttons::default()
+                },
+            );
+            run_frames(&mut vm, 3);
+            vm.run_for_cycles(1000).unwrap();
+            vm.take_audio();
+            let snapshot = vm.save_state();
+            let expected = run_frames(&mut vm, 2);
+            let cycles = vm.cycles();
+            assert!(!expected.1.is_empty());
+
+            let mut other = state_test_machine(fm_sound);
+            other.load_state(&snapshot).unwrap();
+            assert!(other.input(Player::One).up);
+            assert_eq!(run_frames(&mut other, 2), expected);
+            assert_eq!(other.cycles(), cycles);
+            assert_eq!(other.save_state(), vm.save_state());
+        }
+    }
+
+    #[test]
+    fn save_state_restores_cartridge() {
+        let rom = paged_rom(8);
+        let mut vm = Machine::with_cartridge(Cartridge::new(rom.clone()).unwrap());
+        vm.bus.write_u8(0xFFFC, 0x08);
+        vm.bus.write_u8(0x8000, 0x99);
+        vm.bus.write_u8(0xFFFF, 5);
+        vm.bus.write_u8(0xC123, 0x77);
+        let snapshot = vm.save_state();
+
+        let mut other = Machine::with_cartridge(Cartridge::new(rom).unwrap());
+        other.load_state(&snapshot).unwrap();
+        assert_eq!(other.bus.read_u8(0xC123), 0x77);
+        assert_eq!(other.bus.read_u8(0x8000), 0x99);
+        other.bus.write_u8(0xFFFC, 0x00);
+        assert_eq!(other.bus.read_u8(0x8000), 5);
+        let cartridge = other.cartridge().unwrap().borrow();
+        assert!(cartridge.has_save_data());
+        assert_eq!(cartridge.sram()[0], 0x99);
+    }
+
+    #[test]
+    fn load_state_rejects_bad_snapshots() {
+        let mut vm = state_test_machine(false);
+        run_frames(&mut vm, 1);
+        let snapshot = vm.save_state();
+        run_frames(&mut vm, 1);
+        let before = vm.save_state();
+
+        let mut bad_magic = snapshot.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(vm.load_state(&bad_magic), Err(StateError::BadMagic));
+        assert_eq!(vm.load_state(&[]), Err(StateError::BadMagic));
+
+        let mut old_version = snapshot.clone();
+        old_version[4..6].copy_from_slice(&(state::VERSION + 1).to_le_bytes());
+        assert_eq!(
+            vm.load_state(&old_version),
+            Err(StateError::UnsupportedVersion(state::VERSION + 1))
+        );
+
+        assert_eq!(
+            vm.load_state(&snapshot[..snapshot.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        let mut padded = snapshot.clone();
+        padded.push(0);
+        assert_eq!(vm.load_state(&padded), Err(StateError::TrailingData(1)));
+
+        let fm = state_test_machine(true).save_state();
+        assert_eq!(
+            vm.load_state(&fm),
+            Err(StateError::Mismatch("machine configuration"))
+        );
+        assert_eq!(
+            vm.load_state(&Machine::new().save_state()),
+            Err(StateError::Mismatch("machine configuration"))
+        );
+        let other_rom = cartridge_machine(&Program::new()).save_state();
+        assert_eq!(vm.load_state(&other_rom), Err(StateError::Mismatch("ROM")));
+
+        // A rejected snapshot leaves the machine untouched.
+        assert_eq!(vm.save_state(), before);
+        vm.load_state(&snapshot).unwrap();
+        assert_eq!(vm.save_state(), snapshot);
+    }
+
+    #[test]
+    fn rewind_delta_codec() {
+        let older: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut newer = older.clone();
+        newer[3] ^= 0xFF;
+        newer[500] = 0x00;
+        newer[501] = 0x42;
+        let runs = rewind::encode_delta(&older, &newer);
+        assert!(runs.len() < 20);
+        assert_eq!(rewind::decode_delta(&newer, &runs, older.len()), older);
+
+        let shorter = &older[..600];
+        let runs = rewind::encode_delta(shorter, &newer);
+        assert_eq!(rewind::decode_delta(&newer, &runs, 600), shorter);
+        let runs = rewind::encode_delta(&older, shorter);
+        assert_eq!(rewind::decode_delta(shorter, &runs, older.len()), older);
+        assert!(rewind::encode_delta(&older, &older).is_empty());
+    }
+
+    #[test]
+    fn rewind_buffer_depth() {
+        let mut buffer = RewindBuffer::new(RewindConfig {
+            interval: Period::Frames(2),
+            snapshots: 4,
+        });
+        assert!(buffer.is_due(0, 0));
+        let snapshot = |frame: u8| {
+            let mut snapshot = vec![0x55; 300];
+            snapshot[100] = frame;
+            snapshot
+        };
+        for frame in 0..10 {
+            buffer.push(snapshot(frame), frame as u64 * 2, 0);
+        }
+        assert_eq!(buffer.len(), 4);
+        assert!(!buffer.is_due(19, 0));
+        assert!(buffer.is_due(20, 0));
+        assert!(buffer.memory_usage() < 300 + 3 * 10);
+
+        assert_eq!(buffer.rewind(0), None);
+        assert_eq!(buffer.rewind(1), Some(&snapshot(9)[..]));
+        assert_eq!(buffer.rewind(2), Some(&snapshot(8)[..]));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.rewind(10), Some(&snapshot(6)[..]));
+        assert_eq!(buffer.len(), 1);
+        buffer.clear();
+        assert_eq!(buffer.rewind(1), None);
+    }
+
+    #[test]
+    fn machine_rewinds_frames() {
+        let mut vm = state_test_machine(true);
+        assert_eq!(vm.rewind(1), Ok(0));
+        vm.enable_rewind(RewindConfig {
+            interval: Period::Frames(1),
+            snapshots: 5,
+        });
+        let mut states = vec![vm.save_state()];
+        for _ in 0..6 {
+            vm.run_frame().unwrap();
+            vm.take_audio();
+            states.push(vm.save_state());
+        }
+        let buffer = vm.rewind_buffer().unwrap();
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.memory_usage() < 2 * states[0].len());
+
+        vm.run_for_cycles(5000).unwrap();
+        assert_eq!(vm.rewind(1), Ok(1));
+        assert_eq!(vm.save_state(), states[6]);
+        assert_eq!(vm.rewind(3), Ok(3));
+        assert_eq!(vm.save_state(), states[4]);
+
+        vm.run_frame().unwrap();
+        vm.take_audio();
+        assert_eq!(vm.save_state(), states[5]);
+        assert_eq!(vm.rewind(10), Ok(4));
+        assert_eq!(vm.save_state(), states[2]);
+    }
+
+    #[test]
+    fn machine_rewinds_by_cycles() {
+        let mut vm = state_test_machine(false);
+        vm.enable_rewind(RewindConfig {
+            interval: Period::Cycles(10_000),
+            snapshots: 100,
+        });
+        vm.run_for_cycles(100_000).unwrap();
+        let snapshots = vm.rewind_buffer().unwrap().len();
+        assert!((10..=11).contains(&snapshots));
+        assert_eq!(vm.rewind(100), Ok(snapshots));
+        assert!(vm.cycles() < 100);
+        vm.disable_rewind();
+        assert!(vm.rewind_buffer().is_none());
+    }
+
+    fn disassembly(bytes: &[u8]) -> Vec<String> {
+        disassembler::disassemble(bytes, 0x0000)
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn disassemble_program() {
+        let mut p = Program::new();
+        p.add_param(Opcode::LdAX, 0x05);
+        p.add_param_word(Opcode::LdVXXA, 0xC000);
+        p.add(Opcode::LdBHL);
+        p.add(Opcode::SbcC);
+        p.add_param(Opcode::CpX, 0x10);
+        p.add_param_word(Opcode::CallNZXX, 0x1234);
+        p.add(Opcode::PopAF);
+        p.add(Opcode::ExAFAF);
+        p.add_param(Opcode::OutVXA, 0xBF);
+        p.add(Opcode::Rst38);
+        p.add(Opcode::Halt);
+        let instructions = p.disassemble();
+        assert_eq!(
+            instructions
+                .iter()
+                .map(|instruction| instruction.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "LD A,0x05",
+                "LD (0xC000),A",
+                "LD B,(HL)",
+                "SBC A,C",
+                "CP 0x10",
+                "CALL NZ,0x1234",
+                "POP AF",
+                "EX AF,AF'",
+                "OUT (0xBF),A",
+                "RST 0x38",
+                "HALT",
+            ]
+        );
+        let call = &instructions[5];
+        assert_eq!(call.address, 0x0009);
+        assert_eq!(call.bytes, vec![0xC4, 0x34, 0x12]);
+        assert_eq!(call.length, 3);
+        assert_eq!(call.mnemonic, "CALL");
+        assert_eq!(
+            call.operands,
+            vec![Argument::Condition("NZ"), Argument::Word(0x1234)]
+        );
+    }
+
+    #[test]
+    fn disassemble_prefixed_pages() {
+        assert_eq!(
+            disassembly(&[
+                0xCB, 0x7E, 0xCB, 0x11, 0xCB, 0xF7, 0xED, 0x56, 0xED, 0x43, 0x34, 0x12, 0xED, 0x40,
+                0xED, 0x71, 0xED, 0xB0, 0xED, 0x5F,
+            ]),
+            vec![
+                "BIT 7,(HL)",
+                "RL C",
+                "SET 6,A",
+                "IM 1",
+                "LD (0x1234),BC",
+                "IN B,(C)",
+                "OUT (C),0",
+                "LDIR",
+                "LD A,R",
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_index_registers() {
+        assert_eq!(
+            disassembly(&[
+                0xDD, 0x21, 0x34, 0x12, 0xDD, 0x7E, 0xFE, 0xDD, 0x36, 0x05, 0xAA, 0xFD, 0x66, 0x01,
+                0xDD, 0x44, 0xFD, 0x29, 0xDD, 0xE9, 0xFD, 0xE3, 0xDD, 0xCB, 0x05, 0xC6, 0xDD, 0xCB,
+                0x05, 0xC0, 0xFD, 0xCB, 0x80, 0x46,
+            ]),
+            vec![
+                "LD IX,0x1234",
+                "LD A,(IX-0x02)",
+                "LD (IX+0x05),0xAA",
+                "LD H,(IY+0x01)",
+                "LD B,IXH",
+                "ADD IY,IY",
+                "JP (IX)",
+                "EX (SP),IY",
+                "SET 0,(IX+0x05)",
+                "SET 0,(IX+0x05),B",
+                "BIT 0,(IY-0x80)",
+            ]
+        );
+        // Prefixes with nothing to act on stand alone.
+        assert_eq!(
+            disassembly(&[0xDD, 0x00, 0xFD, 0xEB, 0xDD, 0xDD, 0x23]),
+            vec!["DB 0xDD", "NOP", "DB 0xFD", "EX DE,HL", "DB 0xDD", "INC IX"]
+        );
+    }
+
+    #[test]
+    fn disassemble_invalid_and_truncated() {
+        assert_eq!(
+            disassembly(&[0xED, 0x77, 0x01, 0x34]),
+            vec!["DB 0xED,0x77", "DB 0x01", "INC (HL)"]
+        );
+        assert_eq!(disassembly(&[0xDD, 0x7E]), vec!["DB 0xDD", "LD A,(HL)"]);
+        assert!(disassembly(&[]).is_empty());
+    }
+
+    #[test]
+    fn disassemble_memory() {
+        let mut memory = Memory::new();
+        memory.write_u8(0xFFFE, 0x3E);
+        memory.write_u8(0xFFFF, 0x42);
+        memory.write_u8(0x0000, 0xC3);
+        memory.write_u8(0x0001, 0xFE);
+        memory.write_u8(0x0002, 0xFF);
+        let instructions = disassembler::disassemble_bus(&memory, 0xFFFE, 3);
+        assert_eq!(instructions[0].to_string(), "LD A,0x42");
+        assert_eq!(instructions[1].address, 0x0000);
+        assert_eq!(instructions[1].to_string(), "JP 0xFFFE");
+        assert_eq!(instructions[2].address, 0x0003);
+        assert_eq!(
+            disassembler::listing(&instructions[..2]),
+            "FFFE  3E 42        LD A,0x42\n0000  C3 FE FF     JP 0xFFFE\n"
+        );
+    }
+
+    #[test]
+    fn assemble_round_trips_disassembly() {
+        let mut sequences = Vec::new();
+        for byte in 0..=0xFF {
+            sequences.push(vec![byte]);
+            for &prefix in &[0xCB, 0xDD, 0xED, 0xFD] {
+                sequences.push(vec![prefix, byte]);
+            }
+            sequences.push(vec![0xDD, 0xCB, 0x05, byte]);
+            sequences.push(vec![0xFD, 0xCB, 0xFB, byte]);
+        }
+        for mut bytes in sequences {
+            bytes.extend_from_slice(&[0x34, 0x12]);
+            let instruction = disassembler::disassemble(&bytes, 0x0000).remove(0);
+            if instruction.mnemonic == "DB" {
+                continue;
+            }
+            let text = instruction.to_string();
+            let program = Program::assemble(&format!("    {}", text))
+                .unwrap_or_else(|error| panic!("{}: {}", text, error));
+            let again = program.disassemble();
+            assert_eq!(again.len(), 1, "{}", text);
+            assert_eq!(again[0].to_string(), text);
+        }
+    }
+
+    #[test]
+    fn assemble_program() {
+        let source = "
+; Sums the table into A and stores it.
+COUNT   EQU end - table
+RESULT  EQU 0xC000
+
+        ORG 0x0000
+start:  LD HL,table
+        LD B,COUNT
+        XOR A
+loop:   ADD A,(HL)
+        INC HL
+        DEC B
+        JP NZ,loop
+        LD (RESULT),A
+        LD IX,words
+        LD C,(IX+1)
+        HALT
+table:  DB 1, 2, 3, 'A' - 60
+end:
+words   DW $, 0x1234
+        DS 2, 0xFF
+        DB \"ok\", 0
+";
+        let assembly = assembler::assemble(source).unwrap();
+        assert_eq!(assembly.origin, 0x0000);
+        assert_eq!(assembly.symbols["COUNT"], 4);
+        assert_eq!(assembly.symbols["table"], 0x0017);
+        assert_eq!(assembly.symbols["words"], 0x001B);
+        assert_eq!(
+            &assembly.program.raw()[..8],
+            &[0x21, 0x17, 0x00, 0x06, 0x04, 0xAF, 0x86, 0x23]
+        );
+        assert_eq!(
+            &assembly.program.raw()[0x17..],
+            &[1, 2, 3, 5, 0x1B, 0x00, 0x34, 0x12, 0xFF, 0xFF, b'o', b'k', 0]
+        );
+
+        let mut vm = Machine::new();
+        vm.load(&assembly.program);
+        vm.start().unwrap();
+        assert_eq!(vm.bus.read_u8(0xC000), 11);
+        assert_eq!(vm.cpu.state.registers.c, 0x00);
+
+        let moved =
+            assembler::assemble("  ORG 0x8000\nhere: JP here\n  ORG 0x8005\n  RST 0x38").unwrap();
+        assert_eq!(moved.origin, 0x8000);
+        assert_eq!(
+            moved.program.raw(),
+            &vec![0xC3, 0x00, 0x80, 0x00, 0x00, 0xFF]
+        );
+    }
+
+    #[test]
+    fn assemble_relative_jumps() {
+        let program =
+            Program::assemble("back: NOP\n  DJNZ back\n  JR NZ,ahead\n  JR C,$\nahead: JR back")
+                .unwrap();
+        assert_eq!(
+            program.raw(),
+            &vec![0x00, 0x10, 0xFD, 0x20, 0x02, 0x38, 0xFE, 0x18, 0xF7]
+        );
+
+        let error = Program::assemble("  JR target\n  DS 128\ntarget: NOP")
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (1, 6));
+        assert!(error.message.contains("out of range"), "{}", error);
+        assert!(Program::assemble("  JR target\n  DS 127\ntarget: NOP").is_ok());
+        let back = Program::assemble("target: DS 126\n  JR target").unwrap();
+        assert_eq!(&back.raw()[126..], &[0x18, 0x80]);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let error = |source: &str| {
+            let error: AssemblyError = Program::assemble(source).err().unwrap();
+            (error.line, error.column, error.message)
+        };
+        assert_eq!(
+            error("  NOP\n  FOO A"),
+            (2, 3, "unknown instruction `FOO`".to_string())
+        );
+        assert_eq!(
+            error("  LD A,missing"),
+            (1, 8, "undefined symbol `missing`".to_string())
+        );
+        assert_eq!(
+            error("x: NOP\nx: NOP"),
+            (2, 1, "`x` is already defined".to_string())
+        );
+        assert_eq!(
+            error("  LD A,300"),
+            (1, 8, "300 does not fit in a byte".to_string())
+        );
+        assert_eq!(
+            error("  LD (BC),B"),
+            (1, 11, "invalid operand for `LD`".to_string())
+        );
+        assert_eq!(
+            error("  NOP 1"),
+            (1, 3, "`NOP` does not take 1 operand".to_string())
+        );
+        assert_eq!(
+            error("  LD A,(IX+200)"),
+            (
+                1,
+                8,
+                "displacement 200 is out of range (-128 to 127)".to_string()
+            )
+        );
+        assert_eq!(error("  DB 1/0"), (1, 7, "division by zero".to_string()));
+        assert_eq!(
+            error("  DB \"open"),
+            (1, 6, "unterminated string".to_string())
+        );
+        assert_eq!(
+            error("  ORG later\nlater: NOP"),
+            (1, 7, "`later` must be defined before this line".to_string())
+        );
+        assert_eq!(
+            error("  ORG 0x10\n  NOP\n  ORG 0x08"),
+            (
+                3,
+                3,
+                "ORG 0x0008 is below the current address 0x0011".to_string()
+            )
+        );
+        assert_eq!(
+            error("  LD A,(1+"),
+            (1, 11, "expected an expression".to_string())
+        );
+        assert_eq!(error("a EQU b\nb EQU a").0, 1);
+        assert_eq!(
+            format!("{}", Program::assemble("  LD A,#").err().unwrap()),
+            "line 1, column 8: unexpected character `#`"
+        );
+    }
+
+    #[test]
+    fn program_labels() {
+        let mut p = Program::with_origin(0x8000);
+        let routine = p.label();
+        let done = p.label();
+        p.add_param_word(Opcode::LdSPXX, 0xA000);
+        p.add_param_label(Opcode::CallXX, routine).unwrap();
+        p.add_param_label(Opcode::JpXX, done).unwrap();
+        p.add(Opcode::Halt);
+        p.bind(routine).unwrap();
+        p.add_param(Opcode::LdAX, 0x42);
+        p.add(Opcode::Ret);
+        p.bind(done).unwrap();
+        p.add(Opcode::Halt);
+        assert_eq!(p.address(), 0x800E);
+        p.finish().unwrap();
+        assert_eq!(&p.raw()[3..9], &[0xCD, 0x0A, 0x80, 0xC3, 0x0D, 0x80]);
+
+        let mut vm = Machine::new();
+        assert!(vm.load(&p));
+        vm.start_at(p.origin()).unwrap();
+        assert_eq!(vm.cpu.state.registers.a, 0x42);
+        assert_eq!(vm.cpu.state.program_counter, 0x800E);
+
+        let mut p = Program::new();
+        let nowhere = p.label();
+        p.add_param_label(Opcode::JpXX, nowhere).unwrap();
+        assert_eq!(p.finish(), Err(ProgramError::UnboundLabel(nowhere)));
+        assert_eq!(
+            p.add_param_label(Opcode::LdHLXX, nowhere),
+            Err(ProgramError::NotAJump(Opcode::LdHLXX))
+        );
+        assert_eq!(
+            p.add_relative(Opcode::JpXX, nowhere),
+            Err(ProgramError::NotAJump(Opcode::JpXX))
+        );
+        p.bind(nowhere).unwrap();
+        assert_eq!(p.bind(nowhere), Err(ProgramError::AlreadyBound(nowhere)));
+        assert_eq!(p.finish(), Ok(()));
+
+        let mut other = Program::new();
+        let foreign = other.label();
+        other.label();
+        let mut p = Program::new();
+        p.label();
+        let stranger = other.label();
+        assert_eq!(p.bind(stranger), Err(ProgramError::UnknownLabel(stranger)));
+        assert_eq!(
+            p.add_param_label(Opcode::CallXX, stranger),
+            Err(ProgramError::UnknownLabel(stranger))
+        );
+        assert_eq!(p.bind(foreign), Err(ProgramError::UnknownLabel(foreign)));
+        assert!(p.raw().is_empty());
+    }
+
+    #[test]
+    fn program_relative_labels() {
+        let mut p = Program::new();
+        p.add(Opcode::XorA);
+        p.add_param(Opcode::LdBX, 0x02);
+        let top = p.label_here().unwrap();
+        p.add_relative(Opcode::DjnzX, top).unwrap();
+        let skip = p.label();
+        p.add_relative(Opcode::JrNZX, skip).unwrap();
+        p.add_relative(Opcode::JrZX, skip).unwrap();
+        p.add(Opcode::IncA);
+        p.bind(skip).unwrap();
+        p.add(Opcode::Halt);
+        p.finish().unwrap();
+        assert_eq!(
+            p.raw(),
+            &vec![0xAF, 0x06, 0x02, 0x10, 0xFE, 0x20, 0x03, 0x28, 0x01, 0x3C, 0x76]
+        );
+
+        // A failed finish leaves every reference unwritten.
+        let mut p = Program::new();
+        let far = p.label();
+        p.add_param_label(Opcode::JpXX, far).unwrap();
+        p.add_relative(Opcode::JrX, far).unwrap();
+        p.add_vector(vec![0x00; 0x80]);
+        p.bind(far).unwrap();
+        assert_eq!(
+            p.finish(),
+            Err(ProgramError::OutOfRange {
+                label: far,
+                offset: 0x80
+            })
+        );
+        assert_eq!(&p.raw()[..5], &[0xC3, 0x00, 0x00, 0x18, 0x00]);
+
+        // Jumps across 0xFFFF measure the short way round.
+        let mut p = Program::with_origin(0xFFFC);
+        let wrapped = p.label();
+        p.add(Opcode::Nop);
+        p.add(Opcode::Nop);
+        p.add_relative(Opcode::JrX, wrapped).unwrap();
+        p.bind(wrapped).unwrap();
+        p.add_relative(Opcode::JrX, wrapped).unwrap();
+        p.finish().unwrap();
+        assert_eq!(p.address(), 0x0002);
+        assert_eq!(&p.raw()[2..], &[0x18, 0x00, 0x18, 0xFE]);
+    }
+
+    #[test]
+    fn load_uses_program_origin() {
+        let mut p = Program::with_origin(0xC100);
+        p.add_param(Opcode::LdAX, 0x42);
+        let mut vm = Machine::new();
+        assert!(vm.load(&p));
+        assert_eq!(vm.bus.read_u8(0xC100), Opcode::LdAX as u8);
+        assert_eq!(vm.bus.read_u8(0xC101), 0x42);
+        assert_eq!(vm.bus.read_u8(0x0000), 0x00);
+
+        let mut p = Program::with_origin(0xFFFF);
+        p.add_vector(vec![0x00, 0x00]);
+        assert!(!vm.load(&p));
+    }
+
+    #[test]
+    fn assemble_keeps_origin() {
+        let program = Program::assemble(
+            "  ORG 0x8000\n  LD SP,0xDFF0\n  CALL store\n  HALT\nstore: LD A,0x42\n  LD (0xC000),A\n  RET",
+        )
+        .unwrap();
+        assert_eq!(program.origin(), 0x8000);
+        assert_eq!(program.disassemble()[2].address, 0x8006);
+
+        let mut vm = Machine::new();
+        assert!(vm.load(&program));
+        assert_eq!(vm.bus.read_u8(0x0000), 0x00);
+        vm.start_at(0x8000).unwrap();
+        assert_eq!(vm.bus.read_u8(0xC000), 0x42);
+        assert_eq!(vm.cpu.state.program_counter, 0x8007);
+    }
+
+    #[test]
+    fn rejected_snapshot_keeps_queued_audio() {
+        let mut vm = state_test_machine(true);
+        let mut twin = state_test_machine(true);
+        run_frames(&mut vm, 1);
+        run_frames(&mut twin, 1);
+        let snapshot = vm.save_state();
+        vm.run_for_cycles(20_000).unwrap();
+        twin.run_for_cycles(20_000).unwrap();
+
+        assert_eq!(
+            vm.load_state(&snapshot[..snapshot.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        let queued = vm.take_audio();
+        assert!(!queued.is_empty());
+        assert_eq!(queued, twin.take_audio());
+
+        vm.run_for_cycles(20_000).unwrap();
+        vm.load_state(&snapshot).unwrap();
+        assert!(vm.take_audio().is_empty());
+    }
+
+    #[test]
+    fn opll_state_rejects_bad_sample_rate() {
+        let mut out = StateWriter::new();
+        Opll::new(NTSC_CLOCK, 44_100).save_state(&mut out);
+        let mut snapshot = out.into_bytes();
+        // The rate is followed by the pending cycles and the resampler.
+        let at = snapshot.len() - 44;
+        let mut rate = [0; 8];
+        rate.copy_from_slice(&snapshot[at..at + 8]);
+        assert!(f64::from_le_bytes(rate) > 1000.0);
+
+        for &bad in &[f64::NAN, f64::INFINITY, 0.0, -49_716.0] {
+            snapshot[at..at + 8].copy_from_slice(&bad.to_le_bytes());
+            let mut opll = Opll::new(NTSC_CLOCK, 44_100);
+            assert_eq!(
+                opll.load_state(&mut StateReader::new(&snapshot)),
+                Err(StateError::Invalid("sample rate"))
+            );
+        }
+    }
+
+    #[test]
+    fn words_are_little_endian() {
+        let mut memory = Memory::new();
+        memory.write_u16(0x1000, 0x1234);
+        assert_eq!(memory.read_u8(0x1000), 0x34);
+        assert_eq!(memory.read_u8(0x1001), 0x12);
+        assert_eq!(memory.read_u16(0x1000), 0x1234);
+        memory.write_u16(0xFFFF, 0xABCD);
+        assert_eq!(memory.read_u8(0xFFFF), 0xCD);
+        assert_eq!(memory.read_u8(0x0000), 0xAB);
+        assert_eq!(memory.read_u16(0xFFFF), 0xABCD);
+
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdSPXX, 0x2000);
+        p.add_param_word(Opcode::LdBCXX, 0x1234);
+        p.add(Opcode::PushBC);
+        p.add(Opcode::PopDE);
+        p.add_param_word(Opcode::LdHLXX, 0x5678);
+        p.add_param_word(Opcode::LdVXXHL, 0x3000);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+        vm.start().unwrap();
+
+        assert_eq!(vm.bus.read_u8(0x1FFE), 0x34);
+        assert_eq!(vm.bus.read_u8(0x1FFF), 0x12);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.d, regs.e)), 0x1234);
+        assert_eq!(vm.bus.read_u8(0x3000), 0x78);
+        assert_eq!(vm.bus.read_u8(0x3001), 0x56);
+    }
+
+    #[test]
+    fn resampler_queue_is_bounded() {
+        // A second of output, or 4096 samples at the least.
+        let mut resampler = Resampler::new(1000, 8000);
+        resampler.push(1, 2000);
+        assert_eq!(resampler.len(), 8000);
+        resampler.push(2, 500);
+        assert_eq!(resampler.len(), 8000);
+
+        let samples = resampler.take_all();
+        assert!(samples[..4000].iter().all(|&sample| sample == 1));
+        assert!(samples[4000..].iter().all(|&sample| sample == 2));
+        resampler.push(3, 1);
+        assert_eq!(resampler.len(), 8);
+
+        let mut slow = Resampler::new(1, 1);
+        slow.push(0, 5000);
+        assert_eq!(slow.len(), 4096);
+    }
+
+    #[test]
+    fn logic_parity_flag() {
+        for value in 0..=0xFFu8 {
+            for &(opcode, operand) in &[
+                (Opcode::AndX, 0xFF),
+                (Opcode::OrX, 0x00),
+                (Opcode::XorX, 0x00),
+            ] {
+                let mut vm = Machine::new();
+                let mut p = Program::new();
+                p.add_param(Opcode::LdAX, value);
+                p.add_param(opcode, operand);
+                p.add(Opcode::Halt);
+                vm.load(&p);
+                vm.start().unwrap();
+
+                assert_eq!(vm.cpu.state.registers.a, value);
+                assert_eq!(
+                    Flag::ParityOverflow.get(&vm.cpu.state.status),
+                    value.count_ones() % 2 == 0,
+                    "{:?} with A = {:#04X}",
+                    opcode,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn base_page_additions() {
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0x4000);
+        p.add_param(Opcode::LdAX, 0x10);
+        p.add(Opcode::AddVHL);
+        p.add(Opcode::AdcVHL);
+        p.add(Opcode::SubVHL);
+        p.add(Opcode::SbcVHL);
+        p.add(Opcode::OrVHL);
+        p.add(Opcode::XorVHL);
+        p.add(Opcode::AndVHL);
+        p.add_param(Opcode::AddX, 0x30);
+        p.add_param(Opcode::AdcX, 0x01);
+        p.add_param(Opcode::SubX, 0x11);
+        p.add_param(Opcode::SbcX, 0x10);
+        p.add(Opcode::CpVHL);
+        p.add_param(Opcode::CpX, 0x20);
+        p.add_param(Opcode::LdBX, 0x10);
+        p.add(Opcode::CpB);
+        p.add(Opcode::IncVHL);
+        p.add(Opcode::DecVHL);
+        p.add(Opcode::DecVHL);
+        vm.load(&p);
+        vm.bus.write_u8(0x4000, 0x05);
+
+        let steps = [
+            (0x00, 10),
+            (0x10, 7),
+            (0x15, 7),
+            (0x1A, 7),
+            (0x15, 7),
+            (0x10, 7),
+            (0x15, 7),
+            (0x10, 7),
+            (0x00, 7),
+            (0x30, 7),
+            (0x31, 7),
+            (0x20, 7),
+            (0x10, 7),
+        ];
+        for (i, &(a, cycles)) in steps.iter().enumerate() {
+            assert_eq!(vm.step().unwrap(), cycles, "step {}", i);
+            assert_eq!(vm.cpu.state.registers.a, a, "step {}", i);
+        }
+        // CP leaves A alone and sets the flags of the subtraction.
+        assert_eq!(vm.step().unwrap(), 7);
+        assert!(!Flag::Carry.get(&vm.cpu.state.status));
+        assert_eq!(vm.step().unwrap(), 7);
+        assert!(Flag::Carry.get(&vm.cpu.state.status));
+        assert_eq!(vm.step().unwrap(), 7);
+        assert_eq!(vm.step().unwrap(), 4);
+        assert!(Flag::Zero.get(&vm.cpu.state.status));
+        assert_eq!(vm.cpu.state.registers.a, 0x10);
+
+        assert_eq!(vm.step().unwrap(), 11);
+        assert_eq!(vm.bus.read_u8(0x4000), 0x06);
+        assert_eq!(vm.step().unwrap(), 11);
+        assert_eq!(vm.step().unwrap(), 11);
+        assert_eq!(vm.bus.read_u8(0x4000), 0x04);
+        assert!(!Flag::Zero.get(&vm.cpu.state.status));
+
+        let mut vm = Machine::new();
+        let mut p = Program::new();
+        p.add_param_word(Opcode::LdHLXX, 0xD000);
+        p.add(Opcode::LdSPHL);
+        p.add_param_word(Opcode::LdHLXX, 0x0009);
+        p.add(Opcode::JpHL);
+        p.add(Opcode::Halt);
+        p.add(Opcode::Halt);
+        vm.load(&p);
+
+        assert_eq!(vm.step().unwrap(), 10);
+        assert_eq!(vm.step().unwrap(), 6);
+        assert_eq!(vm.cpu.get_register_pair(|regs| (regs.s, regs.p)), 0xD000);
+        assert_eq!(vm.step().unwrap(), 10);
+        assert_eq!(vm.step().unwrap(), 4);
+        assert_eq!(vm.cpu.state.program_counter, 0x0009);
+        vm.start_at(0x0009).unwrap();
+        assert_eq!(vm.cpu.state.program_counter, 0x000A);
+    }
+
+    #[test]
+    fn repeated_index_prefixes() {
+        // Each prefix of a run is its own step, so a long run does not nest.
+        let mut p = Program::new();
+        for _ in 0..0x4000 {
+            p.add(Opcode::PrefixDD);
+            p.add(Opcode::PrefixFD);
+        }
+        p.add_param_word(Opcode::LdHLXX, 0x1234);
+        p.add(Opcode::Halt);
+        let cycles = cycles_of(&p);
+        assert_eq!(cycles.len(), 0x8001);
+        assert!(cycles[..0x7FFF].iter().all(|&c| c == 4));
+        assert_eq!(cycles[0x7FFF..], [14, 4]);
+
+        let mut vm = Machine::new();
+        vm.load(&p);
+        vm.start().unwrap();
+        assert_eq!(vm.cpu.state.ix, 0xFFFF);
+        assert_eq!(vm.cpu.state.iy, 0x1234);
+
+        // A prefix in the last byte of memory reads itself as the next byte.
+        for &prefix in &[Opcode::PrefixDD, Opcode::PrefixFD] {
+            let mut vm = Machine::new();
+            vm.bus.write_u8(0xFFFF, prefix as u8);
+            vm.start_at(0xFFFF).unwrap();
+            assert!(vm.cpu.is_halted());
+            assert_eq!(vm.cpu.state.program_counter, 0xFFFF);
+            assert_eq!(vm.cycles(), 4);
+        }
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        let run = |a: u8, b: u8, opcode: Opcode| {
+            let mut vm = Machine::new();
+            let mut p = Program::new();
+            p.add(opcode);
+            p.add(Opcode::DAA);
+            p.add(Opcode::Halt);
+            vm.load(&p);
+            vm.cpu.state.registers.a = a;
+            vm.cpu.state.registers.b = b;
+            vm.start().unwrap();
+            let status = vm.cpu.state.status;
+            let flags = [
+                Flag::Sign,
+                Flag::Zero,
+                Flag::HalfCarry,
+                Flag::ParityOverflow,
+                Flag::AddSubtract,
+                Flag::Carry,
+            ]
+            .iter()
+            .map(|flag| flag.get(&status))
+            .collect::<Vec<_>>();
+            (vm.cpu.state.registers.a, flags)
+        };
+
+        // 15 + 27 = 42, with a half carry out of the low digit.
+        assert_eq!(
+            run(0x15, 0x27, Opcode::AddB),
+            (0x42, vec![false, false, true, true, false, false])
+        );
+        // 99 + 1 = 100, which leaves 00 and a carry.
+        assert_eq!(
+            run(0x99, 0x01, Opcode::AddB),
+            (0x00, vec![false, true, true, true, false, true])
+        );
+        // 42 - 15 = 27, borrowing from the high digit. N is kept.
+        assert_eq!(
+            run(0x42, 0x15, Opcode::SubB),
+            (0x27, vec![false, false, false, true, true, false])
+        );
+        // 10 - 20 = 90 with a borrow.
+        assert_eq!(
+            run(0x10, 0x20, Opcode::SubB),
+            (0x90, vec![true, false, false, true, true, true])
+        );
+        assert_eq!(
+            cycles_of(&Program::assemble("  DAA\n  HALT").unwrap()),
+            vec![4, 4]
+        );
+        assert_eq!(Program::assemble("  daa").unwrap().raw(), &[0x27]);
+    }
 }
@@ -0,0 +1,3 @@
+pub mod opll;
+pub mod psg;
+pub mod resampler;
@@ -0,0 +1,737 @@
+use std::f64::consts::PI;
+use vm::audio::resampler::Resampler;
+use vm::io::port::PortDevice;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// The OPLL computes one output sample every 72 input clocks.
+pub const CYCLES_PER_SAMPLE: u32 = 72;
+pub const CHANNEL_COUNT: usize = 9;
+
+pub const ADDRESS_PORT: u8 = 0xF0;
+pub const DATA_PORT: u8 = 0xF1;
+pub const CONTROL_PORT: u8 = 0xF2;
+
+const RHYTHM_CHANNEL: usize = 6;
+const RHYTHM_ENABLE: u8 = 0x20;
+const KEY_BASS_DRUM: u8 = 0x10;
+const KEY_SNARE_DRUM: u8 = 0x08;
+const KEY_TOM_TOM: u8 = 0x04;
+const KEY_CYMBAL: u8 = 0x02;
+const KEY_HI_HAT: u8 = 0x01;
+
+// Instrument 0 is the user patch from registers 0x00-0x07; 1-15 are the
+// built-in melodic voices and 16-18 the rhythm voices for channels 6-8.
+const PATCHES: [[u8; 8]; 19] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x71, 0x61, 0x1E, 0x17, 0xD0, 0x78, 0x00, 0x17],
+    [0x13, 0x41, 0x1A, 0x0D, 0xD8, 0xF7, 0x23, 0x13],
+    [0x13, 0x01, 0x99, 0x00, 0xF2, 0xC4, 0x11, 0x23],
+    [0x31, 0x61, 0x0E, 0x07, 0xA8, 0x64, 0x70, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE0, 0x76, 0x00, 0x28],
+    [0x31, 0x22, 0x16, 0x05, 0xE0, 0x71, 0x00, 0x18],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x10, 0x07],
+    [0x23, 0x21, 0x2D, 0x14, 0xA2, 0x72, 0x00, 0x07],
+    [0x61, 0x61, 0x1B, 0x06, 0x64, 0x65, 0x10, 0x17],
+    [0x41, 0x61, 0x0B, 0x18, 0x85, 0xF7, 0x71, 0x07],
+    [0x13, 0x01, 0x83, 0x11, 0xFA, 0xE4, 0x10, 0x04],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x61, 0x50, 0x0C, 0x05, 0xC2, 0xF5, 0x20, 0x42],
+    [0x01, 0x01, 0x55, 0x03, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x41, 0x89, 0x03, 0xF1, 0xE4, 0x40, 0x13],
+    [0x01, 0x01, 0x18, 0x0F, 0xDF, 0xF8, 0x6A, 0x6D],
+    [0x01, 0x01, 0x00, 0x00, 0xC8, 0xD8, 0xA7, 0x68],
+    [0x05, 0x01, 0x00, 0x00, 0xF8, 0xAA, 0x59, 0x55],
+];
+
+const MULTIPLIERS: [f64; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+// Key scale level at block 7, in dB, indexed by the top four F-number bits.
+const KEY_SCALE_LEVELS: [f64; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+    42.0,
+];
+const KEY_SCALE_FACTORS: [f64; 4] = [0.0, 0.25, 0.5, 1.0];
+
+const ENVELOPE_RANGE: f64 = 48.0;
+// Times for rate 1; each step up in rate halves them.
+const ATTACK_TIME: f64 = 2.826;
+const DECAY_TIME: f64 = 39.28;
+const DECAY_RANGE: f64 = 96.0;
+
+const TREMOLO_FREQUENCY: f64 = 3.7;
+const TREMOLO_DEPTH: f64 = 4.8;
+const VIBRATO_FREQUENCY: f64 = 6.4;
+const VIBRATO_DEPTH: f64 = 0.004;
+
+// Modulator output at full scale shifts the carrier by this many cycles.
+const MODULATION_DEPTH: f64 = 2.0;
+const CHANNEL_LEVEL: f64 = 2400.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: f64,
+    key_scale_level: u8,
+    rectified: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: f64,
+    release: u8,
+}
+
+impl OperatorPatch {
+    fn new(patch: &[u8; 8], carrier: bool) -> OperatorPatch {
+        let index = if carrier { 1 } else { 0 };
+        let flags = patch[index];
+        let (key_scale_level, rectified) = if carrier {
+            (patch[3] >> 6, patch[3] & 0x10 != 0)
+        } else {
+            (patch[2] >> 6, patch[3] & 0x08 != 0)
+        };
+        OperatorPatch {
+            tremolo: flags & 0x80 != 0,
+            vibrato: flags & 0x40 != 0,
+            sustained: flags & 0x20 != 0,
+            key_scale_rate: flags & 0x10 != 0,
+            multiplier: MULTIPLIERS[(flags & 0x0F) as usize],
+            key_scale_level,
+            rectified,
+            attack: patch[4 + index] >> 4,
+            decay: patch[4 + index] & 0x0F,
+            sustain_level: (patch[6 + index] >> 4) as f64 * 3.0,
+            release: patch[6 + index] & 0x0F,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Operator {
+    phase: f64,
+    state: EnvelopeState,
+    attenuation: f64,
+    release: u8,
+    history: [f64; 2],
+}
+
+impl Operator {
+    fn new() -> Operator {
+        Operator {
+            phase: 0.0,
+            state: EnvelopeState::Off,
+            attenuation: ENVELOPE_RANGE,
+            release: 0,
+            history: [0.0; 2],
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self, release: u8) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+            self.release = release;
+        }
+    }
+
+    fn update_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, sample_rate: f64) {
+        let rate = match self.state {
+            EnvelopeState::Attack => patch.attack,
+            EnvelopeState::Decay => patch.decay,
+            EnvelopeState::Sustain if patch.sustained => 0,
+            EnvelopeState::Sustain => patch.release,
+            EnvelopeState::Release => self.release,
+            EnvelopeState::Off => 0,
+        };
+        if rate == 0 {
+            return;
+        }
+        let offset = if patch.key_scale_rate {
+            key_scale
+        } else {
+            key_scale >> 2
+        };
+        let speed = 2f64.powf(((rate * 4 + offset).min(63) as f64) / 4.0 - 1.0);
+        match self.state {
+            EnvelopeState::Attack => {
+                if rate == 15 {
+                    self.attenuation = 0.0;
+                } else {
+                    self.attenuation -= ENVELOPE_RANGE * speed / (ATTACK_TIME * sample_rate);
+                }
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            _ => {
+                self.attenuation += DECAY_RANGE * speed / (DECAY_TIME * sample_rate);
+                if self.state == EnvelopeState::Decay && self.attenuation >= patch.sustain_level {
+                    self.attenuation = patch.sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+                if self.attenuation >= ENVELOPE_RANGE {
+                    self.attenuation = ENVELOPE_RANGE;
+                    self.state = EnvelopeState::Off;
+                }
+            }
+        }
+    }
+
+    fn gain(&self, patch: &OperatorPatch, base: f64, tremolo: f64) -> f64 {
+        if self.state == EnvelopeState::Off {
+            return 0.0;
+        }
+        let tremolo = if patch.tremolo { tremolo } else { 0.0 };
+        let attenuation = self.attenuation + base + tremolo;
+        if attenuation >= ENVELOPE_RANGE {
+            0.0
+        } else {
+            10f64.powf(-attenuation / 20.0)
+        }
+    }
+
+    fn output(&mut self, patch: &OperatorPatch, modulation: f64, gain: f64) -> f64 {
+        let wave = (2.0 * PI * (self.phase + modulation)).sin();
+        let wave = if patch.rectified && wave < 0.0 {
+            0.0
+        } else {
+            wave
+        };
+        let output = wave * gain;
+        self.history = [output, self.history[0]];
+        output
+    }
+
+    fn advance(&mut self, patch: &OperatorPatch, increment: f64, vibrato: f64) {
+        let vibrato = if patch.vibrato { vibrato } else { 1.0 };
+        self.phase = (self.phase + increment * patch.multiplier * vibrato).fract();
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Channel {
+    f_number: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            f_number: 0,
+            block: 0,
+            key: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+
+    // Phase advance per sample at a multiplier of one.
+    fn increment(&self) -> f64 {
+        ((self.f_number as u32) << self.block) as f64 / (1 << 19) as f64
+    }
+
+    fn key_scale(&self) -> u8 {
+        self.block * 2 + (self.f_number >> 8) as u8
+    }
+
+    fn key_scale_level(&self, patch: &OperatorPatch) -> f64 {
+        let octaves = 6.0 * (7 - self.block) as f64;
+        let level = (KEY_SCALE_LEVELS[(self.f_number >> 5) as usize] - octaves).max(0.0);
+        level * KEY_SCALE_FACTORS[patch.key_scale_level as usize]
+    }
+
+    // Sustained voices release at their own rate unless the channel's
+    // sustain bit asks for the slow one; percussive voices use rate 7.
+    fn release_rate(&self, patch: &OperatorPatch) -> u8 {
+        if self.sustain {
+            5
+        } else if patch.sustained {
+            patch.release
+        } else {
+            7
+        }
+    }
+}
+
+pub struct Opll {
+    address: u8,
+    control: u8,
+    user_patch: [u8; 8],
+    rhythm: u8,
+    channels: [Channel; CHANNEL_COUNT],
+    noise: u32,
+    lfo_time: f64,
+    sample_rate: f64,
+    pending_cycles: u32,
+    resampler: Resampler,
+}
+
+impl Opll {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Opll {
+        Opll {
+            address: 0x00,
+            control: 0x00,
+            user_patch: [0; 8],
+            rhythm: 0x00,
+            channels: [Channel::new(); CHANNEL_COUNT],
+            noise: 1,
+            lfo_time: 0.0,
+            sample_rate: clock_rate as f64 / CYCLES_PER_SAMPLE as f64,
+            pending_cycles: 0,
+            resampler: Resampler::new(clock_rate, sample_rate),
+        }
+    }
+
+    pub fn audio_control(&self) -> u8 {
+        self.control
+    }
+
+    pub fn rhythm_mode(&self) -> bool {
+        self.rhythm & RHYTHM_ENABLE != 0
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: u32) {
+        self.sample_rate = clock_rate as f64 / CYCLES_PER_SAMPLE as f64;
+        self.resampler.set_clock_rate(clock_rate);
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.resampler.len()
+    }
+
+    pub fn drain_samples(&mut self, count: usize) -> Vec<i16> {
+        self.resampler.drain(count)
+    }
+
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.resampler.take_all()
+    }
+
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        match register {
+            0x00..=0x07 => self.user_patch[register as usize] = value,
+            0x0E => self.write_rhythm(value),
+            0x10..=0x18 => {
+                let channel = &mut self.channels[(register - 0x10) as usize];
+                channel.f_number = (channel.f_number & 0x100) | value as u16;
+            }
+            0x20..=0x28 => self.write_key(register as usize - 0x20, value),
+            0x30..=0x38 => {
+                let channel = &mut self.channels[(register - 0x30) as usize];
+                channel.instrument = value >> 4;
+                channel.volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    fn write_key(&mut self, index: usize, value: u8) {
+        let key = value & 0x10 != 0;
+        let was_keyed = self.channels[index].key;
+        {
+            let channel = &mut self.channels[index];
+            channel.f_number = (channel.f_number & 0x0FF) | ((value as u16 & 0x01) << 8);
+            channel.block = (value >> 1) & 0x07;
+            channel.sustain = value & 0x20 != 0;
+            channel.key = key;
+        }
+        if index >= RHYTHM_CHANNEL && self.rhythm_mode() {
+            return;
+        }
+        let (modulator, carrier) = self.patch(index);
+        let channel = &mut self.channels[index];
+        if key && !was_keyed {
+            channel.modulator.key_on();
+            channel.carrier.key_on();
+        } else if !key && was_keyed {
+            let release = channel.release_rate(&modulator);
+            channel.modulator.key_off(release);
+            let release = channel.release_rate(&carrier);
+            channel.carrier.key_off(release);
+        }
+    }
+
+    fn write_rhythm(&mut self, value: u8) {
+        let previous = self.rhythm;
+        self.rhythm = value;
+        if value & RHYTHM_ENABLE == 0 {
+            return;
+        }
+        let pressed = value & !previous;
+        let released = !value & previous;
+        let keys: [(u8, usize, bool); 6] = [
+            (KEY_BASS_DRUM, 6, false),
+            (KEY_BASS_DRUM, 6, true),
+            (KEY_HI_HAT, 7, false),
+            (KEY_SNARE_DRUM, 7, true),
+            (KEY_TOM_TOM, 8, false),
+            (KEY_CYMBAL, 8, true),
+        ];
+        for &(bit, index, carrier) in keys.iter() {
+            let patch = OperatorPatch::new(&PATCHES[10 + index], carrier);
+            let channel = &mut self.channels[index];
+            let release = channel.release_rate(&patch);
+            let operator = if carrier {
+                &mut channel.carrier
+            } else {
+                &mut channel.modulator
+            };
+            if pressed & bit != 0 {
+                operator.key_on();
+            } else if released & bit != 0 {
+                operator.key_off(release);
+            }
+        }
+    }
+
+    fn patch_data(&self, index: usize) -> [u8; 8] {
+        if index >= RHYTHM_CHANNEL && self.rhythm_mode() {
+            PATCHES[10 + index]
+        } else if self.channels[index].instrument == 0 {
+            self.user_patch
+        } else {
+            PATCHES[self.channels[index].instrument as usize]
+        }
+    }
+
+    fn patch(&self, index: usize) -> (OperatorPatch, OperatorPatch) {
+        let patch = self.patch_data(index);
+        (
+            OperatorPatch::new(&patch, false),
+            OperatorPatch::new(&patch, true),
+        )
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.pending_cycles += cycles;
+        while self.pending_cycles >= CYCLES_PER_SAMPLE {
+            self.pending_cycles -= CYCLES_PER_SAMPLE;
+            let level = self.generate();
+            self.resampler.push(level, CYCLES_PER_SAMPLE);
+        }
+    }
+
+    fn generate(&mut self) -> i32 {
+        self.lfo_time += 1.0 / self.sample_rate;
+        let tremolo =
+            TREMOLO_DEPTH * (1.0 - (2.0 * PI * TREMOLO_FREQUENCY * self.lfo_time).cos()) / 2.0;
+        let vibrato = 1.0 + VIBRATO_DEPTH * (2.0 * PI * VIBRATO_FREQUENCY * self.lfo_time).sin();
+        self.step_noise();
+
+        let melodic = if self.rhythm_mode() {
+            RHYTHM_CHANNEL
+        } else {
+            CHANNEL_COUNT
+        };
+        let mut output = 0.0;
+        for index in 0..melodic {
+            output += self.melodic_output(index, tremolo, vibrato);
+        }
+        if self.rhythm_mode() {
+            output += self.rhythm_output(tremolo, vibrato);
+        }
+        (output * CHANNEL_LEVEL) as i32
+    }
+
+    // 23-bit noise generator shared by the hi-hat, snare and cymbal.
+    fn step_noise(&mut self) {
+        if self.noise & 0x01 != 0 {
+            self.noise ^= 0x0080_0302;
+        }
+        self.noise >>= 1;
+    }
+
+    fn melodic_output(&mut self, index: usize, tremolo: f64, vibrato: f64) -> f64 {
+        let (modulator_patch, carrier_patch) = self.patch(index);
+        let patch = self.patch_data(index);
+        let channel = &mut self.channels[index];
+        let total_level = (patch[2] & 0x3F) as f64 * 0.75;
+        let feedback = patch[3] & 0x07;
+        two_operator_output(
+            channel,
+            &modulator_patch,
+            &carrier_patch,
+            total_level,
+            feedback,
+            channel.volume as f64 * 3.0,
+            self.sample_rate,
+            tremolo,
+            vibrato,
+        )
+    }
+
+    // Bass drum is an ordinary two operator voice at double level; the
+    // other four drums each play from a single operator of channels 7 and
+    // 8, with their volumes taken from the channel volume nibbles.
+    fn rhythm_output(&mut self, tremolo: f64, vibrato: f64) -> f64 {
+        let noise = self.noise & 0x01 != 0;
+        let sample_rate = self.sample_rate;
+
+        let (modulator_patch, carrier_patch) = self.patch(6);
+        let patch = self.patch_data(6);
+        let bass_volume = self.channels[6].volume as f64 * 3.0;
+        let bass = 2.0
+            * two_operator_output(
+                &mut self.channels[6],
+                &modulator_patch,
+                &carrier_patch,
+                (patch[2] & 0x3F) as f64 * 0.75,
+                patch[3] & 0x07,
+                bass_volume,
+                sample_rate,
+                tremolo,
+                vibrato,
+            );
+
+        let (hi_hat_patch, snare_patch) = self.patch(7);
+        let (tom_patch, cymbal_patch) = self.patch(8);
+        let hi_hat_volume = self.channels[7].instrument as f64 * 3.0;
+        let snare_volume = self.channels[7].volume as f64 * 3.0;
+        let tom_volume = self.channels[8].instrument as f64 * 3.0;
+        let cymbal_volume = self.channels[8].volume as f64 * 3.0;
+
+        let hi_hat_phase = self.channels[7].modulator.phase;
+        let cymbal_phase = self.channels[8].carrier.phase;
+        let square = |phase: f64| phase >= 0.5;
+        let polarity = |high: bool| if high { 1.0 } else { -1.0 };
+        let metallic = square(hi_hat_phase) ^ square(cymbal_phase * 2.0 % 1.0);
+
+        let mut output = bass;
+        {
+            let channel = &mut self.channels[7];
+            let increment = channel.increment();
+            let key_scale = channel.key_scale();
+            let level = channel.key_scale_level(&hi_hat_patch) + hi_hat_volume;
+            channel
+                .modulator
+                .update_envelope(&hi_hat_patch, key_scale, sample_rate);
+            let gain = channel.modulator.gain(&hi_hat_patch, level, tremolo);
+            output += polarity(metallic ^ noise) * gain;
+            channel.modulator.advance(&hi_hat_patch, increment, vibrato);
+
+            let level = channel.key_scale_level(&snare_patch) + snare_volume;
+            channel
+                .carrier
+                .update_envelope(&snare_patch, key_scale, sample_rate);
+            let gain = channel.carrier.gain(&snare_patch, level, tremolo);
+            output += polarity(square(channel.carrier.phase) ^ noise) * gain;
+            channel.carrier.advance(&snare_patch, increment, vibrato);
+        }
+        {
+            let channel = &mut self.channels[8];
+            let increment = channel.increment();
+            let key_scale = channel.key_scale();
+            let level = channel.key_scale_level(&tom_patch) + tom_volume;
+            channel
+                .modulator
+                .update_envelope(&tom_patch, key_scale, sample_rate);
+            let gain = channel.modulator.gain(&tom_patch, level, tremolo);
+            output += channel.modulator.output(&tom_patch, 0.0, gain);
+            channel.modulator.advance(&tom_patch, increment, vibrato);
+
+            let level = channel.key_scale_level(&cymbal_patch) + cymbal_volume;
+            channel
+                .carrier
+                .update_envelope(&cymbal_patch, key_scale, sample_rate);
+            let gain = channel.carrier.gain(&cymbal_patch, level, tremolo);
+            output += polarity(metallic) * gain;
+            channel.carrier.advance(&cymbal_patch, increment, vibrato);
+        }
+        output
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn two_operator_output(
+    channel: &mut Channel,
+    modulator_patch: &OperatorPatch,
+    carrier_patch: &OperatorPatch,
+    total_level: f64,
+    feedback: u8,
+    volume: f64,
+    sample_rate: f64,
+    tremolo: f64,
+    vibrato: f64,
+) -> f64 {
+    let increment = channel.increment();
+    let key_scale = channel.key_scale();
+    let modulator_level = channel.key_scale_level(modulator_patch) + total_level;
+    let carrier_level = channel.key_scale_level(carrier_patch) + volume;
+
+    channel
+        .modulator
+        .update_envelope(modulator_patch, key_scale, sample_rate);
+    channel
+        .carrier
+        .update_envelope(carrier_patch, key_scale, sample_rate);
+
+    // Feedback 1-7 feeds back the mean of the last two outputs, scaled
+    // from pi/16 up to 4pi radians.
+    let feedback = if feedback == 0 {
+        0.0
+    } else {
+        let history = channel.modulator.history;
+        (history[0] + history[1]) / 2.0 * (1 << (feedback - 1)) as f64 / 32.0
+    };
+    let gain = channel
+        .modulator
+        .gain(modulator_patch, modulator_level, tremolo);
+    let modulation = channel.modulator.output(modulator_patch, feedback, gain);
+    let gain = channel.carrier.gain(carrier_patch, carrier_level, tremolo);
+    let output = channel
+        .carrier
+        .output(carrier_patch, modulation * MODULATION_DEPTH, gain);
+
+    channel
+        .modulator
+        .advance(modulator_patch, increment, vibrato);
+    channel.carrier.advance(carrier_patch, increment, vibrato);
+    output
+}
+
+// Port 0xF0 selects a register and 0xF1 writes it. Port 0xF2 is the audio
+// control register games use to detect the unit: it reads back the last
+// value written.
+impl PortDevice for Opll {
+    fn read(&mut self, port: u8) -> u8 {
+        if port == CONTROL_PORT {
+            self.control & 0x07
+        } else {
+            0xFF
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        match port {
+            ADDRESS_PORT => self.address = value,
+            DATA_PORT => {
+                let address = self.address;
+                self.write_register(address, value);
+            }
+            CONTROL_PORT => self.control = value & 0x07,
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for Operator {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_f64(self.phase);
+        out.write_u8(match self.state {
+            EnvelopeState::Attack => 0,
+            EnvelopeState::Decay => 1,
+            EnvelopeState::Sustain => 2,
+            EnvelopeState::Release => 3,
+            EnvelopeState::Off => 4,
+        });
+        out.write_f64(self.attenuation);
+        out.write_u8(self.release);
+        out.write_f64(self.history[0]);
+        out.write_f64(self.history[1]);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.phase = input.read_f64()?;
+        self.state = match input.read_u8()? {
+            0 => EnvelopeState::Attack,
+            1 => EnvelopeState::Decay,
+            2 => EnvelopeState::Sustain,
+            3 => EnvelopeState::Release,
+            4 => EnvelopeState::Off,
+            _ => return Err(StateError::Invalid("envelope state")),
+        };
+        self.attenuation = input.read_f64()?;
+        self.release = input.read_u8()? & 0x0F;
+        self.history = [input.read_f64()?, input.read_f64()?];
+        Ok(())
+    }
+}
+
+impl Snapshot for Channel {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u16(self.f_number);
+        out.write_u8(self.block);
+        out.write_bool(self.key);
+        out.write_bool(self.sustain);
+        out.write_u8(self.instrument);
+        out.write_u8(self.volume);
+        self.modulator.save_state(out);
+        self.carrier.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.f_number = input.read_u16()? & 0x01FF;
+        self.block = input.read_u8()? & 0x07;
+        self.key = input.read_bool()?;
+        self.sustain = input.read_bool()?;
+        self.instrument = input.read_u8()? & 0x0F;
+        self.volume = input.read_u8()? & 0x0F;
+        self.modulator.load_state(input)?;
+        self.carrier.load_state(input)
+    }
+}
+
+impl Snapshot for Opll {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u8(self.address);
+        out.write_u8(self.control);
+        out.write_bytes(&self.user_patch);
+        out.write_u8(self.rhythm);
+        for channel in &self.channels {
+            channel.save_state(out);
+        }
+        out.write_u32(self.noise);
+        out.write_f64(self.lfo_time);
+        out.write_f64(self.sample_rate);
+        out.write_u32(self.pending_cycles);
+        self.resampler.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.address = input.read_u8()?;
+        self.control = input.read_u8()?;
+        input.read_into(&mut self.user_patch)?;
+        self.rhythm = input.read_u8()?;
+        for channel in self.channels.iter_mut() {
+            channel.load_state(input)?;
+        }
+        self.noise = input.read_u32()?;
+        self.lfo_time = input.read_f64()?;
+        self.sample_rate = input.read_f64()?;
+        if !self.sample_rate.is_finite() || self.sample_rate <= 0.0 {
+            return Err(StateError::Invalid("sample rate"));
+        }
+        self.pending_cycles = input.read_u32()?;
+        self.resampler.load_state(input)
+    }
+}
@@ -0,0 +1,232 @@
+use vm::audio::resampler::Resampler;
+use vm::io::port::PortDevice;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const NTSC_CLOCK: u32 = 3_579_545;
+pub const PAL_CLOCK: u32 = 3_546_893;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+// The chip divides its input clock by 16 before driving the counters.
+const CYCLES_PER_STEP: u32 = 16;
+const NOISE_CHANNEL: usize = 3;
+const LFSR_RESET: u16 = 0x8000;
+const WHITE_NOISE: u8 = 0x04;
+
+// 2 dB per attenuation step, scaled so four channels at full volume
+// cannot clip.
+const VOLUME_TABLE: [i16; 16] = [
+    8191, 6506, 5168, 4105, 3261, 2590, 2057, 1634, 1298, 1031, 819, 651, 517, 411, 326, 0,
+];
+
+pub struct Psg {
+    tones: [u16; 3],
+    volumes: [u8; 4],
+    noise: u8,
+    // Register selected by the last latch byte: channel * 2, plus one for
+    // the attenuation register.
+    latched: usize,
+    counters: [u16; 4],
+    outputs: [bool; 4],
+    lfsr: u16,
+    pending_cycles: u32,
+    resampler: Resampler,
+}
+
+impl Psg {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Psg {
+        Psg {
+            tones: [0; 3],
+            volumes: [0x0F; 4],
+            noise: 0x00,
+            latched: 0,
+            counters: [0; 4],
+            outputs: [true; 4],
+            lfsr: LFSR_RESET,
+            pending_cycles: 0,
+            resampler: Resampler::new(clock_rate, sample_rate),
+        }
+    }
+
+    pub fn tone(&self, channel: usize) -> u16 {
+        self.tones[channel]
+    }
+
+    pub fn volume(&self, channel: usize) -> u8 {
+        self.volumes[channel]
+    }
+
+    pub fn noise(&self) -> u8 {
+        self.noise
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.resampler.sample_rate()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: u32) {
+        self.resampler.set_clock_rate(clock_rate);
+    }
+
+    // Latch bytes (bit 7 set) select a register and carry its low four
+    // bits; data bytes update the latched register, supplying the upper
+    // six bits of a tone period.
+    pub fn write(&mut self, value: u8) {
+        if value & 0x80 != 0 {
+            self.latched = ((value >> 4) & 0x07) as usize;
+            self.write_register(value & 0x0F, false);
+        } else {
+            self.write_register(value & 0x3F, true);
+        }
+    }
+
+    fn write_register(&mut self, data: u8, high: bool) {
+        let channel = self.latched / 2;
+        if self.latched % 2 == 1 {
+            self.volumes[channel] = data & 0x0F;
+        } else if channel == NOISE_CHANNEL {
+            self.noise = data & 0x07;
+            self.lfsr = LFSR_RESET;
+        } else if high {
+            self.tones[channel] = (self.tones[channel] & 0x000F) | (data as u16) << 4;
+        } else {
+            self.tones[channel] = (self.tones[channel] & 0x03F0) | data as u16;
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.pending_cycles += cycles;
+        while self.pending_cycles >= CYCLES_PER_STEP {
+            self.pending_cycles -= CYCLES_PER_STEP;
+            self.step();
+        }
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.resampler.len()
+    }
+
+    pub fn drain_samples(&mut self, count: usize) -> Vec<i16> {
+        self.resampler.drain(count)
+    }
+
+    // Drains the samples produced so far.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.resampler.take_all()
+    }
+
+    fn step(&mut self) {
+        for channel in 0..NOISE_CHANNEL {
+            if self.tick_counter(channel, self.tones[channel]) {
+                self.outputs[channel] = !self.outputs[channel];
+            }
+        }
+        let period = match self.noise & 0x03 {
+            0 => 0x10,
+            1 => 0x20,
+            2 => 0x40,
+            _ => self.tones[2],
+        };
+        if self.tick_counter(NOISE_CHANNEL, period) {
+            self.outputs[NOISE_CHANNEL] = !self.outputs[NOISE_CHANNEL];
+            if self.outputs[NOISE_CHANNEL] {
+                self.shift_lfsr();
+            }
+        }
+
+        let level = self.level() as i32;
+        self.resampler.push(level, CYCLES_PER_STEP);
+    }
+
+    fn tick_counter(&mut self, channel: usize, period: u16) -> bool {
+        if self.counters[channel] > 0 {
+            self.counters[channel] -= 1;
+        }
+        if self.counters[channel] == 0 {
+            self.counters[channel] = period;
+            true
+        } else {
+            false
+        }
+    }
+
+    // The SMS variant taps bits 0 and 3 for white noise.
+    fn shift_lfsr(&mut self) {
+        let feedback = if self.noise & WHITE_NOISE != 0 {
+            (self.lfsr ^ (self.lfsr >> 3)) & 0x0001
+        } else {
+            self.lfsr & 0x0001
+        };
+        self.lfsr = (self.lfsr >> 1) | (feedback << 15);
+    }
+
+    // Periods of 0 and 1 hold a tone channel high, which games rely on to
+    // play samples through the volume registers.
+    fn level(&self) -> i16 {
+        let mut level = 0;
+        for channel in 0..4 {
+            let high = if channel == NOISE_CHANNEL {
+                self.lfsr & 0x0001 != 0
+            } else {
+                self.tones[channel] <= 1 || self.outputs[channel]
+            };
+            let volume = VOLUME_TABLE[self.volumes[channel] as usize];
+            level += if high { volume } else { -volume };
+        }
+        level
+    }
+}
+
+impl PortDevice for Psg {
+    fn read(&mut self, _port: u8) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _port: u8, value: u8) {
+        Psg::write(self, value);
+    }
+}
+
+impl Snapshot for Psg {
+    fn save_state(&self, out: &mut StateWriter) {
+        for &tone in &self.tones {
+            out.write_u16(tone);
+        }
+        out.write_bytes(&self.volumes);
+        out.write_u8(self.noise);
+        out.write_u8(self.latched as u8);
+        for &counter in &self.counters {
+            out.write_u16(counter);
+        }
+        for &output in &self.outputs {
+            out.write_bool(output);
+        }
+        out.write_u16(self.lfsr);
+        out.write_u32(self.pending_cycles);
+        self.resampler.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        for tone in self.tones.iter_mut() {
+            *tone = input.read_u16()?;
+        }
+        input.read_into(&mut self.volumes)?;
+        self.noise = input.read_u8()?;
+        self.latched = input.read_u8()? as usize;
+        if self.latched >= 8 {
+            return Err(StateError::Invalid("PSG register"));
+        }
+        for counter in self.counters.iter_mut() {
+            *counter = input.read_u16()?;
+        }
+        for output in self.outputs.iter_mut() {
+            *output = input.read_bool()?;
+        }
+        self.lfsr = input.read_u16()?;
+        self.pending_cycles = input.read_u32()?;
+        self.resampler.load_state(input)
+    }
+}
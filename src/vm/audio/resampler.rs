@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// Converts a level held for a number of input clocks into PCM at the output
+// rate. Each sample is the mean level since the previous one, which is
+// crude but keeps the highest tones from aliasing too badly. Sources fed
+// the same clock emit samples on the same cycle boundaries, so their
+// outputs can be mixed sample for sample.
+//
+// At most a second of samples, or MIN_QUEUED at low rates, is queued; past
+// that the oldest are dropped, so a host that never drains the queue does
+// not grow it without bound.
+const MIN_QUEUED: usize = 4096;
+
+pub struct Resampler {
+    clock_rate: u32,
+    sample_rate: u32,
+    phase: u64,
+    accumulator: i64,
+    weight: u64,
+    samples: VecDeque<i16>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Resampler {
+        Resampler {
+            clock_rate,
+            sample_rate,
+            phase: 0,
+            accumulator: 0,
+            weight: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.phase = 0;
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: u32) {
+        self.clock_rate = clock_rate;
+        self.phase = 0;
+    }
+
+    pub fn push(&mut self, level: i32, cycles: u32) {
+        self.accumulator += level as i64 * cycles as i64;
+        self.weight += cycles as u64;
+        self.phase += cycles as u64 * self.sample_rate as u64;
+        while self.phase >= self.clock_rate as u64 {
+            self.phase -= self.clock_rate as u64;
+            self.emit();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn drain(&mut self, count: usize) -> Vec<i16> {
+        let count = count.min(self.samples.len());
+        self.samples.drain(..count).collect()
+    }
+
+    pub fn take_all(&mut self) -> Vec<i16> {
+        self.samples.drain(..).collect()
+    }
+
+    fn capacity(&self) -> usize {
+        (self.sample_rate as usize).max(MIN_QUEUED)
+    }
+
+    fn emit(&mut self) {
+        let sample = if self.weight > 0 {
+            let mean = self.accumulator / self.weight as i64;
+            mean.max(i16::MIN as i64).min(i16::MAX as i64) as i16
+        } else {
+            self.samples.back().cloned().unwrap_or(0)
+        };
+        if self.samples.len() >= self.capacity() {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.accumulator = 0;
+        self.weight = 0;
+    }
+}
+
+// Samples already produced belong to the host, so they are left out and
+// loading leaves the queue alone. Machine::load_state empties it once a
+// snapshot has loaded; rewinding would otherwise replay audio that has been
+// played.
+impl Snapshot for Resampler {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.clock_rate);
+        out.write_u32(self.sample_rate);
+        out.write_u64(self.phase);
+        out.write_u64(self.accumulator as u64);
+        out.write_u64(self.weight);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.clock_rate = input.read_u32()?;
+        self.sample_rate = input.read_u32()?;
+        if self.clock_rate == 0 {
+            return Err(StateError::Invalid("clock rate"));
+        }
+        self.phase = input.read_u64()?;
+        self.accumulator = input.read_u64()? as i64;
+        self.weight = input.read_u64()?;
+        Ok(())
+    }
+}
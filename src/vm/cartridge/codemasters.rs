@@ -0,0 +1,49 @@
+use vm::cartridge::mapper::{Mapper, Mapping, PAGE_SIZE};
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// Codemasters boards decode a bank register at the start of each slot and
+// have no unpaged area, so slot 0 can be switched out entirely.
+pub struct CodemastersMapper {
+    slots: [u8; 3],
+}
+
+impl CodemastersMapper {
+    pub fn new() -> CodemastersMapper {
+        CodemastersMapper { slots: [0, 1, 0] }
+    }
+
+    pub fn slot(&self, slot: usize) -> u8 {
+        self.slots[slot]
+    }
+}
+
+impl Mapper for CodemastersMapper {
+    fn map(&self, address: u16) -> Mapping {
+        if address < 0xC000 {
+            let slot = address as usize / PAGE_SIZE;
+            let offset = address as usize % PAGE_SIZE;
+            Mapping::Rom(self.slots[slot] as usize * PAGE_SIZE + offset)
+        } else {
+            Mapping::Unmapped
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 => self.slots[0] = value,
+            0x4000 => self.slots[1] = value,
+            0x8000 => self.slots[2] = value,
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for CodemastersMapper {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_bytes(&self.slots);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        input.read_into(&mut self.slots)
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+pub const SIGNATURE: &[u8; 8] = b"TMR SEGA";
+pub const HEADER_SIZE: usize = 16;
+
+// Checked in the same order as the export BIOS.
+const HEADER_OFFSETS: [usize; 3] = [0x7FF0, 0x3FF0, 0x1FF0];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegionCode {
+    SmsJapan,
+    SmsExport,
+    GameGearJapan,
+    GameGearExport,
+    GameGearInternational,
+    Unknown(u8),
+}
+
+impl RegionCode {
+    pub fn from_bits(bits: u8) -> RegionCode {
+        match bits {
+            0x3 => RegionCode::SmsJapan,
+            0x4 => RegionCode::SmsExport,
+            0x5 => RegionCode::GameGearJapan,
+            0x6 => RegionCode::GameGearExport,
+            0x7 => RegionCode::GameGearInternational,
+            _ => RegionCode::Unknown(bits),
+        }
+    }
+
+    pub fn is_japanese(self) -> bool {
+        self == RegionCode::SmsJapan || self == RegionCode::GameGearJapan
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RomHeader {
+    pub offset: usize,
+    pub checksum: u16,
+    pub product_code: u32,
+    pub version: u8,
+    pub region: RegionCode,
+    pub size_code: u8,
+}
+
+impl RomHeader {
+    pub fn parse(rom: &[u8]) -> Option<RomHeader> {
+        HEADER_OFFSETS
+            .iter()
+            .filter(|&&offset| rom.len() >= offset + HEADER_SIZE)
+            .find(|&&offset| &rom[offset..offset + SIGNATURE.len()] == SIGNATURE)
+            .map(|&offset| RomHeader::decode(offset, &rom[offset..offset + HEADER_SIZE]))
+    }
+
+    fn decode(offset: usize, header: &[u8]) -> RomHeader {
+        let bcd = |value: u8| (value >> 4) as u32 * 10 + (value & 0x0F) as u32;
+        RomHeader {
+            offset,
+            checksum: (header[0xB] as u16) << 8 | header[0xA] as u16,
+            product_code: (header[0xE] >> 4) as u32 * 10000
+                + bcd(header[0xD]) * 100
+                + bcd(header[0xC]),
+            version: header[0xE] & 0x0F,
+            region: RegionCode::from_bits(header[0xF] >> 4),
+            size_code: header[0xF] & 0x0F,
+        }
+    }
+
+    pub fn declared_size(&self) -> Option<usize> {
+        match self.size_code {
+            0xA => Some(0x2000),
+            0xB => Some(0x4000),
+            0xC => Some(0x8000),
+            0xD => Some(0xC000),
+            0xE => Some(0x10000),
+            0xF => Some(0x20000),
+            0x0 => Some(0x40000),
+            0x1 => Some(0x80000),
+            0x2 => Some(0x100000),
+            _ => None,
+        }
+    }
+
+    // Sums every byte of the declared range except the header itself, or
+    // returns None when the image is shorter than it claims to be.
+    pub fn compute_checksum(&self, rom: &[u8]) -> Option<u16> {
+        let size = self.declared_size()?;
+        if rom.len() < size {
+            return None;
+        }
+        let header = self.offset..self.offset + HEADER_SIZE;
+        Some(
+            rom[..size]
+                .iter()
+                .enumerate()
+                .filter(|&(address, _)| !header.contains(&address))
+                .fold(0u16, |sum, (_, &value)| sum.wrapping_add(value as u16)),
+        )
+    }
+
+    pub fn verify(&self, rom: &[u8]) -> bool {
+        self.compute_checksum(rom) == Some(self.checksum)
+    }
+
+    pub fn check(&self, rom: &[u8]) -> Vec<RomWarning> {
+        let mut warnings = Vec::new();
+        match self.declared_size() {
+            None => warnings.push(RomWarning::UnknownSize(self.size_code)),
+            Some(size) if rom.len() < size => warnings.push(RomWarning::Truncated {
+                declared: size,
+                actual: rom.len(),
+            }),
+            Some(_) => {}
+        }
+        if let Some(computed) = self.compute_checksum(rom) {
+            if computed != self.checksum {
+                warnings.push(RomWarning::ChecksumMismatch {
+                    declared: self.checksum,
+                    computed,
+                });
+            }
+        }
+        warnings
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RomWarning {
+    MissingHeader,
+    UnknownSize(u8),
+    Truncated { declared: usize, actual: usize },
+    ChecksumMismatch { declared: u16, computed: u16 },
+}
+
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomWarning::MissingHeader => write!(f, "no TMR SEGA header found"),
+            RomWarning::UnknownSize(code) => write!(f, "unknown ROM size code {:#X}", code),
+            RomWarning::Truncated { declared, actual } => write!(
+                f,
+                "header declares {} bytes but the image has {}",
+                declared, actual
+            ),
+            RomWarning::ChecksumMismatch { declared, computed } => write!(
+                f,
+                "checksum mismatch: header says {:#06X}, image sums to {:#06X}",
+                declared, computed
+            ),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use vm::cartridge::mapper::{Mapper, Mapping, PAGE_SIZE};
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const BANK_REGISTER: u16 = 0xA000;
+
+// Boards used by many Korean releases: the first 32 KiB is fixed and a
+// single register at 0xA000 pages slot 2.
+pub struct KoreanMapper {
+    slot: u8,
+}
+
+impl KoreanMapper {
+    pub fn new() -> KoreanMapper {
+        KoreanMapper { slot: 2 }
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+}
+
+impl Mapper for KoreanMapper {
+    fn map(&self, address: u16) -> Mapping {
+        match address {
+            0x0000..=0x7FFF => Mapping::Rom(address as usize),
+            0x8000..=0xBFFF => {
+                Mapping::Rom(self.slot as usize * PAGE_SIZE + (address as usize - 0x8000))
+            }
+            _ => Mapping::Unmapped,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address == BANK_REGISTER {
+            self.slot = value;
+        }
+    }
+}
+
+impl Snapshot for KoreanMapper {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u8(self.slot);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.slot = input.read_u8()?;
+        Ok(())
+    }
+}
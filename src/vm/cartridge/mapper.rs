@@ -0,0 +1,78 @@
+use vm::cartridge::codemasters::CodemastersMapper;
+use vm::cartridge::header::RomHeader;
+use vm::cartridge::korean::KoreanMapper;
+use vm::cartridge::sega::SegaMapper;
+use vm::state::Snapshot;
+
+pub const PAGE_SIZE: usize = 0x4000;
+
+// Codemasters images carry their own header at 0x7FE0, with a checksum
+// word followed by its two's complement.
+const CODEMASTERS_CHECKSUM: usize = 0x7FE6;
+
+// LD (0xA000),A and LD (0xFFFF),A: the bank switch idioms of Korean and
+// Sega boards respectively.
+const KOREAN_BANK_WRITE: [u8; 3] = [0x32, 0x00, 0xA0];
+const SEGA_BANK_WRITE: [u8; 3] = [0x32, 0xFF, 0xFF];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mapping {
+    Rom(usize),
+    Sram(usize),
+    Unmapped,
+}
+
+// Translates CPU addresses below 0xC000 into the cartridge's ROM and SRAM,
+// and latches whatever bank registers the board decodes from writes.
+pub trait Mapper: Snapshot {
+    fn map(&self, address: u16) -> Mapping;
+    fn write(&mut self, address: u16, value: u8);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MapperKind {
+    Sega,
+    Codemasters,
+    Korean,
+}
+
+impl MapperKind {
+    // A header whose checksum verifies is a strong hint that the image was
+    // built for Sega hardware, so the opcode count only breaks ties for
+    // headerless or mastered-elsewhere images.
+    pub fn detect(rom: &[u8], header: Option<&RomHeader>) -> MapperKind {
+        if has_codemasters_checksum(rom) {
+            MapperKind::Codemasters
+        } else if header.is_some_and(|header| header.verify(rom)) {
+            MapperKind::Sega
+        } else if count(rom, &KOREAN_BANK_WRITE) > count(rom, &SEGA_BANK_WRITE) {
+            MapperKind::Korean
+        } else {
+            MapperKind::Sega
+        }
+    }
+
+    pub fn create(self) -> Box<dyn Mapper> {
+        match self {
+            MapperKind::Sega => Box::new(SegaMapper::new()),
+            MapperKind::Codemasters => Box::new(CodemastersMapper::new()),
+            MapperKind::Korean => Box::new(KoreanMapper::new()),
+        }
+    }
+}
+
+fn has_codemasters_checksum(rom: &[u8]) -> bool {
+    if rom.len() < CODEMASTERS_CHECKSUM + 4 {
+        return false;
+    }
+    let word = |at: usize| (rom[at + 1] as u16) << 8 | rom[at] as u16;
+    let checksum = word(CODEMASTERS_CHECKSUM);
+    let inverse = word(CODEMASTERS_CHECKSUM + 2);
+    checksum != 0x0000 && checksum.wrapping_add(inverse) == 0x0000
+}
+
+fn count(rom: &[u8], pattern: &[u8]) -> usize {
+    rom.windows(pattern.len())
+        .filter(|window| *window == pattern)
+        .count()
+}
@@ -0,0 +1,176 @@
+pub mod codemasters;
+pub mod header;
+pub mod korean;
+pub mod mapper;
+pub mod sega;
+
+use std::error::Error;
+use std::fmt;
+use vm::cartridge::header::{RomHeader, RomWarning};
+use vm::cartridge::mapper::{Mapper, MapperKind, Mapping, PAGE_SIZE};
+use vm::ram::bus::Bus;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const MAX_ROM_SIZE: usize = 4 * 1024 * 1024;
+pub const SRAM_SIZE: usize = 2 * PAGE_SIZE;
+
+// Some dumps carry the 512 byte header left behind by copier hardware.
+const COPIER_HEADER_SIZE: usize = 512;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    Empty,
+    TooLarge(usize),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CartridgeError::Empty => write!(f, "ROM image is empty"),
+            CartridgeError::TooLarge(size) => write!(
+                f,
+                "ROM image is {} bytes, the limit is {}",
+                size, MAX_ROM_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for CartridgeError {}
+
+pub struct Cartridge {
+    rom: Vec<u8>,
+    sram: Vec<u8>,
+    sram_used: bool,
+    header: Option<RomHeader>,
+    // Identifies the image in save states.
+    fingerprint: u32,
+    kind: MapperKind,
+    mapper: Box<dyn Mapper>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        Cartridge::load(rom, None)
+    }
+
+    // Bypasses detection for the images the heuristic gets wrong.
+    pub fn with_mapper(rom: Vec<u8>, kind: MapperKind) -> Result<Cartridge, CartridgeError> {
+        Cartridge::load(rom, Some(kind))
+    }
+
+    fn load(mut rom: Vec<u8>, kind: Option<MapperKind>) -> Result<Cartridge, CartridgeError> {
+        if rom.len() % PAGE_SIZE == COPIER_HEADER_SIZE {
+            rom.drain(..COPIER_HEADER_SIZE);
+        }
+        if rom.is_empty() {
+            return Err(CartridgeError::Empty);
+        }
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge(rom.len()));
+        }
+        let header = RomHeader::parse(&rom);
+        let kind = kind.unwrap_or_else(|| MapperKind::detect(&rom, header.as_ref()));
+        Ok(Cartridge {
+            fingerprint: fingerprint(&rom),
+            rom,
+            sram: vec![0; SRAM_SIZE],
+            sram_used: false,
+            header,
+            kind,
+            mapper: kind.create(),
+        })
+    }
+
+    pub fn header(&self) -> Option<&RomHeader> {
+        self.header.as_ref()
+    }
+
+    // Problems worth reporting to the user; an empty list means the image
+    // looks like a good dump.
+    pub fn warnings(&self) -> Vec<RomWarning> {
+        match self.header {
+            Some(ref header) => header.check(&self.rom),
+            None => vec![RomWarning::MissingHeader],
+        }
+    }
+
+    pub fn mapper_kind(&self) -> MapperKind {
+        self.kind
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.rom.len().div_ceil(PAGE_SIZE)
+    }
+
+    // True once the game has paged the battery-backed RAM in, which is the
+    // only reliable sign that there is anything worth persisting.
+    pub fn has_save_data(&self) -> bool {
+        self.sram_used
+    }
+
+    pub fn sram(&self) -> &[u8] {
+        &self.sram
+    }
+
+    pub fn load_sram(&mut self, data: &[u8]) {
+        let length = data.len().min(SRAM_SIZE);
+        self.sram[..length].copy_from_slice(&data[..length]);
+        self.sram_used = true;
+    }
+}
+
+// 32-bit FNV-1a.
+fn fingerprint(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811C_9DC5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl Bus for Cartridge {
+    fn read_u8(&self, address: u16) -> u8 {
+        match self.mapper.map(address) {
+            // Bank numbers wrap around the image like the unconnected upper
+            // address lines do on a real board.
+            Mapping::Rom(offset) => self.rom[offset % self.rom.len()],
+            Mapping::Sram(offset) => self.sram[offset],
+            Mapping::Unmapped => 0xFF,
+        }
+    }
+
+    fn write_u8(&mut self, address: u16, value: u8) {
+        if let Mapping::Sram(offset) = self.mapper.map(address) {
+            self.sram[offset] = value;
+            self.sram_used = true;
+        }
+        self.mapper.write(address, value);
+    }
+}
+
+// Only what the game can change is saved; a snapshot refuses to load into
+// a cartridge holding a different image or mapper.
+impl Snapshot for Cartridge {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.fingerprint);
+        out.write_u8(self.kind as u8);
+        out.write_bytes(&self.sram);
+        out.write_bool(self.sram_used);
+        self.mapper.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        if input.read_u32()? != self.fingerprint {
+            return Err(StateError::Mismatch("ROM"));
+        }
+        if input.read_u8()? != self.kind as u8 {
+            return Err(StateError::Mismatch("mapper"));
+        }
+        input.read_into(&mut self.sram)?;
+        self.sram_used = input.read_bool()?;
+        self.mapper.load_state(input)
+    }
+}
@@ -0,0 +1,76 @@
+use vm::cartridge::mapper::{Mapper, Mapping, PAGE_SIZE};
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const CONTROL_REGISTER: u16 = 0xFFFC;
+
+const SRAM_BANK: u8 = 0x04;
+const SRAM_ENABLE: u8 = 0x08;
+
+// The standard Sega 315-5235 mapper. Its registers sit at the top of the
+// work RAM mirror, so writes there land in RAM as well. The bank shift bits
+// of the control register were never used by a released game and are
+// ignored.
+pub struct SegaMapper {
+    control: u8,
+    slots: [u8; 3],
+}
+
+impl SegaMapper {
+    pub fn new() -> SegaMapper {
+        SegaMapper {
+            control: 0x00,
+            slots: [0, 1, 2],
+        }
+    }
+
+    pub fn slot(&self, slot: usize) -> u8 {
+        self.slots[slot]
+    }
+
+    pub fn sram_enabled(&self) -> bool {
+        self.control & SRAM_ENABLE != 0
+    }
+
+    fn page(&self, slot: usize, address: u16) -> Mapping {
+        let offset = address as usize % PAGE_SIZE;
+        Mapping::Rom(self.slots[slot] as usize * PAGE_SIZE + offset)
+    }
+}
+
+impl Mapper for SegaMapper {
+    fn map(&self, address: u16) -> Mapping {
+        match address {
+            // The first 1 KiB always comes from page 0 so the interrupt
+            // vectors survive slot 0 being remapped.
+            0x0000..=0x03FF => Mapping::Rom(address as usize),
+            0x0400..=0x3FFF => self.page(0, address),
+            0x4000..=0x7FFF => self.page(1, address),
+            0x8000..=0xBFFF if self.sram_enabled() => {
+                let bank = if self.control & SRAM_BANK != 0 { 1 } else { 0 };
+                Mapping::Sram(bank * PAGE_SIZE + (address as usize - 0x8000))
+            }
+            0x8000..=0xBFFF => self.page(2, address),
+            _ => Mapping::Unmapped,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            CONTROL_REGISTER => self.control = value,
+            0xFFFD..=0xFFFF => self.slots[(address - 0xFFFD) as usize] = value,
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for SegaMapper {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u8(self.control);
+        out.write_bytes(&self.slots);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.control = input.read_u8()?;
+        input.read_into(&mut self.slots)
+    }
+}
@@ -34,6 +34,10 @@ pub(crate) fn get_bit<T: Zero + One>(value: bool) -> T {
     }
 }
 
+pub(crate) fn parity(value: u8) -> bool {
+    value.count_ones() & 1 == 0
+}
+
 pub(crate) fn negate<T: Add<Output = T> + Not<Output = T> + One>(value: T) -> T {
     !value + num::one()
 }
@@ -54,9 +58,9 @@ pub(crate) fn add_octets(a: u8, b: u8) -> AdderResult<u8> {
     };
     AdderResult {
         value: result,
-        half_carry: half_carry,
-        carry: carry,
-        overflow: overflow,
+        half_carry,
+        carry,
+        overflow,
     }
 }
 
@@ -87,6 +91,52 @@ pub(crate) fn add_words(a: u16, b: u16) -> AdderResult<u16> {
         value: result,
         half_carry: high.half_carry,
         carry: high.carry,
-        overflow: overflow,
+        overflow,
+    }
+}
+
+pub(crate) fn add_octets_with_carry(a: u8, b: u8, carry: bool) -> AdderResult<u8> {
+    let carry_in = get_bit::<u16>(carry);
+    let result = a as u16 + b as u16 + carry_in;
+    let value = result as u8;
+    AdderResult {
+        value,
+        half_carry: (a & 0x0F) as u16 + (b & 0x0F) as u16 + carry_in > 0x0F,
+        carry: result > 0xFF,
+        overflow: !(a ^ b) & (a ^ value) & 0x80 != 0,
+    }
+}
+
+pub(crate) fn subtract_octets_with_carry(a: u8, b: u8, borrow: bool) -> AdderResult<u8> {
+    let borrow_in = get_bit::<u16>(borrow);
+    let value = (a as u16).wrapping_sub(b as u16).wrapping_sub(borrow_in) as u8;
+    AdderResult {
+        value,
+        half_carry: ((a & 0x0F) as u16) < (b & 0x0F) as u16 + borrow_in,
+        carry: (a as u16) < b as u16 + borrow_in,
+        overflow: (a ^ b) & (a ^ value) & 0x80 != 0,
+    }
+}
+
+pub(crate) fn add_words_with_carry(a: u16, b: u16, carry: bool) -> AdderResult<u16> {
+    let carry_in = get_bit::<u32>(carry);
+    let result = a as u32 + b as u32 + carry_in;
+    let value = result as u16;
+    AdderResult {
+        value,
+        half_carry: (a & 0x0FFF) as u32 + (b & 0x0FFF) as u32 + carry_in > 0x0FFF,
+        carry: result > 0xFFFF,
+        overflow: !(a ^ b) & (a ^ value) & 0x8000 != 0,
+    }
+}
+
+pub(crate) fn subtract_words_with_carry(a: u16, b: u16, borrow: bool) -> AdderResult<u16> {
+    let borrow_in = get_bit::<u32>(borrow);
+    let value = (a as u32).wrapping_sub(b as u32).wrapping_sub(borrow_in) as u16;
+    AdderResult {
+        value,
+        half_carry: ((a & 0x0FFF) as u32) < (b & 0x0FFF) as u32 + borrow_in,
+        carry: (a as u32) < b as u32 + borrow_in,
+        overflow: (a ^ b) & (a ^ value) & 0x8000 != 0,
     }
 }
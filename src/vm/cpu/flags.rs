@@ -48,9 +48,8 @@ impl Flag {
     pub(crate) fn set_values(status: &mut u8, affected: &[Flag], values: &[(Flag, bool)]) {
         let map: HashMap<Flag, bool> = values.iter().cloned().collect();
         for flag in affected {
-            match map.get(&flag) {
-                Some(value) => flag.set(status, *value),
-                None => {}
+            if let Some(value) = map.get(flag) {
+                flag.set(status, *value);
             }
         }
     }
@@ -1,17 +1,26 @@
 use vm::cpu::alu;
 use vm::cpu::registers::Registers;
 use vm::cpu::state::State;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
 
 pub struct Processor {
     pub state: State,
     halted: bool,
+    irq: bool,
+    nmi: bool,
+    interrupt_data: u8,
+    interrupt_delay: bool,
 }
 
 impl Processor {
     pub fn new() -> Processor {
         Processor {
             state: State::new(),
-            halted: true,
+            halted: false,
+            irq: false,
+            nmi: false,
+            interrupt_data: 0xFF,
+            interrupt_delay: false,
         }
     }
 
@@ -37,4 +46,63 @@ impl Processor {
         let (high, low) = selector(&self.state.registers);
         alu::get_word(high, low)
     }
+
+    pub fn irq_asserted(&self) -> bool {
+        self.irq
+    }
+    pub fn raise_irq(&mut self) {
+        self.irq = true;
+    }
+    pub fn clear_irq(&mut self) {
+        self.irq = false;
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi
+    }
+    pub fn raise_nmi(&mut self) {
+        self.nmi = true;
+    }
+    pub(crate) fn take_nmi(&mut self) -> bool {
+        let pending = self.nmi;
+        self.nmi = false;
+        pending
+    }
+
+    pub fn interrupt_data(&self) -> u8 {
+        self.interrupt_data
+    }
+    pub fn set_interrupt_data(&mut self, value: u8) {
+        self.interrupt_data = value;
+    }
+
+    pub(crate) fn delay_interrupts(&mut self) {
+        self.interrupt_delay = true;
+    }
+    pub(crate) fn take_interrupt_delay(&mut self) -> bool {
+        let delayed = self.interrupt_delay;
+        self.interrupt_delay = false;
+        delayed
+    }
+}
+
+impl Snapshot for Processor {
+    fn save_state(&self, out: &mut StateWriter) {
+        self.state.save_state(out);
+        out.write_bool(self.halted);
+        out.write_bool(self.irq);
+        out.write_bool(self.nmi);
+        out.write_u8(self.interrupt_data);
+        out.write_bool(self.interrupt_delay);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.state.load_state(input)?;
+        self.halted = input.read_bool()?;
+        self.irq = input.read_bool()?;
+        self.nmi = input.read_bool()?;
+        self.interrupt_data = input.read_u8()?;
+        self.interrupt_delay = input.read_bool()?;
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 use vm::cpu::alu;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
 
 pub struct Registers {
     pub a: u8,
@@ -62,3 +63,30 @@ impl Registers {
         alu::get_word(*high, *low)
     }
 }
+
+impl Snapshot for Registers {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_bytes(&[
+            self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, self.s, self.p,
+        ]);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        let mut bytes = [0; 10];
+        input.read_into(&mut bytes)?;
+        let [a, b, c, d, e, f, h, l, s, p] = bytes;
+        *self = Registers {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f,
+            h,
+            l,
+            s,
+            p,
+        };
+        Ok(())
+    }
+}
@@ -1,10 +1,25 @@
 use vm::cpu::registers::Registers;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    Mode0,
+    Mode1,
+    Mode2,
+}
 
 pub struct State {
     pub registers: Registers,
     pub alt_registers: Registers,
     pub program_counter: u16,
     pub status: u8,
+    pub ix: u16,
+    pub iy: u16,
+    pub interrupt_vector: u8,
+    pub memory_refresh: u8,
+    pub interrupt_mode: InterruptMode,
+    pub iff1: bool,
+    pub iff2: bool,
 }
 
 impl State {
@@ -14,6 +29,58 @@ impl State {
             alt_registers: Registers::new(),
             program_counter: 0,
             status: 0,
+            ix: 0xFFFF,
+            iy: 0xFFFF,
+            interrupt_vector: 0,
+            memory_refresh: 0,
+            interrupt_mode: InterruptMode::Mode0,
+            iff1: false,
+            iff2: false,
         }
     }
+
+    pub(crate) fn refresh(&mut self) {
+        let counter = self.memory_refresh.wrapping_add(1) & 0x7F;
+        self.memory_refresh = (self.memory_refresh & 0x80) | counter;
+    }
+}
+
+impl Snapshot for State {
+    fn save_state(&self, out: &mut StateWriter) {
+        self.registers.save_state(out);
+        self.alt_registers.save_state(out);
+        out.write_u16(self.program_counter);
+        out.write_u8(self.status);
+        out.write_u16(self.ix);
+        out.write_u16(self.iy);
+        out.write_u8(self.interrupt_vector);
+        out.write_u8(self.memory_refresh);
+        out.write_u8(match self.interrupt_mode {
+            InterruptMode::Mode0 => 0,
+            InterruptMode::Mode1 => 1,
+            InterruptMode::Mode2 => 2,
+        });
+        out.write_bool(self.iff1);
+        out.write_bool(self.iff2);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load_state(input)?;
+        self.alt_registers.load_state(input)?;
+        self.program_counter = input.read_u16()?;
+        self.status = input.read_u8()?;
+        self.ix = input.read_u16()?;
+        self.iy = input.read_u16()?;
+        self.interrupt_vector = input.read_u8()?;
+        self.memory_refresh = input.read_u8()?;
+        self.interrupt_mode = match input.read_u8()? {
+            0 => InterruptMode::Mode0,
+            1 => InterruptMode::Mode1,
+            2 => InterruptMode::Mode2,
+            _ => return Err(StateError::Invalid("interrupt mode")),
+        };
+        self.iff1 = input.read_bool()?;
+        self.iff2 = input.read_bool()?;
+        Ok(())
+    }
 }
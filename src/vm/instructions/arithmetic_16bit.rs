@@ -25,6 +25,48 @@ impl Machine {
         self.add_register_pair(|regs| (&mut regs.h, &mut regs.l), selector);
     }
 
+    pub(crate) fn add_carry_register_pair_to_hl(&mut self, selector: fn(&Registers) -> (u8, u8)) {
+        self.operate_on_hl_with_carry(Operation::Add, selector);
+    }
+
+    pub(crate) fn subtract_carry_register_pair_from_hl(
+        &mut self,
+        selector: fn(&Registers) -> (u8, u8),
+    ) {
+        self.operate_on_hl_with_carry(Operation::Subtract, selector);
+    }
+
+    fn operate_on_hl_with_carry(
+        &mut self,
+        operation: Operation,
+        selector: fn(&Registers) -> (u8, u8),
+    ) {
+        let op1 = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let op2 = alu::get_word_from_tuple(selector(&self.cpu.state.registers));
+        let carry = Flag::Carry.get(&self.cpu.state.status);
+        let result = match operation {
+            Operation::Add => alu::add_words_with_carry(op1, op2, carry),
+            Operation::Subtract => alu::subtract_words_with_carry(op1, op2, carry),
+        };
+        self.cpu
+            .state
+            .registers
+            .assign_word(|regs| (&mut regs.h, &mut regs.l), result.value);
+        Flag::set_values(
+            &mut self.cpu.state.status,
+            &Flag::all(),
+            &[
+                (Flag::Zero, result.value == 0x0000),
+                (Flag::Sign, result.value > 0x7FFF),
+                (Flag::HalfCarry, result.half_carry),
+                (Flag::ParityOverflow, result.overflow),
+                (Flag::AddSubtract, operation == Operation::Subtract),
+                (Flag::Carry, result.carry),
+            ],
+        );
+        self.clock(15);
+    }
+
     fn add_register_pair(
         &mut self,
         target: fn(&mut Registers) -> (&mut u8, &mut u8),
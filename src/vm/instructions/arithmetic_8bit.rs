@@ -4,102 +4,189 @@ use vm::cpu::operation::Operation;
 use vm::cpu::registers::Registers;
 use vm::machine::Machine;
 
+pub(crate) type AccumulatorOperation = fn(&mut Machine, u8);
+
 impl Machine {
     pub(crate) fn add_register(&mut self, selector: fn(&Registers) -> u8) {
-        let operand = selector(&self.cpu.state.registers);
-        self.operate_on_register(
-            Operation::Add,
-            |regs| &mut regs.a,
-            operand,
-            &[
-                Flag::AddSubtract,
-                Flag::Carry,
-                Flag::HalfCarry,
-                Flag::ParityOverflow,
-                Flag::Sign,
-                Flag::Zero,
-            ],
-        );
-        self.clock(4);
+        self.accumulate_register(selector, Machine::add_to_accumulator);
     }
 
     pub(crate) fn add_carry_register(&mut self, selector: fn(&Registers) -> u8) {
-        let operand = selector(&self.cpu.state.registers);
-        let carry = Flag::Carry.get_bit(&self.cpu.state.status);
-        self.operate_on_register(
-            Operation::Add,
-            |regs| &mut regs.a,
-            operand + carry,
-            &[
-                Flag::AddSubtract,
-                Flag::Carry,
-                Flag::HalfCarry,
-                Flag::ParityOverflow,
-                Flag::Sign,
-                Flag::Zero,
-            ],
-        );
-        self.clock(4);
+        self.accumulate_register(selector, Machine::add_carry_to_accumulator);
     }
 
     pub(crate) fn subtract_register(&mut self, selector: fn(&Registers) -> u8) {
-        let operand = selector(&self.cpu.state.registers);
-        self.operate_on_register(
-            Operation::Subtract,
-            |regs| &mut regs.a,
-            operand,
-            &[
-                Flag::AddSubtract,
-                Flag::Carry,
-                Flag::HalfCarry,
-                Flag::ParityOverflow,
-                Flag::Sign,
-                Flag::Zero,
-            ],
-        );
-        self.clock(4);
+        self.accumulate_register(selector, Machine::subtract_from_accumulator);
     }
 
     pub(crate) fn subtract_carry_register(&mut self, selector: fn(&Registers) -> u8) {
+        self.accumulate_register(selector, Machine::subtract_carry_from_accumulator);
+    }
+
+    pub(crate) fn compare_register(&mut self, selector: fn(&Registers) -> u8) {
+        self.accumulate_register(selector, Machine::compare_with_accumulator);
+    }
+
+    pub(crate) fn add_value(&mut self) {
+        self.accumulate_value(Machine::add_to_accumulator);
+    }
+
+    pub(crate) fn add_carry_value(&mut self) {
+        self.accumulate_value(Machine::add_carry_to_accumulator);
+    }
+
+    pub(crate) fn subtract_value(&mut self) {
+        self.accumulate_value(Machine::subtract_from_accumulator);
+    }
+
+    pub(crate) fn subtract_carry_value(&mut self) {
+        self.accumulate_value(Machine::subtract_carry_from_accumulator);
+    }
+
+    pub(crate) fn compare_value(&mut self) {
+        self.accumulate_value(Machine::compare_with_accumulator);
+    }
+
+    pub(crate) fn accumulate_register(
+        &mut self,
+        selector: fn(&Registers) -> u8,
+        operation: AccumulatorOperation,
+    ) {
         let operand = selector(&self.cpu.state.registers);
-        let carry = Flag::Carry.get_bit(&self.cpu.state.status);
-        self.operate_on_register(
-            Operation::Subtract,
-            |regs| &mut regs.a,
-            operand + carry,
-            &[
-                Flag::AddSubtract,
-                Flag::Carry,
-                Flag::HalfCarry,
-                Flag::ParityOverflow,
-                Flag::Sign,
-                Flag::Zero,
-            ],
-        );
+        operation(self, operand);
         self.clock(4);
     }
 
+    pub(crate) fn accumulate_value(&mut self, operation: AccumulatorOperation) {
+        let operand = self.next_byte();
+        operation(self, operand);
+        self.clock(7);
+    }
+
+    pub(crate) fn accumulate_memory(&mut self, operation: AccumulatorOperation) {
+        let address = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let operand = self.bus.read_u8(address);
+        operation(self, operand);
+        self.clock(7);
+    }
+
+    pub(crate) fn accumulate_indexed(&mut self, address: u16, operation: AccumulatorOperation) {
+        let operand = self.bus.read_u8(address);
+        operation(self, operand);
+        self.clock(19);
+    }
+
+    pub(crate) fn add_to_accumulator(&mut self, operand: u8) {
+        self.operate_on_accumulator(Operation::Add, operand, false);
+    }
+
+    pub(crate) fn add_carry_to_accumulator(&mut self, operand: u8) {
+        let carry = Flag::Carry.get(&self.cpu.state.status);
+        self.operate_on_accumulator(Operation::Add, operand, carry);
+    }
+
+    pub(crate) fn subtract_from_accumulator(&mut self, operand: u8) {
+        self.operate_on_accumulator(Operation::Subtract, operand, false);
+    }
+
+    pub(crate) fn subtract_carry_from_accumulator(&mut self, operand: u8) {
+        let carry = Flag::Carry.get(&self.cpu.state.status);
+        self.operate_on_accumulator(Operation::Subtract, operand, carry);
+    }
+
+    pub(crate) fn compare_with_accumulator(&mut self, operand: u8) {
+        let a = self.cpu.state.registers.a;
+        self.operate_on_accumulator(Operation::Subtract, operand, false);
+        self.cpu.state.registers.a = a;
+    }
+
     pub(crate) fn increment_register(&mut self, target: fn(&mut Registers) -> &mut u8) {
-        self.operate_on_register(
-            Operation::Add,
-            target,
-            1,
-            &[
-                Flag::AddSubtract,
-                Flag::ParityOverflow,
-                Flag::HalfCarry,
-                Flag::Zero,
-                Flag::Sign,
-            ],
-        );
+        let value = *target(&mut self.cpu.state.registers);
+        *target(&mut self.cpu.state.registers) = self.step_value(Operation::Add, value);
         self.clock(4);
     }
 
     pub(crate) fn decrement_register(&mut self, target: fn(&mut Registers) -> &mut u8) {
-        self.operate_on_register(
-            Operation::Subtract,
-            target,
+        let value = *target(&mut self.cpu.state.registers);
+        *target(&mut self.cpu.state.registers) = self.step_value(Operation::Subtract, value);
+        self.clock(4);
+    }
+
+    pub(crate) fn increment_memory(&mut self) {
+        let address = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        self.step_memory(Operation::Add, address);
+        self.clock(11);
+    }
+
+    pub(crate) fn decrement_memory(&mut self) {
+        let address = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        self.step_memory(Operation::Subtract, address);
+        self.clock(11);
+    }
+
+    pub(crate) fn increment_indexed(&mut self, address: u16) {
+        self.step_memory(Operation::Add, address);
+        self.clock(23);
+    }
+
+    pub(crate) fn decrement_indexed(&mut self, address: u16) {
+        self.step_memory(Operation::Subtract, address);
+        self.clock(23);
+    }
+
+    pub(crate) fn negate_accumulator(&mut self) {
+        let a = self.cpu.state.registers.a;
+        self.cpu.state.registers.a = self.operate(Operation::Subtract, 0, a, false, &Flag::all());
+        self.clock(8);
+    }
+
+    // Corrects A after a BCD addition or subtraction. N tells which one it
+    // was, H and C which digits carried.
+    pub(crate) fn decimal_adjust_accumulator(&mut self) {
+        let a = self.cpu.state.registers.a;
+        let subtract = Flag::AddSubtract.get(&self.cpu.state.status);
+        let half_carry = Flag::HalfCarry.get(&self.cpu.state.status);
+        let mut carry = Flag::Carry.get(&self.cpu.state.status);
+        let mut correction = 0;
+        if half_carry || (!subtract && (a & 0x0F) > 0x09) {
+            correction |= 0x06;
+        }
+        if carry || (!subtract && a > 0x99) {
+            correction |= 0x60;
+            carry = true;
+        }
+        let result = if subtract {
+            a.wrapping_sub(correction)
+        } else {
+            a.wrapping_add(correction)
+        };
+        self.cpu.state.registers.a = result;
+
+        let status = &mut self.cpu.state.status;
+        Flag::Zero.set(status, result == 0x00);
+        Flag::Sign.set(status, result > 0x7F);
+        if subtract {
+            Flag::HalfCarry.set(status, half_carry && (a & 0x0F) < 0x06);
+        } else {
+            Flag::HalfCarry.set(status, (a & 0x0F) > 0x09);
+        }
+        Flag::ParityOverflow.set(status, alu::parity(result));
+        Flag::Carry.set(status, carry);
+        self.clock(4);
+    }
+
+    fn step_memory(&mut self, operation: Operation, address: u16) {
+        let value = self.bus.read_u8(address);
+        let result = self.step_value(operation, value);
+        self.bus.write_u8(address, result);
+    }
+
+    fn step_value(&mut self, operation: Operation, value: u8) -> u8 {
+        self.operate(
+            operation,
+            value,
             1,
+            false,
             &[
                 Flag::AddSubtract,
                 Flag::ParityOverflow,
@@ -107,21 +194,26 @@ impl Machine {
                 Flag::Zero,
                 Flag::Sign,
             ],
-        );
-        self.clock(4);
+        )
     }
 
-    fn operate_on_register(
+    fn operate_on_accumulator(&mut self, operation: Operation, operand: u8, carry: bool) {
+        let a = self.cpu.state.registers.a;
+        self.cpu.state.registers.a = self.operate(operation, a, operand, carry, &Flag::all());
+    }
+
+    fn operate(
         &mut self,
         operation: Operation,
-        target: fn(&mut Registers) -> &mut u8,
-        operand: u8,
+        op1: u8,
+        op2: u8,
+        carry: bool,
         affected_flags: &[Flag],
-    ) {
-        let op1 = *target(&mut self.cpu.state.registers);
-        let op2 = operation.maybe_negate(operand);
-        let result = alu::add_octets(op1, op2);
-        *target(&mut self.cpu.state.registers) = result.value;
+    ) -> u8 {
+        let result = match operation {
+            Operation::Add => alu::add_octets_with_carry(op1, op2, carry),
+            Operation::Subtract => alu::subtract_octets_with_carry(op1, op2, carry),
+        };
         Flag::set_values(
             &mut self.cpu.state.status,
             affected_flags,
@@ -134,5 +226,6 @@ impl Machine {
                 (Flag::Carry, result.carry),
             ],
         );
+        result.value
     }
 }
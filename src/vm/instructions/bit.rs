@@ -0,0 +1,69 @@
+use vm::cpu::flags::Flag;
+use vm::instructions::opcodes::Operand;
+use vm::machine::Machine;
+
+impl Machine {
+    pub(crate) fn test_bit(&mut self, bit: u8, operand: Operand) {
+        let value = self.read_operand(operand);
+        self.test_bit_value(bit, value);
+        if operand == Operand::IndirectHL {
+            self.clock(12);
+        } else {
+            self.clock(8);
+        }
+    }
+
+    pub(crate) fn set_bit(&mut self, bit: u8, operand: Operand) {
+        let value = self.read_operand(operand) | (1 << bit);
+        self.write_operand(operand, value);
+        self.clock_bit_update(operand);
+    }
+
+    pub(crate) fn reset_bit(&mut self, bit: u8, operand: Operand) {
+        let value = self.read_operand(operand) & !(1 << bit);
+        self.write_operand(operand, value);
+        self.clock_bit_update(operand);
+    }
+
+    pub(crate) fn test_indexed_bit(&mut self, bit: u8, address: u16) {
+        let value = self.bus.read_u8(address);
+        self.test_bit_value(bit, value);
+        self.clock(20);
+    }
+
+    pub(crate) fn set_indexed_bit(&mut self, bit: u8, address: u16, copy: Operand) {
+        let value = self.bus.read_u8(address) | (1 << bit);
+        self.update_indexed_bit(address, value, copy);
+    }
+
+    pub(crate) fn reset_indexed_bit(&mut self, bit: u8, address: u16, copy: Operand) {
+        let value = self.bus.read_u8(address) & !(1 << bit);
+        self.update_indexed_bit(address, value, copy);
+    }
+
+    fn test_bit_value(&mut self, bit: u8, value: u8) {
+        let is_zero = value & (1 << bit) == 0;
+        let status = &mut self.cpu.state.status;
+        Flag::Zero.set(status, is_zero);
+        Flag::ParityOverflow.set(status, is_zero);
+        Flag::Sign.set(status, bit == 7 && !is_zero);
+        Flag::HalfCarry.set(status, true);
+        Flag::AddSubtract.set(status, false);
+    }
+
+    fn update_indexed_bit(&mut self, address: u16, value: u8, copy: Operand) {
+        self.bus.write_u8(address, value);
+        if copy != Operand::IndirectHL {
+            self.write_operand(copy, value);
+        }
+        self.clock(23);
+    }
+
+    fn clock_bit_update(&mut self, operand: Operand) {
+        if operand == Operand::IndirectHL {
+            self.clock(15);
+        } else {
+            self.clock(8);
+        }
+    }
+}
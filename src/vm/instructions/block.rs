@@ -0,0 +1,111 @@
+use vm::cpu::alu;
+use vm::cpu::flags::Flag;
+use vm::machine::Machine;
+
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum Direction {
+    Increment,
+    Decrement,
+}
+
+impl Direction {
+    fn step(self, address: u16) -> u16 {
+        match self {
+            Direction::Increment => address.wrapping_add(1),
+            Direction::Decrement => address.wrapping_sub(1),
+        }
+    }
+}
+
+impl Machine {
+    pub(crate) fn block_load(&mut self, direction: Direction, repeat: bool) {
+        let hl = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let de = self.cpu.get_register_pair(|regs| (regs.d, regs.e));
+        let bc = self
+            .cpu
+            .get_register_pair(|regs| (regs.b, regs.c))
+            .wrapping_sub(1);
+        let value = self.bus.read_u8(hl);
+        self.bus.write_u8(de, value);
+        {
+            let regs = &mut self.cpu.state.registers;
+            regs.assign_word(|regs| (&mut regs.h, &mut regs.l), direction.step(hl));
+            regs.assign_word(|regs| (&mut regs.d, &mut regs.e), direction.step(de));
+            regs.assign_word(|regs| (&mut regs.b, &mut regs.c), bc);
+        }
+        let status = &mut self.cpu.state.status;
+        Flag::HalfCarry.set(status, false);
+        Flag::AddSubtract.set(status, false);
+        Flag::ParityOverflow.set(status, bc != 0);
+        self.repeat_block(repeat && bc != 0);
+    }
+
+    pub(crate) fn block_compare(&mut self, direction: Direction, repeat: bool) {
+        let hl = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let bc = self
+            .cpu
+            .get_register_pair(|regs| (regs.b, regs.c))
+            .wrapping_sub(1);
+        let value = self.bus.read_u8(hl);
+        let result = alu::subtract_octets_with_carry(self.cpu.state.registers.a, value, false);
+        {
+            let regs = &mut self.cpu.state.registers;
+            regs.assign_word(|regs| (&mut regs.h, &mut regs.l), direction.step(hl));
+            regs.assign_word(|regs| (&mut regs.b, &mut regs.c), bc);
+        }
+        let status = &mut self.cpu.state.status;
+        Flag::Zero.set(status, result.value == 0x00);
+        Flag::Sign.set(status, result.value > 0x7F);
+        Flag::HalfCarry.set(status, result.half_carry);
+        Flag::AddSubtract.set(status, true);
+        Flag::ParityOverflow.set(status, bc != 0);
+        self.repeat_block(repeat && bc != 0 && result.value != 0x00);
+    }
+
+    pub(crate) fn block_input(&mut self, direction: Direction, repeat: bool) {
+        let hl = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let port = self.cpu.get_register_pair(|regs| (regs.b, regs.c));
+        let value = self.read_port(port);
+        self.bus.write_u8(hl, value);
+        let b = self.cpu.state.registers.b.wrapping_sub(1);
+        self.cpu.state.registers.b = b;
+        self.cpu
+            .state
+            .registers
+            .assign_word(|regs| (&mut regs.h, &mut regs.l), direction.step(hl));
+        self.set_block_io_flags(b);
+        self.repeat_block(repeat && b != 0);
+    }
+
+    pub(crate) fn block_output(&mut self, direction: Direction, repeat: bool) {
+        let hl = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let b = self.cpu.state.registers.b.wrapping_sub(1);
+        self.cpu.state.registers.b = b;
+        let value = self.bus.read_u8(hl);
+        let port = self.cpu.get_register_pair(|regs| (regs.b, regs.c));
+        self.write_port(port, value);
+        self.cpu
+            .state
+            .registers
+            .assign_word(|regs| (&mut regs.h, &mut regs.l), direction.step(hl));
+        self.set_block_io_flags(b);
+        self.repeat_block(repeat && b != 0);
+    }
+
+    fn set_block_io_flags(&mut self, b: u8) {
+        let status = &mut self.cpu.state.status;
+        Flag::Zero.set(status, b == 0x00);
+        Flag::AddSubtract.set(status, true);
+    }
+
+    // A repeating instruction rewinds to its own prefix so that it is fetched
+    // again, which leaves room for interrupts between iterations.
+    fn repeat_block(&mut self, again: bool) {
+        if again {
+            self.cpu.state.program_counter = self.cpu.state.program_counter.wrapping_sub(2);
+            self.clock(21);
+        } else {
+            self.clock(16);
+        }
+    }
+}
@@ -20,6 +20,39 @@ impl Machine {
         self.clock(10);
     }
 
+    // The offset counts from the end of the two byte instruction.
+    pub(crate) fn jump_relative(&mut self, condition: fn(&u8) -> bool) {
+        let offset = self.next_byte() as i8;
+
+        if condition(&self.cpu.state.status) {
+            let dest = self.cpu.state.program_counter.wrapping_add(offset as u16);
+            self.cpu.goto(dest);
+            self.clock(12);
+        } else {
+            self.clock(7);
+        }
+    }
+
+    pub(crate) fn decrement_jump_nonzero(&mut self) {
+        let offset = self.next_byte() as i8;
+        let b = self.cpu.state.registers.b.wrapping_sub(1);
+        self.cpu.state.registers.b = b;
+
+        if b != 0 {
+            let dest = self.cpu.state.program_counter.wrapping_add(offset as u16);
+            self.cpu.goto(dest);
+            self.clock(13);
+        } else {
+            self.clock(8);
+        }
+    }
+
+    pub(crate) fn jump_to_hl(&mut self) {
+        let dest = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        self.cpu.goto(dest);
+        self.clock(4);
+    }
+
     pub(crate) fn call(&mut self, condition: fn(&u8) -> bool) {
         let dest = self.next_word();
 
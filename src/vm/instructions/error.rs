@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExecErrorReason {
+    UnimplementedOpcode,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecError {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub reason: ExecErrorReason,
+}
+
+impl ExecError {
+    pub fn new(pc: u16, bytes: Vec<u8>, reason: ExecErrorReason) -> ExecError {
+        ExecError { pc, bytes, reason }
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.reason {
+            ExecErrorReason::UnimplementedOpcode => "unimplemented opcode",
+        };
+        write!(f, "{} at {:#06X}:", reason, self.pc)?;
+        for byte in &self.bytes {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ExecError {}
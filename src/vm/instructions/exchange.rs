@@ -32,13 +32,18 @@ impl Machine {
         {
             let reg = &mut self.cpu.state.registers;
             let low_address = (reg.s as u16) << 8 | reg.p as u16;
-            let high_address = low_address + 1;
-            self.ram.write_u8(low_address, reg.l);
-            self.ram.write_u8(high_address, reg.h);
+            let high_address = low_address.wrapping_add(1);
+            let low_value = self.bus.read_u8(low_address);
+            let high_value = self.bus.read_u8(high_address);
+            self.bus.write_u8(low_address, reg.l);
+            self.bus.write_u8(high_address, reg.h);
+            reg.l = low_value;
+            reg.h = high_value;
         }
         self.clock(19);
     }
 
+    #[allow(clippy::type_complexity)]
     fn exchange(&mut self, selectors: Vec<fn(&mut Registers) -> (&mut u8, &mut u8)>) {
         let reg = &mut self.cpu.state.registers;
         for s in selectors {
@@ -0,0 +1,45 @@
+use std::mem;
+use vm::machine::Machine;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum IndexRegister {
+    IX,
+    IY,
+}
+
+impl IndexRegister {
+    pub fn prefix(self) -> u8 {
+        match self {
+            IndexRegister::IX => 0xDD,
+            IndexRegister::IY => 0xFD,
+        }
+    }
+}
+
+impl Machine {
+    // Index prefixes make the following instruction use IX or IY in place of
+    // HL, so the unprefixed handlers are reused by exchanging them in and out.
+    pub(crate) fn swap_index_register(&mut self, index: IndexRegister) {
+        let hl = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let previous = {
+            let target = match index {
+                IndexRegister::IX => &mut self.cpu.state.ix,
+                IndexRegister::IY => &mut self.cpu.state.iy,
+            };
+            mem::replace(target, hl)
+        };
+        self.cpu
+            .state
+            .registers
+            .assign_word(|regs| (&mut regs.h, &mut regs.l), previous);
+    }
+
+    pub(crate) fn indexed_address(&mut self, index: IndexRegister) -> u16 {
+        let displacement = self.next_byte() as i8;
+        let base = match index {
+            IndexRegister::IX => self.cpu.state.ix,
+            IndexRegister::IY => self.cpu.state.iy,
+        };
+        base.wrapping_add(displacement as u16)
+    }
+}
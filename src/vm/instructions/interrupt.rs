@@ -0,0 +1,78 @@
+use vm::cpu::state::InterruptMode;
+use vm::machine::Machine;
+
+impl Machine {
+    pub(crate) fn set_interrupt_mode(&mut self, mode: InterruptMode) {
+        self.cpu.state.interrupt_mode = mode;
+        self.clock(8);
+    }
+
+    // RETI also copies IFF2 into IFF1 on a real Z80, so both share this.
+    pub(crate) fn return_from_interrupt(&mut self) {
+        self.pop_stack_to_program_counter();
+        self.cpu.state.iff1 = self.cpu.state.iff2;
+        self.clock(14);
+    }
+
+    pub(crate) fn disable_interrupts(&mut self) {
+        self.cpu.state.iff1 = false;
+        self.cpu.state.iff2 = false;
+        self.clock(4);
+    }
+
+    pub(crate) fn enable_interrupts(&mut self) {
+        self.cpu.state.iff1 = true;
+        self.cpu.state.iff2 = true;
+        self.cpu.delay_interrupts();
+        self.clock(4);
+    }
+
+    pub(crate) fn restart(&mut self, address: u16) {
+        self.push_program_counter_to_stack();
+        self.cpu.goto(address);
+        self.clock(11);
+    }
+
+    pub(crate) fn accept_interrupt(&mut self, maskable: bool) -> bool {
+        if self.cpu.take_nmi() {
+            self.acknowledge_interrupt();
+            self.cpu.state.iff1 = false;
+            self.push_program_counter_to_stack();
+            self.cpu.goto(0x0066);
+            self.clock(11);
+            return true;
+        }
+        if !(maskable && self.cpu.irq_asserted() && self.cpu.state.iff1) {
+            return false;
+        }
+        self.acknowledge_interrupt();
+        self.cpu.state.iff1 = false;
+        self.cpu.state.iff2 = false;
+        self.push_program_counter_to_stack();
+        let data = self.cpu.interrupt_data();
+        match self.cpu.state.interrupt_mode {
+            // Only RST instructions are supported on the data bus in mode 0,
+            // which is all the Master System ever puts there (0xFF).
+            InterruptMode::Mode0 => {
+                self.cpu.goto((data & 0x38) as u16);
+                self.clock(13);
+            }
+            InterruptMode::Mode1 => {
+                self.cpu.goto(0x0038);
+                self.clock(13);
+            }
+            InterruptMode::Mode2 => {
+                let table = ((self.cpu.state.interrupt_vector as u16) << 8) | data as u16;
+                let address = self.bus.read_u16(table);
+                self.cpu.goto(address);
+                self.clock(19);
+            }
+        }
+        true
+    }
+
+    fn acknowledge_interrupt(&mut self) {
+        self.cpu.unhalt();
+        self.cpu.state.refresh();
+    }
+}
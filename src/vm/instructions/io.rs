@@ -0,0 +1,56 @@
+use vm::cpu::alu;
+use vm::cpu::flags::Flag;
+use vm::cpu::registers::Registers;
+use vm::machine::Machine;
+
+impl Machine {
+    pub(crate) fn input_from_param_port(&mut self) {
+        let port = alu::get_word(self.cpu.state.registers.a, self.next_byte());
+        self.cpu.state.registers.a = self.read_port(port);
+        self.clock(11);
+    }
+
+    pub(crate) fn output_to_param_port(&mut self) {
+        let a = self.cpu.state.registers.a;
+        let port = alu::get_word(a, self.next_byte());
+        self.write_port(port, a);
+        self.clock(11);
+    }
+
+    pub(crate) fn input_register(&mut self, selector: fn(&mut Registers) -> &mut u8) {
+        let value = self.input_with_flags();
+        *selector(&mut self.cpu.state.registers) = value;
+    }
+
+    // IN (C) only updates the flags.
+    pub(crate) fn input_with_flags(&mut self) -> u8 {
+        let port = self.cpu.get_register_pair(|regs| (regs.b, regs.c));
+        let value = self.read_port(port);
+        {
+            let status = &mut self.cpu.state.status;
+            Flag::Zero.set(status, value == 0x00);
+            Flag::Sign.set(status, value > 0x7F);
+            Flag::HalfCarry.set(status, false);
+            Flag::AddSubtract.set(status, false);
+            Flag::ParityOverflow.set(status, alu::parity(value));
+        }
+        self.clock(12);
+        value
+    }
+
+    pub(crate) fn output_register(&mut self, selector: fn(&Registers) -> u8) {
+        let port = self.cpu.get_register_pair(|regs| (regs.b, regs.c));
+        let value = selector(&self.cpu.state.registers);
+        self.write_port(port, value);
+        self.clock(12);
+    }
+
+    // Only the low half of the address bus selects a port on the Master System.
+    pub(crate) fn read_port(&mut self, port: u16) -> u8 {
+        self.io.read(port as u8)
+    }
+
+    pub(crate) fn write_port(&mut self, port: u16, value: u8) {
+        self.io.write(port as u8, value);
+    }
+}
@@ -1,4 +1,6 @@
+use vm::cpu::flags::Flag;
 use vm::cpu::registers::Registers;
+use vm::cpu::state::State;
 use vm::machine::Machine;
 
 impl Machine {
@@ -15,7 +17,7 @@ impl Machine {
         {
             let (high_addr, low_addr) = pointer(&self.cpu.state.registers);
             let address = Registers::u8s_to_u16(high_addr, low_addr);
-            let value = self.ram.read_u8(address);
+            let value = self.bus.read_u8(address);
             let dest = selector(&mut self.cpu.state.registers);
             *dest = value;
         }
@@ -27,7 +29,7 @@ impl Machine {
             let (high_addr, low_addr) = pointer(&self.cpu.state.registers);
             let address = Registers::u8s_to_u16(high_addr, low_addr);
             let value = selector(&self.cpu.state.registers);
-            self.ram.write_u8(address, value);
+            self.bus.write_u8(address, value);
         }
         self.clock(7);
     }
@@ -35,14 +37,14 @@ impl Machine {
     pub(crate) fn load_register_into_param_memory(&mut self, selector: fn(&Registers) -> u8) {
         let address = self.next_word();
         let value = selector(&self.cpu.state.registers);
-        self.ram.write_u8(address, value);
+        self.bus.write_u8(address, value);
         self.clock(13);
     }
 
     pub(crate) fn load_param_memory_into_register(&mut self, selector: fn(&mut Registers) -> &mut u8) {
         {
             let address = self.next_word();
-            let value = self.ram.read_u8(address);
+            let value = self.bus.read_u8(address);
             let dest = selector(&mut self.cpu.state.registers);
             *dest = value;
         }
@@ -53,7 +55,7 @@ impl Machine {
         let address = self.next_word();
         let (high_val, low_val) = selector(&self.cpu.state.registers);
         let value = Registers::u8s_to_u16(high_val, low_val);
-        self.ram.write_u16(address, value);
+        self.bus.write_u16(address, value);
         self.clock(16);
     }
 
@@ -61,7 +63,7 @@ impl Machine {
         {
             let address = self.next_word();
             let (high_addr, low_addr) = selector(&mut self.cpu.state.registers);
-            let value = self.ram.read_u16(address);
+            let value = self.bus.read_u16(address);
             let (high_val, low_val) = Registers::u16_to_u8s(value);
             *high_addr = high_val;
             *low_addr = low_val;
@@ -73,7 +75,7 @@ impl Machine {
         let (high_addr, low_addr) = selector(&self.cpu.state.registers);
         let address = Registers::u8s_to_u16(high_addr, low_addr);
         let value = self.next_byte();
-        self.ram.write_u8(address, value);
+        self.bus.write_u8(address, value);
         self.clock(10);
     }
 
@@ -105,8 +107,56 @@ impl Machine {
             let value = source(&self.cpu.state.registers);
             let (high_addr, low_addr) = pointer(&self.cpu.state.registers);
             let address = ((high_addr as u16) << 8) | (low_addr as u16);
-            self.ram.write_u8(address, value);
+            self.bus.write_u8(address, value);
         }
         self.clock(7);
     }    
+
+    pub(crate) fn load_special_register_into_accumulator(&mut self, selector: fn(&State) -> u8) {
+        let value = selector(&self.cpu.state);
+        let iff2 = self.cpu.state.iff2;
+        self.cpu.state.registers.a = value;
+        {
+            let status = &mut self.cpu.state.status;
+            Flag::Zero.set(status, value == 0x00);
+            Flag::Sign.set(status, value > 0x7F);
+            Flag::HalfCarry.set(status, false);
+            Flag::AddSubtract.set(status, false);
+            Flag::ParityOverflow.set(status, iff2);
+        }
+        self.clock(9);
+    }
+
+    pub(crate) fn load_accumulator_into_special_register(&mut self, selector: fn(&mut State) -> &mut u8) {
+        let value = self.cpu.state.registers.a;
+        *selector(&mut self.cpu.state) = value;
+        self.clock(9);
+    }
+
+    pub(crate) fn load_indexed_into_register(&mut self, address: u16, selector: fn(&mut Registers) -> &mut u8) {
+        let value = self.bus.read_u8(address);
+        *selector(&mut self.cpu.state.registers) = value;
+        self.clock(19);
+    }
+
+    pub(crate) fn load_register_into_indexed(&mut self, selector: fn(&Registers) -> u8, address: u16) {
+        let value = selector(&self.cpu.state.registers);
+        self.bus.write_u8(address, value);
+        self.clock(19);
+    }
+
+    pub(crate) fn load_param_into_indexed(&mut self, address: u16) {
+        let value = self.next_byte();
+        self.bus.write_u8(address, value);
+        self.clock(19);
+    }
+
+    pub(crate) fn load_hl_into_sp(&mut self) {
+        {
+            let regs = &mut self.cpu.state.registers;
+            regs.s = regs.h;
+            regs.p = regs.l;
+        }
+        self.clock(6);
+    }
 }
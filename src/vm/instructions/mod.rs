@@ -1,19 +1,52 @@
 mod arithmetic_16bit;
 mod arithmetic_8bit;
+mod bit;
 mod bitwise;
+mod block;
 mod control;
+pub mod error;
 mod exchange;
+mod indexed;
+mod interrupt;
+mod io;
 mod memory;
 pub mod opcodes;
+mod shift;
 mod stack;
 
+use std::convert::TryFrom;
 use vm::cpu::flags::Flag;
-use vm::instructions::opcodes::Opcode;
+use vm::cpu::registers::Registers;
+use vm::cpu::state::InterruptMode;
+use vm::instructions::block::Direction;
+use vm::instructions::error::{ExecError, ExecErrorReason};
+use vm::instructions::indexed::IndexRegister;
+use vm::instructions::opcodes::{BitOpcode, ExtendedOpcode, Opcode, Operand};
 use vm::machine::Machine;
 
 impl Machine {
-    pub fn execute(&mut self) {
-        let opcode = Opcode::from(self.next_byte());
+    pub fn execute(&mut self) -> Result<(), ExecError> {
+        let maskable = !self.cpu.take_interrupt_delay();
+        if self.accept_interrupt(maskable) {
+            return Ok(());
+        }
+        if self.cpu.is_halted() {
+            self.cpu.state.refresh();
+            self.nop();
+            return Ok(());
+        }
+        let pc = self.cpu.state.program_counter;
+        let byte = self.fetch_opcode();
+        let opcode = match Opcode::try_from(byte) {
+            Ok(opcode) => opcode,
+            Err(byte) => {
+                return Err(self.fault(pc, vec![byte], ExecErrorReason::UnimplementedOpcode))
+            }
+        };
+        self.execute_opcode(pc, opcode)
+    }
+
+    fn execute_opcode(&mut self, pc: u16, opcode: Opcode) -> Result<(), ExecError> {
         match opcode {
             Opcode::Nop => self.nop(),
 
@@ -38,6 +71,9 @@ impl Machine {
             Opcode::DecH => self.decrement_register(|regs| &mut regs.h),
             Opcode::DecL => self.decrement_register(|regs| &mut regs.l),
 
+            Opcode::IncVHL => self.increment_memory(),
+            Opcode::DecVHL => self.decrement_memory(),
+
             Opcode::IncBC => self.increment_register_wide(|regs| (&mut regs.b, &mut regs.c)),
             Opcode::IncDE => self.increment_register_wide(|regs| (&mut regs.d, &mut regs.e)),
             Opcode::IncHL => self.increment_register_wide(|regs| (&mut regs.h, &mut regs.l)),
@@ -64,6 +100,29 @@ impl Machine {
             Opcode::SubH => self.subtract_register(|regs| regs.h),
             Opcode::SubL => self.subtract_register(|regs| regs.l),
 
+            Opcode::CpA => self.compare_register(|regs| regs.a),
+            Opcode::CpB => self.compare_register(|regs| regs.b),
+            Opcode::CpC => self.compare_register(|regs| regs.c),
+            Opcode::CpD => self.compare_register(|regs| regs.d),
+            Opcode::CpE => self.compare_register(|regs| regs.e),
+            Opcode::CpH => self.compare_register(|regs| regs.h),
+            Opcode::CpL => self.compare_register(|regs| regs.l),
+
+            Opcode::AddVHL => self.accumulate_memory(Machine::add_to_accumulator),
+            Opcode::AdcVHL => self.accumulate_memory(Machine::add_carry_to_accumulator),
+            Opcode::SubVHL => self.accumulate_memory(Machine::subtract_from_accumulator),
+            Opcode::SbcVHL => self.accumulate_memory(Machine::subtract_carry_from_accumulator),
+            Opcode::AndVHL => self.accumulate_memory(Machine::and_with_accumulator),
+            Opcode::XorVHL => self.accumulate_memory(Machine::xor_with_accumulator),
+            Opcode::OrVHL => self.accumulate_memory(Machine::or_with_accumulator),
+            Opcode::CpVHL => self.accumulate_memory(Machine::compare_with_accumulator),
+
+            Opcode::AddX => self.add_value(),
+            Opcode::AdcX => self.add_carry_value(),
+            Opcode::SubX => self.subtract_value(),
+            Opcode::SbcX => self.subtract_carry_value(),
+            Opcode::CpX => self.compare_value(),
+
             Opcode::AddHLBC => self.add_register_pair_to_hl(|regs| (regs.b, regs.c)),
             Opcode::AddHLDE => self.add_register_pair_to_hl(|regs| (regs.d, regs.e)),
             Opcode::AddHLHL => self.add_register_pair_to_hl(|regs| (regs.h, regs.l)),
@@ -94,6 +153,13 @@ impl Machine {
             Opcode::JpPEXX => self.jump(|status| !Flag::ParityOverflow.get(status)),
             Opcode::JpPXX => self.jump(|status| !Flag::Sign.get(status)),
             Opcode::JpMXX => self.jump(|status| Flag::Sign.get(status)),
+            Opcode::JpHL => self.jump_to_hl(),
+            Opcode::JrX => self.jump_relative(|_| true),
+            Opcode::JrNZX => self.jump_relative(|status| !Flag::Zero.get(status)),
+            Opcode::JrZX => self.jump_relative(|status| Flag::Zero.get(status)),
+            Opcode::JrNCX => self.jump_relative(|status| !Flag::Carry.get(status)),
+            Opcode::JrCX => self.jump_relative(|status| Flag::Carry.get(status)),
+            Opcode::DjnzX => self.decrement_jump_nonzero(),
 
             Opcode::CallXX => self.call(|_| true),
             Opcode::CallNZXX => self.call(|status| !Flag::Zero.get(status)),
@@ -229,6 +295,7 @@ impl Machine {
             Opcode::LdVXXA => self.load_register_into_param_memory(|regs| regs.a),
             Opcode::LdAVXX => self.load_param_memory_into_register(|regs| &mut regs.a),
             Opcode::LdVHLX => self.load_param_into_memory(|regs| (regs.h, regs.l)),
+            Opcode::LdSPHL => self.load_hl_into_sp(),
 
             Opcode::AndA => self.and_register(|regs| regs.a),
             Opcode::AndB => self.and_register(|regs| regs.b),
@@ -270,15 +337,351 @@ impl Machine {
             Opcode::SCF => self.set_carry_flag(),
             Opcode::CCF => self.complement_carry_flag(),
             Opcode::CPL => self.complement_registers(|regs| &mut regs.a),
-            Opcode::RLCA => self.rotate_accumulator_left(),
+            Opcode::DAA => self.decimal_adjust_accumulator(),
+            Opcode::RLCA => self.rotate_accumulator(shift::rotate_left_circular),
+            Opcode::RRCA => self.rotate_accumulator(shift::rotate_right_circular),
+            Opcode::RLA => self.rotate_accumulator(shift::rotate_left),
+            Opcode::RRA => self.rotate_accumulator(shift::rotate_right),
+
+            Opcode::PrefixCB => self.execute_bit_page(),
+            Opcode::PrefixED => self.execute_extended_page(),
+            Opcode::PrefixDD => self.execute_index_page(pc, IndexRegister::IX)?,
+            Opcode::PrefixFD => self.execute_index_page(pc, IndexRegister::IY)?,
 
             Opcode::Halt => self.halt(),
+            Opcode::InAVX => self.input_from_param_port(),
+            Opcode::OutVXA => self.output_to_param_port(),
+            Opcode::Di => self.disable_interrupts(),
+            Opcode::Ei => self.enable_interrupts(),
+
+            Opcode::Rst00 => self.restart(0x0000),
+            Opcode::Rst08 => self.restart(0x0008),
+            Opcode::Rst10 => self.restart(0x0010),
+            Opcode::Rst18 => self.restart(0x0018),
+            Opcode::Rst20 => self.restart(0x0020),
+            Opcode::Rst28 => self.restart(0x0028),
+            Opcode::Rst30 => self.restart(0x0030),
+            Opcode::Rst38 => self.restart(0x0038),
         }
+        Ok(())
+    }
+
+    fn execute_index_page(&mut self, pc: u16, index: IndexRegister) -> Result<(), ExecError> {
+        // In a run of DD and FD prefixes only the last one counts. Each of
+        // the others costs its own fetch and the next execute starts at the
+        // following prefix, so interrupts can come in between.
+        let next = self.bus.read_u8(self.cpu.state.program_counter);
+        if next == Opcode::PrefixDD as u8 || next == Opcode::PrefixFD as u8 {
+            self.clock(4);
+            return Ok(());
+        }
+        let byte = self.fetch_opcode();
+        let opcode = match Opcode::try_from(byte) {
+            Ok(opcode) => opcode,
+            Err(byte) => {
+                let reason = ExecErrorReason::UnimplementedOpcode;
+                return Err(self.fault(pc, vec![index.prefix(), byte], reason));
+            }
+        };
+        match opcode {
+            Opcode::PrefixCB => self.execute_index_bit_page(index),
+
+            Opcode::LdAHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.a)
+            }
+            Opcode::LdBHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.b)
+            }
+            Opcode::LdCHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.c)
+            }
+            Opcode::LdDHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.d)
+            }
+            Opcode::LdEHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.e)
+            }
+            Opcode::LdHHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.h)
+            }
+            Opcode::LdLHL => {
+                let address = self.indexed_address(index);
+                self.load_indexed_into_register(address, |regs| &mut regs.l)
+            }
+
+            Opcode::LdHLA => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.a, address)
+            }
+            Opcode::LdHLB => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.b, address)
+            }
+            Opcode::LdHLC => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.c, address)
+            }
+            Opcode::LdHLD => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.d, address)
+            }
+            Opcode::LdHLE => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.e, address)
+            }
+            Opcode::LdHLH => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.h, address)
+            }
+            Opcode::LdHLL => {
+                let address = self.indexed_address(index);
+                self.load_register_into_indexed(|regs| regs.l, address)
+            }
+            Opcode::LdVHLX => {
+                let address = self.indexed_address(index);
+                self.load_param_into_indexed(address)
+            }
+
+            Opcode::IncVHL => {
+                let address = self.indexed_address(index);
+                self.increment_indexed(address)
+            }
+            Opcode::DecVHL => {
+                let address = self.indexed_address(index);
+                self.decrement_indexed(address)
+            }
+
+            Opcode::AddVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::add_to_accumulator)
+            }
+            Opcode::AdcVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::add_carry_to_accumulator)
+            }
+            Opcode::SubVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::subtract_from_accumulator)
+            }
+            Opcode::SbcVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::subtract_carry_from_accumulator)
+            }
+            Opcode::AndVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::and_with_accumulator)
+            }
+            Opcode::XorVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::xor_with_accumulator)
+            }
+            Opcode::OrVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::or_with_accumulator)
+            }
+            Opcode::CpVHL => {
+                let address = self.indexed_address(index);
+                self.accumulate_indexed(address, Machine::compare_with_accumulator)
+            }
+
+            // These never refer to HL through a prefix: the prefix only
+            // costs its own fetch.
+            Opcode::ExDEHL | Opcode::Exx | Opcode::PrefixED => {
+                self.clock(4);
+                self.execute_opcode(pc, opcode)?;
+            }
+
+            _ => {
+                self.swap_index_register(index);
+                let result = self.execute_opcode(pc, opcode);
+                self.swap_index_register(index);
+                result?;
+                self.clock(4);
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_index_bit_page(&mut self, index: IndexRegister) {
+        let address = self.indexed_address(index);
+        match BitOpcode::from(self.next_byte()) {
+            BitOpcode::Rlc(copy) => self.shift_indexed(shift::rotate_left_circular, address, copy),
+            BitOpcode::Rrc(copy) => self.shift_indexed(shift::rotate_right_circular, address, copy),
+            BitOpcode::Rl(copy) => self.shift_indexed(shift::rotate_left, address, copy),
+            BitOpcode::Rr(copy) => self.shift_indexed(shift::rotate_right, address, copy),
+            BitOpcode::Sla(copy) => self.shift_indexed(shift::shift_left_arithmetic, address, copy),
+            BitOpcode::Sra(copy) => {
+                self.shift_indexed(shift::shift_right_arithmetic, address, copy)
+            }
+            BitOpcode::Sll(copy) => self.shift_indexed(shift::shift_left_logical, address, copy),
+            BitOpcode::Srl(copy) => self.shift_indexed(shift::shift_right_logical, address, copy),
+            BitOpcode::Bit(bit, _) => self.test_indexed_bit(bit, address),
+            BitOpcode::Res(bit, copy) => self.reset_indexed_bit(bit, address, copy),
+            BitOpcode::Set(bit, copy) => self.set_indexed_bit(bit, address, copy),
+        }
+    }
+
+    fn execute_bit_page(&mut self) {
+        match BitOpcode::from(self.fetch_opcode()) {
+            BitOpcode::Rlc(operand) => self.shift_operand(shift::rotate_left_circular, operand),
+            BitOpcode::Rrc(operand) => self.shift_operand(shift::rotate_right_circular, operand),
+            BitOpcode::Rl(operand) => self.shift_operand(shift::rotate_left, operand),
+            BitOpcode::Rr(operand) => self.shift_operand(shift::rotate_right, operand),
+            BitOpcode::Sla(operand) => self.shift_operand(shift::shift_left_arithmetic, operand),
+            BitOpcode::Sra(operand) => self.shift_operand(shift::shift_right_arithmetic, operand),
+            BitOpcode::Sll(operand) => self.shift_operand(shift::shift_left_logical, operand),
+            BitOpcode::Srl(operand) => self.shift_operand(shift::shift_right_logical, operand),
+            BitOpcode::Bit(bit, operand) => self.test_bit(bit, operand),
+            BitOpcode::Res(bit, operand) => self.reset_bit(bit, operand),
+            BitOpcode::Set(bit, operand) => self.set_bit(bit, operand),
+        }
+    }
+
+    fn execute_extended_page(&mut self) {
+        let byte = self.fetch_opcode();
+        let opcode = match ExtendedOpcode::try_from(byte) {
+            Ok(opcode) => opcode,
+            // Undefined ED bytes run as two NOPs on a real Z80.
+            Err(_) => {
+                self.clock(8);
+                return;
+            }
+        };
+        match opcode {
+            ExtendedOpcode::InAVC => self.input_register(|regs| &mut regs.a),
+            ExtendedOpcode::InBVC => self.input_register(|regs| &mut regs.b),
+            ExtendedOpcode::InCVC => self.input_register(|regs| &mut regs.c),
+            ExtendedOpcode::InDVC => self.input_register(|regs| &mut regs.d),
+            ExtendedOpcode::InEVC => self.input_register(|regs| &mut regs.e),
+            ExtendedOpcode::InHVC => self.input_register(|regs| &mut regs.h),
+            ExtendedOpcode::InLVC => self.input_register(|regs| &mut regs.l),
+            ExtendedOpcode::InVC => {
+                self.input_with_flags();
+            }
+
+            ExtendedOpcode::OutVCA => self.output_register(|regs| regs.a),
+            ExtendedOpcode::OutVCB => self.output_register(|regs| regs.b),
+            ExtendedOpcode::OutVCC => self.output_register(|regs| regs.c),
+            ExtendedOpcode::OutVCD => self.output_register(|regs| regs.d),
+            ExtendedOpcode::OutVCE => self.output_register(|regs| regs.e),
+            ExtendedOpcode::OutVCH => self.output_register(|regs| regs.h),
+            ExtendedOpcode::OutVCL => self.output_register(|regs| regs.l),
+            ExtendedOpcode::OutVC0 => self.output_register(|_| 0),
+
+            ExtendedOpcode::AdcHLBC => self.add_carry_register_pair_to_hl(|regs| (regs.b, regs.c)),
+            ExtendedOpcode::AdcHLDE => self.add_carry_register_pair_to_hl(|regs| (regs.d, regs.e)),
+            ExtendedOpcode::AdcHLHL => self.add_carry_register_pair_to_hl(|regs| (regs.h, regs.l)),
+            ExtendedOpcode::AdcHLSP => self.add_carry_register_pair_to_hl(|regs| (regs.s, regs.p)),
+
+            ExtendedOpcode::SbcHLBC => {
+                self.subtract_carry_register_pair_from_hl(|regs| (regs.b, regs.c))
+            }
+            ExtendedOpcode::SbcHLDE => {
+                self.subtract_carry_register_pair_from_hl(|regs| (regs.d, regs.e))
+            }
+            ExtendedOpcode::SbcHLHL => {
+                self.subtract_carry_register_pair_from_hl(|regs| (regs.h, regs.l))
+            }
+            ExtendedOpcode::SbcHLSP => {
+                self.subtract_carry_register_pair_from_hl(|regs| (regs.s, regs.p))
+            }
+
+            // The prefix fetch adds four T-states to the unprefixed forms.
+            ExtendedOpcode::LdVXXBC => {
+                self.load_wide_register_into_param_memory(|regs| (regs.b, regs.c));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdVXXDE => {
+                self.load_wide_register_into_param_memory(|regs| (regs.d, regs.e));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdVXXHL => {
+                self.load_wide_register_into_param_memory(|regs| (regs.h, regs.l));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdVXXSP => {
+                self.load_wide_register_into_param_memory(|regs| (regs.s, regs.p));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdBCVXX => {
+                self.load_param_memory_into_wide_register(|regs| (&mut regs.b, &mut regs.c));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdDEVXX => {
+                self.load_param_memory_into_wide_register(|regs| (&mut regs.d, &mut regs.e));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdHLVXX => {
+                self.load_param_memory_into_wide_register(|regs| (&mut regs.h, &mut regs.l));
+                self.clock(4);
+            }
+            ExtendedOpcode::LdSPVXX => {
+                self.load_param_memory_into_wide_register(|regs| (&mut regs.s, &mut regs.p));
+                self.clock(4);
+            }
+
+            ExtendedOpcode::LdIA => {
+                self.load_accumulator_into_special_register(|state| &mut state.interrupt_vector)
+            }
+            ExtendedOpcode::LdRA => {
+                self.load_accumulator_into_special_register(|state| &mut state.memory_refresh)
+            }
+            ExtendedOpcode::LdAI => {
+                self.load_special_register_into_accumulator(|state| state.interrupt_vector)
+            }
+            ExtendedOpcode::LdAR => {
+                self.load_special_register_into_accumulator(|state| state.memory_refresh)
+            }
+
+            ExtendedOpcode::Neg => self.negate_accumulator(),
+            ExtendedOpcode::Rrd => self.rotate_digit_right(),
+            ExtendedOpcode::Rld => self.rotate_digit_left(),
+
+            ExtendedOpcode::Retn => self.return_from_interrupt(),
+            ExtendedOpcode::Reti => self.return_from_interrupt(),
+            ExtendedOpcode::Im0 => self.set_interrupt_mode(InterruptMode::Mode0),
+            ExtendedOpcode::Im1 => self.set_interrupt_mode(InterruptMode::Mode1),
+            ExtendedOpcode::Im2 => self.set_interrupt_mode(InterruptMode::Mode2),
+
+            ExtendedOpcode::Ldi => self.block_load(Direction::Increment, false),
+            ExtendedOpcode::Ldd => self.block_load(Direction::Decrement, false),
+            ExtendedOpcode::Ldir => self.block_load(Direction::Increment, true),
+            ExtendedOpcode::Lddr => self.block_load(Direction::Decrement, true),
+            ExtendedOpcode::Cpi => self.block_compare(Direction::Increment, false),
+            ExtendedOpcode::Cpd => self.block_compare(Direction::Decrement, false),
+            ExtendedOpcode::Cpir => self.block_compare(Direction::Increment, true),
+            ExtendedOpcode::Cpdr => self.block_compare(Direction::Decrement, true),
+            ExtendedOpcode::Ini => self.block_input(Direction::Increment, false),
+            ExtendedOpcode::Ind => self.block_input(Direction::Decrement, false),
+            ExtendedOpcode::Inir => self.block_input(Direction::Increment, true),
+            ExtendedOpcode::Indr => self.block_input(Direction::Decrement, true),
+            ExtendedOpcode::Outi => self.block_output(Direction::Increment, false),
+            ExtendedOpcode::Outd => self.block_output(Direction::Decrement, false),
+            ExtendedOpcode::Otir => self.block_output(Direction::Increment, true),
+            ExtendedOpcode::Otdr => self.block_output(Direction::Decrement, true),
+        }
+    }
+
+    fn fault(&mut self, pc: u16, bytes: Vec<u8>, reason: ExecErrorReason) -> ExecError {
+        self.cpu.goto(pc);
+        self.cpu.unhalt();
+        ExecError::new(pc, bytes, reason)
+    }
+
+    fn fetch_opcode(&mut self) -> u8 {
+        self.cpu.state.refresh();
+        self.next_byte()
     }
 
     fn next_byte(&mut self) -> u8 {
         let pc = self.cpu.state.program_counter;
-        let val = self.ram.read_u8(pc);
+        let val = self.bus.read_u8(pc);
         let (result, overflow) = pc.overflowing_add(1);
         if overflow {
             self.cpu.halt();
@@ -300,7 +703,34 @@ impl Machine {
         (high << 8) | low
     }
 
-    pub fn clock(&mut self, _tstates: u8) {
-        // TODO: Something.
+    fn read_operand(&self, operand: Operand) -> u8 {
+        let regs = &self.cpu.state.registers;
+        match operand {
+            Operand::A => regs.a,
+            Operand::B => regs.b,
+            Operand::C => regs.c,
+            Operand::D => regs.d,
+            Operand::E => regs.e,
+            Operand::H => regs.h,
+            Operand::L => regs.l,
+            Operand::IndirectHL => self.bus.read_u8(Registers::u8s_to_u16(regs.h, regs.l)),
+        }
+    }
+
+    fn write_operand(&mut self, operand: Operand, value: u8) {
+        let regs = &mut self.cpu.state.registers;
+        match operand {
+            Operand::A => regs.a = value,
+            Operand::B => regs.b = value,
+            Operand::C => regs.c = value,
+            Operand::D => regs.d = value,
+            Operand::E => regs.e = value,
+            Operand::H => regs.h = value,
+            Operand::L => regs.l = value,
+            Operand::IndirectHL => {
+                let address = Registers::u8s_to_u16(regs.h, regs.l);
+                self.bus.write_u8(address, value);
+            }
+        }
     }
 }
@@ -1,4 +1,7 @@
-#[derive(Copy, Clone)]
+use std::convert::TryFrom;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Opcode {
     Nop = 0x00,
@@ -16,26 +19,34 @@ pub enum Opcode {
     IncC = 0x0C,
     DecC = 0x0D,
     LdCX = 0x0E,
+    RRCA = 0x0F,
 
+    DjnzX = 0x10,
     LdDEXX = 0x11,
     LdVDEA = 0x12,
     IncDE = 0x13,
     IncD = 0x14,
     DecD = 0x15,
     LdDX = 0x16,
+    RLA = 0x17,
+    JrX = 0x18,
     AddHLDE = 0x19,
     LdAVDE = 0x1A,
     DecDE = 0x1B,
     IncE = 0x1C,
     DecE = 0x1D,
     LdEX = 0x1E,
+    RRA = 0x1F,
 
+    JrNZX = 0x20,
     LdHLXX = 0x21,
     LdVXXHL = 0x22,
     IncHL = 0x23,
     IncH = 0x24,
     DecH = 0x25,
     LdHX = 0x26,
+    DAA = 0x27,
+    JrZX = 0x28,
     AddHLHL = 0x29,
     LdHLVXX = 0x2A,
     DecHL = 0x2B,
@@ -44,11 +55,15 @@ pub enum Opcode {
     LdLX = 0x2E,
     CPL = 0x2F,
 
+    JrNCX = 0x30,
     LdSPXX = 0x31,
     LdVXXA = 0x32,
     IncSP = 0x33,
+    IncVHL = 0x34,
+    DecVHL = 0x35,
     LdVHLX = 0x36,
     SCF = 0x37,
+    JrCX = 0x38,
     AddHLSP = 0x39,
     LdAVXX = 0x3A,
     DecSP = 0x3B,
@@ -131,6 +146,7 @@ pub enum Opcode {
     AddE = 0x83,
     AddH = 0x84,
     AddL = 0x85,
+    AddVHL = 0x86,
     AddA = 0x87,
     AdcB = 0x88,
     AdcC = 0x89,
@@ -138,6 +154,7 @@ pub enum Opcode {
     AdcE = 0x8B,
     AdcH = 0x8C,
     AdcL = 0x8D,
+    AdcVHL = 0x8E,
     AdcA = 0x8F,
 
     SubB = 0x90,
@@ -146,6 +163,7 @@ pub enum Opcode {
     SubE = 0x93,
     SubH = 0x94,
     SubL = 0x95,
+    SubVHL = 0x96,
     SubA = 0x97,
     SbcB = 0x98,
     SbcC = 0x99,
@@ -153,6 +171,7 @@ pub enum Opcode {
     SbcE = 0x9B,
     SbcH = 0x9C,
     SbcL = 0x9D,
+    SbcVHL = 0x9E,
     SbcA = 0x9F,
 
     AndB = 0xA0,
@@ -161,6 +180,7 @@ pub enum Opcode {
     AndE = 0xA3,
     AndH = 0xA4,
     AndL = 0xA5,
+    AndVHL = 0xA6,
     AndA = 0xA7,
     XorB = 0xA8,
     XorC = 0xA9,
@@ -168,6 +188,7 @@ pub enum Opcode {
     XorE = 0xAB,
     XorH = 0xAC,
     XorL = 0xAD,
+    XorVHL = 0xAE,
     XorA = 0xAF,
 
     OrB = 0xB0,
@@ -176,7 +197,16 @@ pub enum Opcode {
     OrE = 0xB3,
     OrH = 0xB4,
     OrL = 0xB5,
+    OrVHL = 0xB6,
     OrA = 0xB7,
+    CpB = 0xB8,
+    CpC = 0xB9,
+    CpD = 0xBA,
+    CpE = 0xBB,
+    CpH = 0xBC,
+    CpL = 0xBD,
+    CpVHL = 0xBE,
+    CpA = 0xBF,
 
     RetNZ = 0xC0,
     PopBC = 0xC1,
@@ -184,21 +214,33 @@ pub enum Opcode {
     JpXX = 0xC3,
     CallNZXX = 0xC4,
     PushBC = 0xC5,
+    AddX = 0xC6,
+    Rst00 = 0xC7,
     RetZ = 0xC8,
     Ret = 0xC9,
     JpZXX = 0xCA,
+    PrefixCB = 0xCB,
     CallZXX = 0xCC,
     CallXX = 0xCD,
+    AdcX = 0xCE,
+    Rst08 = 0xCF,
 
     RetNC = 0xD0,
     PopDE = 0xD1,
     JpNCXX = 0xD2,
+    OutVXA = 0xD3,
     CallNCXX = 0xD4,
     PushDE = 0xD5,
+    SubX = 0xD6,
+    Rst10 = 0xD7,
     RetC = 0xD8,
     Exx = 0xD9,
     JpCXX = 0xDA,
+    InAVX = 0xDB,
     CallCXX = 0xDC,
+    PrefixDD = 0xDD,
+    SbcX = 0xDE,
+    Rst18 = 0xDF,
 
     RetPO = 0xE0,
     PopHL = 0xE1,
@@ -207,25 +249,549 @@ pub enum Opcode {
     CallPOXX = 0xE4,
     PushHL = 0xE5,
     AndX = 0xE6,
+    Rst20 = 0xE7,
     RetPE = 0xE8,
+    JpHL = 0xE9,
     JpPEXX = 0xEA,
     ExDEHL = 0xEB,
     CallPEXX = 0xEC,
+    PrefixED = 0xED,
     XorX = 0xEE,
+    Rst28 = 0xEF,
 
     RetP = 0xF0,
     PopAF = 0xF1,
     JpPXX = 0xF2,
+    Di = 0xF3,
     CallPXX = 0xF4,
     PushAF = 0xF5,
     OrX = 0xF6,
+    Rst30 = 0xF7,
     RetM = 0xF8,
+    LdSPHL = 0xF9,
     JpMXX = 0xFA,
+    Ei = 0xFB,
     CallMXX = 0xFC,
+    PrefixFD = 0xFD,
+    CpX = 0xFE,
+    Rst38 = 0xFF,
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Opcode::Nop),
+            0x01 => Ok(Opcode::LdBCXX),
+            0x02 => Ok(Opcode::LdVBCA),
+            0x03 => Ok(Opcode::IncBC),
+            0x04 => Ok(Opcode::IncB),
+            0x05 => Ok(Opcode::DecB),
+            0x06 => Ok(Opcode::LdBX),
+            0x07 => Ok(Opcode::RLCA),
+            0x08 => Ok(Opcode::ExAFAF),
+            0x09 => Ok(Opcode::AddHLBC),
+            0x0A => Ok(Opcode::LdAVBC),
+            0x0B => Ok(Opcode::DecBC),
+            0x0C => Ok(Opcode::IncC),
+            0x0D => Ok(Opcode::DecC),
+            0x0E => Ok(Opcode::LdCX),
+            0x0F => Ok(Opcode::RRCA),
+            0x10 => Ok(Opcode::DjnzX),
+            0x11 => Ok(Opcode::LdDEXX),
+            0x12 => Ok(Opcode::LdVDEA),
+            0x13 => Ok(Opcode::IncDE),
+            0x14 => Ok(Opcode::IncD),
+            0x15 => Ok(Opcode::DecD),
+            0x16 => Ok(Opcode::LdDX),
+            0x17 => Ok(Opcode::RLA),
+            0x18 => Ok(Opcode::JrX),
+            0x19 => Ok(Opcode::AddHLDE),
+            0x1A => Ok(Opcode::LdAVDE),
+            0x1B => Ok(Opcode::DecDE),
+            0x1C => Ok(Opcode::IncE),
+            0x1D => Ok(Opcode::DecE),
+            0x1E => Ok(Opcode::LdEX),
+            0x1F => Ok(Opcode::RRA),
+            0x20 => Ok(Opcode::JrNZX),
+            0x21 => Ok(Opcode::LdHLXX),
+            0x22 => Ok(Opcode::LdVXXHL),
+            0x23 => Ok(Opcode::IncHL),
+            0x24 => Ok(Opcode::IncH),
+            0x25 => Ok(Opcode::DecH),
+            0x26 => Ok(Opcode::LdHX),
+            0x27 => Ok(Opcode::DAA),
+            0x28 => Ok(Opcode::JrZX),
+            0x29 => Ok(Opcode::AddHLHL),
+            0x2A => Ok(Opcode::LdHLVXX),
+            0x2B => Ok(Opcode::DecHL),
+            0x2C => Ok(Opcode::IncL),
+            0x2D => Ok(Opcode::DecL),
+            0x2E => Ok(Opcode::LdLX),
+            0x2F => Ok(Opcode::CPL),
+            0x30 => Ok(Opcode::JrNCX),
+            0x31 => Ok(Opcode::LdSPXX),
+            0x32 => Ok(Opcode::LdVXXA),
+            0x33 => Ok(Opcode::IncSP),
+            0x34 => Ok(Opcode::IncVHL),
+            0x35 => Ok(Opcode::DecVHL),
+            0x36 => Ok(Opcode::LdVHLX),
+            0x37 => Ok(Opcode::SCF),
+            0x38 => Ok(Opcode::JrCX),
+            0x39 => Ok(Opcode::AddHLSP),
+            0x3A => Ok(Opcode::LdAVXX),
+            0x3B => Ok(Opcode::DecSP),
+            0x3C => Ok(Opcode::IncA),
+            0x3D => Ok(Opcode::DecA),
+            0x3E => Ok(Opcode::LdAX),
+            0x3F => Ok(Opcode::CCF),
+            0x40 => Ok(Opcode::LdBB),
+            0x41 => Ok(Opcode::LdBC),
+            0x42 => Ok(Opcode::LdBD),
+            0x43 => Ok(Opcode::LdBE),
+            0x44 => Ok(Opcode::LdBH),
+            0x45 => Ok(Opcode::LdBL),
+            0x46 => Ok(Opcode::LdBHL),
+            0x47 => Ok(Opcode::LdBA),
+            0x48 => Ok(Opcode::LdCB),
+            0x49 => Ok(Opcode::LdCC),
+            0x4A => Ok(Opcode::LdCD),
+            0x4B => Ok(Opcode::LdCE),
+            0x4C => Ok(Opcode::LdCH),
+            0x4D => Ok(Opcode::LdCL),
+            0x4E => Ok(Opcode::LdCHL),
+            0x4F => Ok(Opcode::LdCA),
+            0x50 => Ok(Opcode::LdDB),
+            0x51 => Ok(Opcode::LdDC),
+            0x52 => Ok(Opcode::LdDD),
+            0x53 => Ok(Opcode::LdDE),
+            0x54 => Ok(Opcode::LdDH),
+            0x55 => Ok(Opcode::LdDL),
+            0x56 => Ok(Opcode::LdDHL),
+            0x57 => Ok(Opcode::LdDA),
+            0x58 => Ok(Opcode::LdEB),
+            0x59 => Ok(Opcode::LdEC),
+            0x5A => Ok(Opcode::LdED),
+            0x5B => Ok(Opcode::LdEE),
+            0x5C => Ok(Opcode::LdEH),
+            0x5D => Ok(Opcode::LdEL),
+            0x5E => Ok(Opcode::LdEHL),
+            0x5F => Ok(Opcode::LdEA),
+            0x60 => Ok(Opcode::LdHB),
+            0x61 => Ok(Opcode::LdHC),
+            0x62 => Ok(Opcode::LdHD),
+            0x63 => Ok(Opcode::LdHE),
+            0x64 => Ok(Opcode::LdHH),
+            0x65 => Ok(Opcode::LdHL),
+            0x66 => Ok(Opcode::LdHHL),
+            0x67 => Ok(Opcode::LdHA),
+            0x68 => Ok(Opcode::LdLB),
+            0x69 => Ok(Opcode::LdLC),
+            0x6A => Ok(Opcode::LdLD),
+            0x6B => Ok(Opcode::LdLE),
+            0x6C => Ok(Opcode::LdLH),
+            0x6D => Ok(Opcode::LdLL),
+            0x6E => Ok(Opcode::LdLHL),
+            0x6F => Ok(Opcode::LdLA),
+            0x70 => Ok(Opcode::LdHLB),
+            0x71 => Ok(Opcode::LdHLC),
+            0x72 => Ok(Opcode::LdHLD),
+            0x73 => Ok(Opcode::LdHLE),
+            0x74 => Ok(Opcode::LdHLH),
+            0x75 => Ok(Opcode::LdHLL),
+            0x76 => Ok(Opcode::Halt),
+            0x77 => Ok(Opcode::LdHLA),
+            0x78 => Ok(Opcode::LdAB),
+            0x79 => Ok(Opcode::LdAC),
+            0x7A => Ok(Opcode::LdAD),
+            0x7B => Ok(Opcode::LdAE),
+            0x7C => Ok(Opcode::LdAH),
+            0x7D => Ok(Opcode::LdAL),
+            0x7E => Ok(Opcode::LdAHL),
+            0x7F => Ok(Opcode::LdAA),
+            0x80 => Ok(Opcode::AddB),
+            0x81 => Ok(Opcode::AddC),
+            0x82 => Ok(Opcode::AddD),
+            0x83 => Ok(Opcode::AddE),
+            0x84 => Ok(Opcode::AddH),
+            0x85 => Ok(Opcode::AddL),
+            0x86 => Ok(Opcode::AddVHL),
+            0x87 => Ok(Opcode::AddA),
+            0x88 => Ok(Opcode::AdcB),
+            0x89 => Ok(Opcode::AdcC),
+            0x8A => Ok(Opcode::AdcD),
+            0x8B => Ok(Opcode::AdcE),
+            0x8C => Ok(Opcode::AdcH),
+            0x8D => Ok(Opcode::AdcL),
+            0x8E => Ok(Opcode::AdcVHL),
+            0x8F => Ok(Opcode::AdcA),
+            0x90 => Ok(Opcode::SubB),
+            0x91 => Ok(Opcode::SubC),
+            0x92 => Ok(Opcode::SubD),
+            0x93 => Ok(Opcode::SubE),
+            0x94 => Ok(Opcode::SubH),
+            0x95 => Ok(Opcode::SubL),
+            0x96 => Ok(Opcode::SubVHL),
+            0x97 => Ok(Opcode::SubA),
+            0x98 => Ok(Opcode::SbcB),
+            0x99 => Ok(Opcode::SbcC),
+            0x9A => Ok(Opcode::SbcD),
+            0x9B => Ok(Opcode::SbcE),
+            0x9C => Ok(Opcode::SbcH),
+            0x9D => Ok(Opcode::SbcL),
+            0x9E => Ok(Opcode::SbcVHL),
+            0x9F => Ok(Opcode::SbcA),
+            0xA0 => Ok(Opcode::AndB),
+            0xA1 => Ok(Opcode::AndC),
+            0xA2 => Ok(Opcode::AndD),
+            0xA3 => Ok(Opcode::AndE),
+            0xA4 => Ok(Opcode::AndH),
+            0xA5 => Ok(Opcode::AndL),
+            0xA6 => Ok(Opcode::AndVHL),
+            0xA7 => Ok(Opcode::AndA),
+            0xA8 => Ok(Opcode::XorB),
+            0xA9 => Ok(Opcode::XorC),
+            0xAA => Ok(Opcode::XorD),
+            0xAB => Ok(Opcode::XorE),
+            0xAC => Ok(Opcode::XorH),
+            0xAD => Ok(Opcode::XorL),
+            0xAE => Ok(Opcode::XorVHL),
+            0xAF => Ok(Opcode::XorA),
+            0xB0 => Ok(Opcode::OrB),
+            0xB1 => Ok(Opcode::OrC),
+            0xB2 => Ok(Opcode::OrD),
+            0xB3 => Ok(Opcode::OrE),
+            0xB4 => Ok(Opcode::OrH),
+            0xB5 => Ok(Opcode::OrL),
+            0xB6 => Ok(Opcode::OrVHL),
+            0xB7 => Ok(Opcode::OrA),
+            0xB8 => Ok(Opcode::CpB),
+            0xB9 => Ok(Opcode::CpC),
+            0xBA => Ok(Opcode::CpD),
+            0xBB => Ok(Opcode::CpE),
+            0xBC => Ok(Opcode::CpH),
+            0xBD => Ok(Opcode::CpL),
+            0xBE => Ok(Opcode::CpVHL),
+            0xBF => Ok(Opcode::CpA),
+            0xC0 => Ok(Opcode::RetNZ),
+            0xC1 => Ok(Opcode::PopBC),
+            0xC2 => Ok(Opcode::JpNZXX),
+            0xC3 => Ok(Opcode::JpXX),
+            0xC4 => Ok(Opcode::CallNZXX),
+            0xC5 => Ok(Opcode::PushBC),
+            0xC6 => Ok(Opcode::AddX),
+            0xC7 => Ok(Opcode::Rst00),
+            0xC8 => Ok(Opcode::RetZ),
+            0xC9 => Ok(Opcode::Ret),
+            0xCA => Ok(Opcode::JpZXX),
+            0xCB => Ok(Opcode::PrefixCB),
+            0xCC => Ok(Opcode::CallZXX),
+            0xCD => Ok(Opcode::CallXX),
+            0xCE => Ok(Opcode::AdcX),
+            0xCF => Ok(Opcode::Rst08),
+            0xD0 => Ok(Opcode::RetNC),
+            0xD1 => Ok(Opcode::PopDE),
+            0xD2 => Ok(Opcode::JpNCXX),
+            0xD3 => Ok(Opcode::OutVXA),
+            0xD4 => Ok(Opcode::CallNCXX),
+            0xD5 => Ok(Opcode::PushDE),
+            0xD6 => Ok(Opcode::SubX),
+            0xD7 => Ok(Opcode::Rst10),
+            0xD8 => Ok(Opcode::RetC),
+            0xD9 => Ok(Opcode::Exx),
+            0xDA => Ok(Opcode::JpCXX),
+            0xDB => Ok(Opcode::InAVX),
+            0xDC => Ok(Opcode::CallCXX),
+            0xDD => Ok(Opcode::PrefixDD),
+            0xDE => Ok(Opcode::SbcX),
+            0xDF => Ok(Opcode::Rst18),
+            0xE0 => Ok(Opcode::RetPO),
+            0xE1 => Ok(Opcode::PopHL),
+            0xE2 => Ok(Opcode::JpPOXX),
+            0xE3 => Ok(Opcode::ExVSPHL),
+            0xE4 => Ok(Opcode::CallPOXX),
+            0xE5 => Ok(Opcode::PushHL),
+            0xE6 => Ok(Opcode::AndX),
+            0xE7 => Ok(Opcode::Rst20),
+            0xE8 => Ok(Opcode::RetPE),
+            0xE9 => Ok(Opcode::JpHL),
+            0xEA => Ok(Opcode::JpPEXX),
+            0xEB => Ok(Opcode::ExDEHL),
+            0xEC => Ok(Opcode::CallPEXX),
+            0xED => Ok(Opcode::PrefixED),
+            0xEE => Ok(Opcode::XorX),
+            0xEF => Ok(Opcode::Rst28),
+            0xF0 => Ok(Opcode::RetP),
+            0xF1 => Ok(Opcode::PopAF),
+            0xF2 => Ok(Opcode::JpPXX),
+            0xF3 => Ok(Opcode::Di),
+            0xF4 => Ok(Opcode::CallPXX),
+            0xF5 => Ok(Opcode::PushAF),
+            0xF6 => Ok(Opcode::OrX),
+            0xF7 => Ok(Opcode::Rst30),
+            0xF8 => Ok(Opcode::RetM),
+            0xF9 => Ok(Opcode::LdSPHL),
+            0xFA => Ok(Opcode::JpMXX),
+            0xFB => Ok(Opcode::Ei),
+            0xFC => Ok(Opcode::CallMXX),
+            0xFD => Ok(Opcode::PrefixFD),
+            0xFE => Ok(Opcode::CpX),
+            0xFF => Ok(Opcode::Rst38),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    IndirectHL,
+    A,
 }
 
-impl From<u8> for Opcode {
+impl Operand {
+    pub fn from_bits(value: u8) -> Operand {
+        match value & 0x07 {
+            0 => Operand::B,
+            1 => Operand::C,
+            2 => Operand::D,
+            3 => Operand::E,
+            4 => Operand::H,
+            5 => Operand::L,
+            6 => Operand::IndirectHL,
+            _ => Operand::A,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            Operand::B => 0,
+            Operand::C => 1,
+            Operand::D => 2,
+            Operand::E => 3,
+            Operand::H => 4,
+            Operand::L => 5,
+            Operand::IndirectHL => 6,
+            Operand::A => 7,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitOpcode {
+    Rlc(Operand),
+    Rrc(Operand),
+    Rl(Operand),
+    Rr(Operand),
+    Sla(Operand),
+    Sra(Operand),
+    Sll(Operand),
+    Srl(Operand),
+    Bit(u8, Operand),
+    Res(u8, Operand),
+    Set(u8, Operand),
+}
+
+impl From<u8> for BitOpcode {
     fn from(value: u8) -> Self {
-        unsafe { ::std::mem::transmute_copy::<u8, Opcode>(&value) }
+        let operand = Operand::from_bits(value);
+        let selector = (value >> 3) & 0x07;
+        match value >> 6 {
+            0 => match selector {
+                0 => BitOpcode::Rlc(operand),
+                1 => BitOpcode::Rrc(operand),
+                2 => BitOpcode::Rl(operand),
+                3 => BitOpcode::Rr(operand),
+                4 => BitOpcode::Sla(operand),
+                5 => BitOpcode::Sra(operand),
+                6 => BitOpcode::Sll(operand),
+                _ => BitOpcode::Srl(operand),
+            },
+            1 => BitOpcode::Bit(selector, operand),
+            2 => BitOpcode::Res(selector, operand),
+            _ => BitOpcode::Set(selector, operand),
+        }
+    }
+}
+
+impl From<BitOpcode> for u8 {
+    fn from(opcode: BitOpcode) -> Self {
+        let (group, selector, operand) = match opcode {
+            BitOpcode::Rlc(operand) => (0, 0, operand),
+            BitOpcode::Rrc(operand) => (0, 1, operand),
+            BitOpcode::Rl(operand) => (0, 2, operand),
+            BitOpcode::Rr(operand) => (0, 3, operand),
+            BitOpcode::Sla(operand) => (0, 4, operand),
+            BitOpcode::Sra(operand) => (0, 5, operand),
+            BitOpcode::Sll(operand) => (0, 6, operand),
+            BitOpcode::Srl(operand) => (0, 7, operand),
+            BitOpcode::Bit(bit, operand) => (1, bit, operand),
+            BitOpcode::Res(bit, operand) => (2, bit, operand),
+            BitOpcode::Set(bit, operand) => (3, bit, operand),
+        };
+        (group << 6) | ((selector & 0x07) << 3) | operand.bits()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExtendedOpcode {
+    InBVC = 0x40,
+    OutVCB = 0x41,
+    SbcHLBC = 0x42,
+    LdVXXBC = 0x43,
+    Neg = 0x44,
+    Retn = 0x45,
+    Im0 = 0x46,
+    LdIA = 0x47,
+    InCVC = 0x48,
+    OutVCC = 0x49,
+    AdcHLBC = 0x4A,
+    LdBCVXX = 0x4B,
+    Reti = 0x4D,
+    LdRA = 0x4F,
+
+    InDVC = 0x50,
+    OutVCD = 0x51,
+    SbcHLDE = 0x52,
+    LdVXXDE = 0x53,
+    Im1 = 0x56,
+    LdAI = 0x57,
+    InEVC = 0x58,
+    OutVCE = 0x59,
+    AdcHLDE = 0x5A,
+    LdDEVXX = 0x5B,
+    Im2 = 0x5E,
+    LdAR = 0x5F,
+
+    InHVC = 0x60,
+    OutVCH = 0x61,
+    SbcHLHL = 0x62,
+    LdVXXHL = 0x63,
+    Rrd = 0x67,
+    InLVC = 0x68,
+    OutVCL = 0x69,
+    AdcHLHL = 0x6A,
+    LdHLVXX = 0x6B,
+    Rld = 0x6F,
+
+    InVC = 0x70,
+    OutVC0 = 0x71,
+    SbcHLSP = 0x72,
+    LdVXXSP = 0x73,
+    InAVC = 0x78,
+    OutVCA = 0x79,
+    AdcHLSP = 0x7A,
+    LdSPVXX = 0x7B,
+
+    Ldi = 0xA0,
+    Cpi = 0xA1,
+    Ini = 0xA2,
+    Outi = 0xA3,
+    Ldd = 0xA8,
+    Cpd = 0xA9,
+    Ind = 0xAA,
+    Outd = 0xAB,
+
+    Ldir = 0xB0,
+    Cpir = 0xB1,
+    Inir = 0xB2,
+    Otir = 0xB3,
+    Lddr = 0xB8,
+    Cpdr = 0xB9,
+    Indr = 0xBA,
+    Otdr = 0xBB,
+}
+
+impl TryFrom<u8> for ExtendedOpcode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x40 => Ok(ExtendedOpcode::InBVC),
+            0x41 => Ok(ExtendedOpcode::OutVCB),
+            0x42 => Ok(ExtendedOpcode::SbcHLBC),
+            0x43 => Ok(ExtendedOpcode::LdVXXBC),
+            0x44 => Ok(ExtendedOpcode::Neg),
+            0x45 => Ok(ExtendedOpcode::Retn),
+            0x46 => Ok(ExtendedOpcode::Im0),
+            0x47 => Ok(ExtendedOpcode::LdIA),
+            0x48 => Ok(ExtendedOpcode::InCVC),
+            0x49 => Ok(ExtendedOpcode::OutVCC),
+            0x4A => Ok(ExtendedOpcode::AdcHLBC),
+            0x4B => Ok(ExtendedOpcode::LdBCVXX),
+            0x4C => Ok(ExtendedOpcode::Neg),
+            0x4D => Ok(ExtendedOpcode::Reti),
+            0x4E => Ok(ExtendedOpcode::Im0),
+            0x4F => Ok(ExtendedOpcode::LdRA),
+            0x50 => Ok(ExtendedOpcode::InDVC),
+            0x51 => Ok(ExtendedOpcode::OutVCD),
+            0x52 => Ok(ExtendedOpcode::SbcHLDE),
+            0x53 => Ok(ExtendedOpcode::LdVXXDE),
+            0x54 => Ok(ExtendedOpcode::Neg),
+            0x55 => Ok(ExtendedOpcode::Retn),
+            0x56 => Ok(ExtendedOpcode::Im1),
+            0x57 => Ok(ExtendedOpcode::LdAI),
+            0x58 => Ok(ExtendedOpcode::InEVC),
+            0x59 => Ok(ExtendedOpcode::OutVCE),
+            0x5A => Ok(ExtendedOpcode::AdcHLDE),
+            0x5B => Ok(ExtendedOpcode::LdDEVXX),
+            0x5C => Ok(ExtendedOpcode::Neg),
+            0x5D => Ok(ExtendedOpcode::Retn),
+            0x5E => Ok(ExtendedOpcode::Im2),
+            0x5F => Ok(ExtendedOpcode::LdAR),
+            0x60 => Ok(ExtendedOpcode::InHVC),
+            0x61 => Ok(ExtendedOpcode::OutVCH),
+            0x62 => Ok(ExtendedOpcode::SbcHLHL),
+            0x63 => Ok(ExtendedOpcode::LdVXXHL),
+            0x64 => Ok(ExtendedOpcode::Neg),
+            0x65 => Ok(ExtendedOpcode::Retn),
+            0x66 => Ok(ExtendedOpcode::Im0),
+            0x67 => Ok(ExtendedOpcode::Rrd),
+            0x68 => Ok(ExtendedOpcode::InLVC),
+            0x69 => Ok(ExtendedOpcode::OutVCL),
+            0x6A => Ok(ExtendedOpcode::AdcHLHL),
+            0x6B => Ok(ExtendedOpcode::LdHLVXX),
+            0x6C => Ok(ExtendedOpcode::Neg),
+            0x6D => Ok(ExtendedOpcode::Retn),
+            0x6E => Ok(ExtendedOpcode::Im0),
+            0x6F => Ok(ExtendedOpcode::Rld),
+            0x70 => Ok(ExtendedOpcode::InVC),
+            0x71 => Ok(ExtendedOpcode::OutVC0),
+            0x72 => Ok(ExtendedOpcode::SbcHLSP),
+            0x73 => Ok(ExtendedOpcode::LdVXXSP),
+            0x74 => Ok(ExtendedOpcode::Neg),
+            0x75 => Ok(ExtendedOpcode::Retn),
+            0x76 => Ok(ExtendedOpcode::Im1),
+            0x78 => Ok(ExtendedOpcode::InAVC),
+            0x79 => Ok(ExtendedOpcode::OutVCA),
+            0x7A => Ok(ExtendedOpcode::AdcHLSP),
+            0x7B => Ok(ExtendedOpcode::LdSPVXX),
+            0x7C => Ok(ExtendedOpcode::Neg),
+            0x7D => Ok(ExtendedOpcode::Retn),
+            0x7E => Ok(ExtendedOpcode::Im2),
+            0xA0 => Ok(ExtendedOpcode::Ldi),
+            0xA1 => Ok(ExtendedOpcode::Cpi),
+            0xA2 => Ok(ExtendedOpcode::Ini),
+            0xA3 => Ok(ExtendedOpcode::Outi),
+            0xA8 => Ok(ExtendedOpcode::Ldd),
+            0xA9 => Ok(ExtendedOpcode::Cpd),
+            0xAA => Ok(ExtendedOpcode::Ind),
+            0xAB => Ok(ExtendedOpcode::Outd),
+            0xB0 => Ok(ExtendedOpcode::Ldir),
+            0xB1 => Ok(ExtendedOpcode::Cpir),
+            0xB2 => Ok(ExtendedOpcode::Inir),
+            0xB3 => Ok(ExtendedOpcode::Otir),
+            0xB8 => Ok(ExtendedOpcode::Lddr),
+            0xB9 => Ok(ExtendedOpcode::Cpdr),
+            0xBA => Ok(ExtendedOpcode::Indr),
+            0xBB => Ok(ExtendedOpcode::Otdr),
+            _ => Err(value),
+        }
     }
 }
@@ -0,0 +1,118 @@
+use vm::cpu::alu;
+use vm::cpu::flags::Flag;
+use vm::instructions::opcodes::Operand;
+use vm::machine::Machine;
+
+pub(crate) type ShiftOperation = fn(u8, bool) -> (u8, bool);
+
+pub(crate) fn rotate_left_circular(value: u8, _: bool) -> (u8, bool) {
+    (value.rotate_left(1), value & 0x80 != 0)
+}
+
+pub(crate) fn rotate_right_circular(value: u8, _: bool) -> (u8, bool) {
+    (value.rotate_right(1), value & 0x01 != 0)
+}
+
+pub(crate) fn rotate_left(value: u8, carry: bool) -> (u8, bool) {
+    ((value << 1) | alu::get_bit::<u8>(carry), value & 0x80 != 0)
+}
+
+pub(crate) fn rotate_right(value: u8, carry: bool) -> (u8, bool) {
+    (
+        (value >> 1) | (alu::get_bit::<u8>(carry) << 7),
+        value & 0x01 != 0,
+    )
+}
+
+pub(crate) fn shift_left_arithmetic(value: u8, _: bool) -> (u8, bool) {
+    (value << 1, value & 0x80 != 0)
+}
+
+pub(crate) fn shift_right_arithmetic(value: u8, _: bool) -> (u8, bool) {
+    ((value >> 1) | (value & 0x80), value & 0x01 != 0)
+}
+
+// Undocumented: shifts a one into bit 0.
+pub(crate) fn shift_left_logical(value: u8, _: bool) -> (u8, bool) {
+    ((value << 1) | 0x01, value & 0x80 != 0)
+}
+
+pub(crate) fn shift_right_logical(value: u8, _: bool) -> (u8, bool) {
+    (value >> 1, value & 0x01 != 0)
+}
+
+impl Machine {
+    pub(crate) fn rotate_accumulator(&mut self, operation: ShiftOperation) {
+        let carry = Flag::Carry.get(&self.cpu.state.status);
+        let (result, carry) = operation(self.cpu.state.registers.a, carry);
+        self.cpu.state.registers.a = result;
+        let status = &mut self.cpu.state.status;
+        Flag::Carry.set(status, carry);
+        Flag::HalfCarry.set(status, false);
+        Flag::AddSubtract.set(status, false);
+        self.clock(4);
+    }
+
+    pub(crate) fn shift_operand(&mut self, operation: ShiftOperation, operand: Operand) {
+        let value = self.read_operand(operand);
+        let result = self.shift_value(operation, value);
+        self.write_operand(operand, result);
+        if operand == Operand::IndirectHL {
+            self.clock(15);
+        } else {
+            self.clock(8);
+        }
+    }
+
+    // Undocumented: unless the operand is (HL), the result is also copied
+    // into the register the opcode names.
+    pub(crate) fn shift_indexed(&mut self, operation: ShiftOperation, address: u16, copy: Operand) {
+        let value = self.bus.read_u8(address);
+        let result = self.shift_value(operation, value);
+        self.bus.write_u8(address, result);
+        if copy != Operand::IndirectHL {
+            self.write_operand(copy, result);
+        }
+        self.clock(23);
+    }
+
+    pub(crate) fn rotate_digit_left(&mut self) {
+        let address = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let value = self.bus.read_u8(address);
+        let a = self.cpu.state.registers.a;
+        self.bus.write_u8(address, (value << 4) | (a & 0x0F));
+        self.set_digit_rotation_result((a & 0xF0) | (value >> 4));
+    }
+
+    pub(crate) fn rotate_digit_right(&mut self) {
+        let address = self.cpu.get_register_pair(|regs| (regs.h, regs.l));
+        let value = self.bus.read_u8(address);
+        let a = self.cpu.state.registers.a;
+        self.bus.write_u8(address, (a << 4) | (value >> 4));
+        self.set_digit_rotation_result((a & 0xF0) | (value & 0x0F));
+    }
+
+    fn set_digit_rotation_result(&mut self, result: u8) {
+        self.cpu.state.registers.a = result;
+        let status = &mut self.cpu.state.status;
+        Flag::HalfCarry.set(status, false);
+        Flag::AddSubtract.set(status, false);
+        Flag::ParityOverflow.set(status, alu::parity(result));
+        Flag::Zero.set(status, result == 0x00);
+        Flag::Sign.set(status, result > 0x7F);
+        self.clock(18);
+    }
+
+    fn shift_value(&mut self, operation: ShiftOperation, value: u8) -> u8 {
+        let carry = Flag::Carry.get(&self.cpu.state.status);
+        let (result, carry) = operation(value, carry);
+        let status = &mut self.cpu.state.status;
+        Flag::Carry.set(status, carry);
+        Flag::HalfCarry.set(status, false);
+        Flag::AddSubtract.set(status, false);
+        Flag::ParityOverflow.set(status, alu::parity(result));
+        Flag::Zero.set(status, result == 0x00);
+        Flag::Sign.set(status, result > 0x7F);
+        result
+    }
+}
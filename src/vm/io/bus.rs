@@ -0,0 +1,52 @@
+use vm::io::port::{Access, PortDevice, PortRange};
+
+struct Mapping {
+    range: PortRange,
+    access: Access,
+    device: Box<dyn PortDevice>,
+}
+
+pub struct IoBus {
+    mappings: Vec<Mapping>,
+}
+
+impl IoBus {
+    pub fn new() -> IoBus {
+        IoBus {
+            mappings: Vec::new(),
+        }
+    }
+
+    // Later attachments take precedence, so a device can shadow part of a
+    // wider range that was registered before it.
+    pub fn attach(&mut self, range: PortRange, access: Access, device: Box<dyn PortDevice>) {
+        self.mappings.push(Mapping {
+            range,
+            access,
+            device,
+        });
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        let mapping = self
+            .mappings
+            .iter_mut()
+            .rev()
+            .find(|m| m.access.allows_read() && m.range.contains(port));
+        match mapping {
+            Some(mapping) => mapping.device.read(port),
+            None => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, value: u8) {
+        let mapping = self
+            .mappings
+            .iter_mut()
+            .rev()
+            .find(|m| m.access.allows_write() && m.range.contains(port));
+        if let Some(mapping) = mapping {
+            mapping.device.write(port, value);
+        }
+    }
+}
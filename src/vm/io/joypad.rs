@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use vm::io::port::PortDevice;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+use vm::video::vdp::Vdp;
+
+pub const PORT_A: u8 = 0xDC;
+pub const PORT_B: u8 = 0xDD;
+pub const IO_CONTROL: u8 = 0x3F;
+
+// Bits of the I/O control register: the low nibble sets each pin's
+// direction (1 is input), the high nibble the level driven on outputs.
+const TR_A_INPUT: u8 = 0x01;
+const TH_A_INPUT: u8 = 0x02;
+const TR_B_INPUT: u8 = 0x04;
+const TH_B_INPUT: u8 = 0x08;
+const TR_A_OUTPUT: u8 = 0x10;
+const TH_A_OUTPUT: u8 = 0x20;
+const TR_B_OUTPUT: u8 = 0x40;
+const TH_B_OUTPUT: u8 = 0x80;
+
+// Player one's pad is the one in port A, player two's the one in port B.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Player {
+    One,
+    Two,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Buttons {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub button1: bool,
+    pub button2: bool,
+}
+
+impl Buttons {
+    // Pressed buttons pull their lines low.
+    fn lines(self) -> u8 {
+        let pressed = [
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+            self.button1,
+            self.button2,
+        ];
+        pressed.iter().enumerate().fold(
+            0x3F,
+            |lines, (bit, &pressed)| {
+                if pressed {
+                    lines & !(1 << bit)
+                } else {
+                    lines
+                }
+            },
+        )
+    }
+}
+
+pub struct Joypads {
+    pads: [Buttons; 2],
+    reset: bool,
+    control: u8,
+    // Japanese consoles invert TH on its way back to port B, which is how
+    // software tells them apart.
+    japanese: bool,
+    vdp: Rc<RefCell<Vdp>>,
+}
+
+impl Joypads {
+    // The VDP handle lets a rising edge on either TH pin latch the H
+    // counter, as a light gun would.
+    pub fn new(vdp: Rc<RefCell<Vdp>>) -> Joypads {
+        Joypads {
+            pads: [Buttons::default(); 2],
+            reset: false,
+            control: 0xFF,
+            japanese: false,
+            vdp,
+        }
+    }
+
+    pub fn buttons(&self, player: Player) -> Buttons {
+        self.pads[player as usize]
+    }
+
+    pub fn set_buttons(&mut self, player: Player, buttons: Buttons) {
+        self.pads[player as usize] = buttons;
+    }
+
+    pub fn reset_pressed(&self) -> bool {
+        self.reset
+    }
+
+    pub fn set_reset(&mut self, pressed: bool) {
+        self.reset = pressed;
+    }
+
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        let (th_a, th_b) = (self.th_a(), self.th_b());
+        self.control = value;
+        if (!th_a && self.th_a()) || (!th_b && self.th_b()) {
+            self.vdp.borrow_mut().latch_h_counter();
+        }
+    }
+
+    pub fn set_japanese(&mut self, japanese: bool) {
+        self.japanese = japanese;
+    }
+
+    pub fn read_port_a(&self) -> u8 {
+        let a = self.pads[0].lines();
+        let b = self.pads[1].lines();
+        let mut value = (a & 0x3F) | ((b & 0x03) << 6);
+        if self.control & TR_A_INPUT == 0 {
+            value = set_line(value, 0x20, self.control & TR_A_OUTPUT != 0);
+        }
+        value
+    }
+
+    pub fn read_port_b(&self) -> u8 {
+        let b = self.pads[1].lines();
+        let mut value = (b >> 2) | 0x30;
+        if self.reset {
+            value &= !0x10;
+        }
+        if self.control & TR_B_INPUT == 0 {
+            value = set_line(value, 0x08, self.control & TR_B_OUTPUT != 0);
+        }
+        value = set_line(value, 0x40, self.th_read(self.th_a(), TH_A_INPUT));
+        set_line(value, 0x80, self.th_read(self.th_b(), TH_B_INPUT))
+    }
+
+    // An unconnected input floats high.
+    fn th_a(&self) -> bool {
+        self.control & TH_A_INPUT != 0 || self.control & TH_A_OUTPUT != 0
+    }
+
+    fn th_b(&self) -> bool {
+        self.control & TH_B_INPUT != 0 || self.control & TH_B_OUTPUT != 0
+    }
+
+    fn th_read(&self, level: bool, input: u8) -> bool {
+        if self.control & input == 0 && self.japanese {
+            !level
+        } else {
+            level
+        }
+    }
+}
+
+fn set_line(value: u8, mask: u8, high: bool) -> u8 {
+    if high {
+        value | mask
+    } else {
+        value & !mask
+    }
+}
+
+// Ports are decoded on A7, A6 and A0 only: odd ports below 0x40 reach the
+// control register and reads from 0xC0 up return port A or B.
+impl PortDevice for Joypads {
+    fn read(&mut self, port: u8) -> u8 {
+        if port & 0x01 == 0 {
+            self.read_port_a()
+        } else {
+            self.read_port_b()
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        if port & 0x01 != 0 {
+            self.write_control(value);
+        }
+    }
+}
+
+impl Snapshot for Buttons {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u8(self.lines());
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        let lines = input.read_u8()?;
+        let pressed = |bit: u8| lines & (1 << bit) == 0;
+        *self = Buttons {
+            up: pressed(0),
+            down: pressed(1),
+            left: pressed(2),
+            right: pressed(3),
+            button1: pressed(4),
+            button2: pressed(5),
+        };
+        Ok(())
+    }
+}
+
+// The VDP handle is wiring, not state, and stays as it is.
+impl Snapshot for Joypads {
+    fn save_state(&self, out: &mut StateWriter) {
+        self.pads[0].save_state(out);
+        self.pads[1].save_state(out);
+        out.write_bool(self.reset);
+        out.write_u8(self.control);
+        out.write_bool(self.japanese);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.pads[0].load_state(input)?;
+        self.pads[1].load_state(input)?;
+        self.reset = input.read_bool()?;
+        self.control = input.read_u8()?;
+        self.japanese = input.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub trait PortDevice {
+    fn read(&mut self, port: u8) -> u8;
+    fn write(&mut self, port: u8, value: u8);
+}
+
+// Lets one device answer on several ranges while its owner keeps a handle.
+impl<T: PortDevice> PortDevice for Rc<RefCell<T>> {
+    fn read(&mut self, port: u8) -> u8 {
+        self.borrow_mut().read(port)
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        self.borrow_mut().write(port, value)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn allows_read(self) -> bool {
+        self != Access::Write
+    }
+
+    pub fn allows_write(self) -> bool {
+        self != Access::Read
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortRange {
+    Span(u8, u8),
+    Masked { mask: u8, value: u8 },
+}
+
+impl PortRange {
+    pub fn exact(port: u8) -> PortRange {
+        PortRange::Span(port, port)
+    }
+
+    // The Master System only decodes A7, A6 and A0 for its built-in ports.
+    pub fn sms(port: u8) -> PortRange {
+        PortRange::Masked {
+            mask: 0xC1,
+            value: port & 0xC1,
+        }
+    }
+
+    pub fn contains(self, port: u8) -> bool {
+        match self {
+            PortRange::Span(first, last) => first <= port && port <= last,
+            PortRange::Masked { mask, value } => port & mask == value,
+        }
+    }
+}
@@ -1,17 +1,270 @@
 use program::Program;
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
+use vm::audio::opll::Opll;
+use vm::audio::psg::{self, Psg};
+use vm::cartridge::Cartridge;
 use vm::cpu::processor::Processor;
+use vm::instructions::error::ExecError;
+use vm::io::bus::IoBus;
+use vm::io::joypad::{self, Buttons, Joypads, Player};
+use vm::io::port::{Access, PortRange};
+use vm::ram::bus::Bus;
 use vm::ram::memory::Memory;
+use vm::ram::system::SystemBus;
+use vm::rewind::{RewindBuffer, RewindConfig};
+use vm::state::{self, Snapshot, StateError, StateReader, StateWriter};
+use vm::video::timing::TvSystem;
+use vm::video::vdp::Vdp;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Japan,
+    Export,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MachineConfig {
+    // None takes the region from the ROM header.
+    pub region: Option<Region>,
+    pub tv_system: TvSystem,
+    pub sample_rate: u32,
+    // Fits the YM2413 of the Mark III FM unit and the Japanese Master
+    // System.
+    pub fm_sound: bool,
+}
+
+impl Default for MachineConfig {
+    fn default() -> MachineConfig {
+        MachineConfig {
+            region: None,
+            tv_system: TvSystem::Ntsc,
+            sample_rate: psg::DEFAULT_SAMPLE_RATE,
+            fm_sound: false,
+        }
+    }
+}
 
 pub struct Machine {
     pub cpu: Processor,
-    pub ram: Memory,
+    pub bus: Box<dyn Bus>,
+    pub io: IoBus,
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+    vdp: Rc<RefCell<Vdp>>,
+    psg: Rc<RefCell<Psg>>,
+    opll: Option<Rc<RefCell<Opll>>>,
+    joypads: Rc<RefCell<Joypads>>,
+    // Level of the /INT line as driven by anything other than the VDP.
+    irq_line: bool,
+    region: Region,
+    cycles: u64,
+    rewind: Option<RewindBuffer>,
 }
 
 impl Machine {
     pub fn new() -> Machine {
+        Machine::with_bus(Box::new(Memory::new()))
+    }
+
+    pub fn with_bus(bus: Box<dyn Bus>) -> Machine {
+        let vdp = Rc::new(RefCell::new(Vdp::new()));
         Machine {
             cpu: Processor::new(),
-            ram: Memory::new(),
+            bus,
+            io: IoBus::new(),
+            cartridge: None,
+            joypads: Rc::new(RefCell::new(Joypads::new(vdp.clone()))),
+            vdp,
+            psg: Rc::new(RefCell::new(Psg::new(
+                psg::NTSC_CLOCK,
+                psg::DEFAULT_SAMPLE_RATE,
+            ))),
+            opll: None,
+            irq_line: false,
+            region: Region::Export,
+            cycles: 0,
+            rewind: None,
+        }
+    }
+
+    pub fn with_cartridge(cartridge: Cartridge) -> Machine {
+        Machine::with_config(cartridge, MachineConfig::default())
+    }
+
+    pub fn with_config(cartridge: Cartridge, config: MachineConfig) -> Machine {
+        let region = config.region.unwrap_or(match cartridge.header() {
+            Some(header) if header.region.is_japanese() => Region::Japan,
+            _ => Region::Export,
+        });
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let mut machine = Machine::with_bus(Box::new(SystemBus::new(Box::new(cartridge.clone()))));
+        machine.cartridge = Some(cartridge);
+        machine.set_region(region);
+        if config.fm_sound {
+            machine.opll = Some(Rc::new(RefCell::new(Opll::new(
+                psg::NTSC_CLOCK,
+                config.sample_rate,
+            ))));
+        }
+        machine.set_tv_system(config.tv_system);
+        machine.set_sample_rate(config.sample_rate);
+        machine.attach_system_devices();
+        machine
+    }
+
+    // A bare machine keeps its I/O space empty so tests can map their own
+    // devices; a cartridge-backed one gets the Master System's hardware.
+    fn attach_system_devices(&mut self) {
+        self.io.attach(
+            PortRange::Masked {
+                mask: 0xC0,
+                value: 0x80,
+            },
+            Access::ReadWrite,
+            Box::new(self.vdp.clone()),
+        );
+        self.io.attach(
+            PortRange::Masked {
+                mask: 0xC0,
+                value: 0x40,
+            },
+            Access::Read,
+            Box::new(self.vdp.clone()),
+        );
+        self.io.attach(
+            PortRange::Masked {
+                mask: 0xC0,
+                value: 0x40,
+            },
+            Access::Write,
+            Box::new(self.psg.clone()),
+        );
+        for &port in &[joypad::PORT_A, joypad::PORT_B] {
+            self.io.attach(
+                PortRange::sms(port),
+                Access::Read,
+                Box::new(self.joypads.clone()),
+            );
+        }
+        self.io.attach(
+            PortRange::sms(joypad::IO_CONTROL),
+            Access::Write,
+            Box::new(self.joypads.clone()),
+        );
+        if let Some(ref opll) = self.opll {
+            self.io.attach(
+                PortRange::Span(0xF0, 0xF2),
+                Access::ReadWrite,
+                Box::new(opll.clone()),
+            );
+        }
+    }
+
+    pub fn vdp(&self) -> Ref<'_, Vdp> {
+        self.vdp.borrow()
+    }
+
+    pub fn vdp_mut(&self) -> RefMut<'_, Vdp> {
+        self.vdp.borrow_mut()
+    }
+
+    pub fn cartridge(&self) -> Option<&Rc<RefCell<Cartridge>>> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.joypads
+            .borrow_mut()
+            .set_japanese(region == Region::Japan);
+    }
+
+    pub fn input(&self, player: Player) -> Buttons {
+        self.joypads.borrow().buttons(player)
+    }
+
+    pub fn set_input(&mut self, player: Player, buttons: Buttons) {
+        self.joypads.borrow_mut().set_buttons(player, buttons);
+    }
+
+    pub fn set_reset_button(&mut self, pressed: bool) {
+        self.joypads.borrow_mut().set_reset(pressed);
+    }
+
+    // The pause button is wired straight to /NMI.
+    pub fn press_pause(&mut self) {
+        self.raise_nmi();
+    }
+
+    pub fn joypads(&self) -> Ref<'_, Joypads> {
+        self.joypads.borrow()
+    }
+
+    pub fn psg(&self) -> Ref<'_, Psg> {
+        self.psg.borrow()
+    }
+
+    pub fn psg_mut(&self) -> RefMut<'_, Psg> {
+        self.psg.borrow_mut()
+    }
+
+    pub fn opll(&self) -> Option<Ref<'_, Opll>> {
+        self.opll.as_ref().map(|opll| opll.borrow())
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.psg.borrow_mut().set_sample_rate(sample_rate);
+        if let Some(ref opll) = self.opll {
+            opll.borrow_mut().set_sample_rate(sample_rate);
+        }
+    }
+
+    // 16-bit mono PCM at the configured sample rate, generated since the
+    // last call. With the FM unit fitted, bits 0 and 1 of its audio control
+    // register pick the outputs: 0 is PSG only, 1 FM only and 3 both.
+    pub fn take_audio(&mut self) -> Vec<i16> {
+        let mut psg = self.psg.borrow_mut();
+        let mut opll = match self.opll {
+            Some(ref opll) => opll.borrow_mut(),
+            None => return psg.take_samples(),
+        };
+        let count = psg.samples_available().min(opll.samples_available());
+        let control = opll.audio_control();
+        let fm_enabled = control & 0x01 != 0;
+        let psg_enabled = !fm_enabled || control & 0x02 != 0;
+        psg.drain_samples(count)
+            .into_iter()
+            .zip(opll.drain_samples(count))
+            .map(|(square, fm)| {
+                let mut sample = 0;
+                if psg_enabled {
+                    sample += square as i32;
+                }
+                if fm_enabled {
+                    sample += fm as i32;
+                }
+                sample.max(i16::MIN as i32).min(i16::MAX as i32) as i16
+            })
+            .collect()
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.vdp.borrow().tv_system()
+    }
+
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        self.vdp.borrow_mut().set_tv_system(tv_system);
+        let clock_rate = match tv_system {
+            TvSystem::Ntsc => psg::NTSC_CLOCK,
+            TvSystem::Pal => psg::PAL_CLOCK,
+        };
+        self.psg.borrow_mut().set_clock_rate(clock_rate);
+        if let Some(ref opll) = self.opll {
+            opll.borrow_mut().set_clock_rate(clock_rate);
         }
     }
 
@@ -21,27 +274,214 @@ impl Machine {
         let mut address = start_address;
         if will_fit {
             for value in program.raw() {
-                self.ram.write_u8(address, *value);
+                self.bus.write_u8(address, *value);
                 address = address.wrapping_add(1);
             }
         }
         will_fit
     }
 
+    // Loads at the program's origin, 0x0000 unless built with_origin.
     pub fn load(&mut self, program: &Program) -> bool {
-        self.load_at(program, 0)
+        self.load_at(program, program.origin())
     }
 
-    pub fn start_at(&mut self, address: u16) {
+    pub fn start_at(&mut self, address: u16) -> Result<(), ExecError> {
         self.cpu.halt();
         self.cpu.goto(address);
         self.cpu.unhalt();
         while !self.cpu.is_halted() {
-            self.execute();
+            self.execute()?;
+        }
+        Ok(())
+    }
+
+    pub fn start(&mut self) -> Result<(), ExecError> {
+        self.start_at(0)
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn step(&mut self) -> Result<u32, ExecError> {
+        let start = self.cycles;
+        self.execute()?;
+        self.record_rewind();
+        Ok((self.cycles - start) as u32)
+    }
+
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<u64, ExecError> {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    // Runs until the VDP wraps round to the first line of the next frame.
+    pub fn run_frame(&mut self) -> Result<u64, ExecError> {
+        let start = self.cycles;
+        let frame = self.vdp.borrow().frame();
+        while self.vdp.borrow().frame() == frame {
+            self.step()?;
         }
+        Ok(self.cycles - start)
     }
 
-    pub fn start(&mut self) {
-        self.start_at(0);
+    pub fn clock(&mut self, tstates: u8) {
+        self.cycles += tstates as u64;
+        self.vdp.borrow_mut().tick(tstates as u32);
+        self.psg.borrow_mut().tick(tstates as u32);
+        if let Some(ref opll) = self.opll {
+            opll.borrow_mut().tick(tstates as u32);
+        }
+        self.update_irq();
+    }
+
+    pub fn raise_irq(&mut self) {
+        self.irq_line = true;
+        self.update_irq();
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq_line = false;
+        self.update_irq();
+    }
+
+    // /INT is open drain, so any device can hold it low.
+    fn update_irq(&mut self) {
+        if self.irq_line || self.vdp.borrow().interrupt_asserted() {
+            self.cpu.raise_irq();
+        } else {
+            self.cpu.clear_irq();
+        }
+    }
+
+    pub fn raise_nmi(&mut self) {
+        self.cpu.raise_nmi();
+    }
+
+    // Starts recording from the current state. The buffer is not part of
+    // save states.
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        self.rewind = Some(RewindBuffer::new(config));
+        self.record_rewind();
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    // Goes back to the snapshot `count` intervals ago, where 1 is the most
+    // recent one, and returns how many were stepped back, which falls short
+    // when the buffer does not reach that far.
+    pub fn rewind(&mut self, count: usize) -> Result<usize, StateError> {
+        let (stepped, snapshot) = match self.rewind {
+            Some(ref mut buffer) => {
+                let stepped = count.min(buffer.len());
+                match buffer.rewind(count) {
+                    Some(snapshot) => (stepped, snapshot.to_vec()),
+                    None => return Ok(0),
+                }
+            }
+            None => return Ok(0),
+        };
+        self.load_state(&snapshot)?;
+        let frame = self.vdp.borrow().frame();
+        if let Some(ref mut buffer) = self.rewind {
+            buffer.mark(frame, self.cycles);
+        }
+        Ok(stepped)
+    }
+
+    fn record_rewind(&mut self) {
+        let frame = self.vdp.borrow().frame();
+        let due = self
+            .rewind
+            .as_ref()
+            .is_some_and(|buffer| buffer.is_due(frame, self.cycles));
+        if due {
+            let snapshot = self.save_state();
+            if let Some(ref mut buffer) = self.rewind {
+                buffer.push(snapshot, frame, self.cycles);
+            }
+        }
+    }
+
+    // The devices this machine was built with; a snapshot only loads into
+    // a machine with the same ones.
+    fn layout(&self) -> u8 {
+        (self.cartridge.is_some() as u8) | ((self.opll.is_some() as u8) << 1)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        out.write_bytes(&state::MAGIC);
+        out.write_u16(state::VERSION);
+        out.write_u8(self.layout());
+        self.cpu.save_state(&mut out);
+        self.bus.save_state(&mut out);
+        out.write_bool(self.irq_line);
+        out.write_bool(self.region == Region::Japan);
+        out.write_u64(self.cycles);
+        self.vdp.save_state(&mut out);
+        self.psg.save_state(&mut out);
+        if let Some(ref opll) = self.opll {
+            opll.save_state(&mut out);
+        }
+        self.joypads.save_state(&mut out);
+        out.into_bytes()
+    }
+
+    // Either restores the snapshot completely and drops any audio still
+    // queued, or, if it is rejected, leaves the machine exactly as it was,
+    // queued audio included.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let backup = self.save_state();
+        if let Err(error) = self.restore_state(data) {
+            self.restore_state(&backup)
+                .expect("a snapshot of this machine must load back");
+            return Err(error);
+        }
+        self.psg.borrow_mut().take_samples();
+        if let Some(ref opll) = self.opll {
+            opll.borrow_mut().take_samples();
+        }
+        Ok(())
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut input = StateReader::new(data);
+        if input.read_bytes(state::MAGIC.len()) != Ok(&state::MAGIC[..]) {
+            return Err(StateError::BadMagic);
+        }
+        let version = input.read_u16()?;
+        if version != state::VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if input.read_u8()? != self.layout() {
+            return Err(StateError::Mismatch("machine configuration"));
+        }
+        self.cpu.load_state(&mut input)?;
+        self.bus.load_state(&mut input)?;
+        self.irq_line = input.read_bool()?;
+        self.region = if input.read_bool()? {
+            Region::Japan
+        } else {
+            Region::Export
+        };
+        self.cycles = input.read_u64()?;
+        self.vdp.load_state(&mut input)?;
+        self.psg.load_state(&mut input)?;
+        if let Some(ref mut opll) = self.opll {
+            opll.load_state(&mut input)?;
+        }
+        self.joypads.load_state(&mut input)?;
+        input.finish()
     }
 }
@@ -1,4 +1,10 @@
+pub mod audio;
+pub mod cartridge;
 pub mod cpu;
 pub mod instructions;
+pub mod io;
 pub mod machine;
 pub mod ram;
+pub mod rewind;
+pub mod state;
+pub mod video;
@@ -0,0 +1,33 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use vm::cpu::registers::Registers;
+use vm::state::Snapshot;
+
+pub trait Bus: Snapshot {
+    fn read_u8(&self, address: u16) -> u8;
+
+    fn write_u8(&mut self, address: u16, value: u8);
+
+    fn read_u16(&self, address: u16) -> u16 {
+        let low = self.read_u8(address) as u16;
+        let high = self.read_u8(address.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    fn write_u16(&mut self, address: u16, value: u16) {
+        let (high, low) = Registers::u16_to_u8s(value);
+        self.write_u8(address, low);
+        self.write_u8(address.wrapping_add(1), high);
+    }
+}
+
+// Lets the owner keep a handle on a cartridge, e.g. to persist its SRAM.
+impl<T: Bus> Bus for Rc<RefCell<T>> {
+    fn read_u8(&self, address: u16) -> u8 {
+        self.borrow().read_u8(address)
+    }
+
+    fn write_u8(&mut self, address: u16, value: u8) {
+        self.borrow_mut().write_u8(address, value)
+    }
+}
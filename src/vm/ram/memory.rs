@@ -1,4 +1,5 @@
-use vm::cpu::registers::Registers;
+use vm::ram::bus::Bus;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
 
 pub struct Memory {
     data: [u8; 65536],
@@ -8,24 +9,24 @@ impl Memory {
     pub fn new() -> Memory {
         Memory { data: [0; 65536] }
     }
+}
 
-    pub fn read_u8(&self, address: u16) -> u8 {
+impl Bus for Memory {
+    fn read_u8(&self, address: u16) -> u8 {
         self.data[address as usize]
     }
 
-    pub fn read_u16(&self, address: u16) -> u16 {
-        let low = self.read_u8(address) as u16;
-        let high = self.read_u8(address + 1) as u16;
-        (high << 8) | low
+    fn write_u8(&mut self, address: u16, value: u8) {
+        self.data[address as usize] = value;
     }
+}
 
-    pub fn write_u8(&mut self, address: u16, value: u8) {
-        self.data[address as usize] = value;
+impl Snapshot for Memory {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_bytes(&self.data);
     }
 
-    pub fn write_u16(&mut self, address: u16, value: u16) {
-        let (low, high) = Registers::u16_to_u8s(value);
-        self.write_u8(address, low);
-        self.write_u8(address + 1, high);
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        input.read_into(&mut self.data)
     }
 }
@@ -1 +1,4 @@
+pub mod bus;
 pub mod memory;
+pub mod rom;
+pub mod system;
@@ -0,0 +1,39 @@
+use vm::ram::bus::Bus;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// An unbanked image mapped from 0x0000; writes are ignored and reads past
+// the end of the image float high.
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>) -> Rom {
+        Rom { data }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl Bus for Rom {
+    fn read_u8(&self, address: u16) -> u8 {
+        self.data.get(address as usize).cloned().unwrap_or(0xFF)
+    }
+
+    fn write_u8(&mut self, _address: u16, _value: u8) {}
+}
+
+// Nothing to save: the image itself is never part of a snapshot.
+impl Snapshot for Rom {
+    fn save_state(&self, _out: &mut StateWriter) {}
+
+    fn load_state(&mut self, _input: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
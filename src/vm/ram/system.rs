@@ -0,0 +1,68 @@
+use vm::ram::bus::Bus;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const WORK_RAM_START: u16 = 0xC000;
+pub const WORK_RAM_SIZE: usize = 0x2000;
+
+// The Master System memory map: the cartridge slot answers reads below
+// 0xC000 and the 8 KiB of work RAM is mirrored across 0xC000-0xFFFF.
+// Every write is also offered to the cartridge so mappers can latch their
+// control registers, which on the Sega mapper live inside the RAM mirror.
+pub struct SystemBus {
+    cartridge: Box<dyn Bus>,
+    work_ram: Vec<u8>,
+}
+
+impl SystemBus {
+    pub fn new(cartridge: Box<dyn Bus>) -> SystemBus {
+        SystemBus {
+            cartridge,
+            work_ram: vec![0; WORK_RAM_SIZE],
+        }
+    }
+
+    pub fn cartridge(&self) -> &dyn Bus {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut dyn Bus {
+        self.cartridge.as_mut()
+    }
+
+    pub fn work_ram(&self) -> &[u8] {
+        &self.work_ram
+    }
+
+    fn work_ram_index(address: u16) -> usize {
+        address as usize & (WORK_RAM_SIZE - 1)
+    }
+}
+
+impl Bus for SystemBus {
+    fn read_u8(&self, address: u16) -> u8 {
+        if address >= WORK_RAM_START {
+            self.work_ram[SystemBus::work_ram_index(address)]
+        } else {
+            self.cartridge.read_u8(address)
+        }
+    }
+
+    fn write_u8(&mut self, address: u16, value: u8) {
+        self.cartridge.write_u8(address, value);
+        if address >= WORK_RAM_START {
+            self.work_ram[SystemBus::work_ram_index(address)] = value;
+        }
+    }
+}
+
+impl Snapshot for SystemBus {
+    fn save_state(&self, out: &mut StateWriter) {
+        self.cartridge.save_state(out);
+        out.write_bytes(&self.work_ram);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        self.cartridge.load_state(input)?;
+        input.read_into(&mut self.work_ram)
+    }
+}
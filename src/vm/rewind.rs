@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Period {
+    Frames(u64),
+    Cycles(u64),
+}
+
+// A snapshot is taken every interval and the oldest is dropped once there
+// are more than `snapshots`, so the buffer reaches back interval times
+// snapshots.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RewindConfig {
+    pub interval: Period,
+    pub snapshots: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> RewindConfig {
+        RewindConfig {
+            interval: Period::Frames(1),
+            snapshots: 600,
+        }
+    }
+}
+
+// Only the newest snapshot is kept whole. Each older one is stored as the
+// XOR against its successor, run-length encoded: consecutive snapshots
+// differ in few bytes, so most of the 64 KiB of memory and the VRAM
+// collapse into a handful of zero runs. Walking back applies the deltas
+// newest first, and dropping the oldest never touches the others.
+struct Delta {
+    length: usize,
+    runs: Vec<u8>,
+}
+
+pub struct RewindBuffer {
+    config: RewindConfig,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    last_frame: u64,
+    last_cycles: u64,
+}
+
+impl RewindBuffer {
+    pub fn new(config: RewindConfig) -> RewindBuffer {
+        RewindBuffer {
+            config,
+            latest: None,
+            deltas: VecDeque::new(),
+            last_frame: 0,
+            last_cycles: 0,
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    // Bytes held by the snapshots, ignoring allocator overhead.
+    pub fn memory_usage(&self) -> usize {
+        let latest = self.latest.as_ref().map_or(0, |latest| latest.len());
+        latest
+            + self
+                .deltas
+                .iter()
+                .map(|delta| delta.runs.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_due(&self, frame: u64, cycles: u64) -> bool {
+        if self.latest.is_none() {
+            return true;
+        }
+        match self.config.interval {
+            Period::Frames(frames) => frame.wrapping_sub(self.last_frame) >= frames.max(1),
+            Period::Cycles(period) => cycles.wrapping_sub(self.last_cycles) >= period.max(1),
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>, frame: u64, cycles: u64) {
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(Delta {
+                length: previous.len(),
+                runs: encode_delta(&previous, &snapshot),
+            });
+        }
+        while self.deltas.len() >= self.config.snapshots.max(1) {
+            self.deltas.pop_front();
+        }
+        self.latest = Some(snapshot);
+        self.mark(frame, cycles);
+    }
+
+    // Discards the newest `count - 1` snapshots and returns the one that is
+    // then the newest, which stays in the buffer so the same point can be
+    // returned to again. Stops at the oldest snapshot.
+    pub fn rewind(&mut self, count: usize) -> Option<&[u8]> {
+        if count == 0 {
+            return None;
+        }
+        let mut latest = self.latest.take()?;
+        for _ in 1..count {
+            match self.deltas.pop_back() {
+                Some(delta) => latest = decode_delta(&latest, &delta.runs, delta.length),
+                None => break,
+            }
+        }
+        self.latest = Some(latest);
+        self.latest.as_ref().map(|latest| &latest[..])
+    }
+
+    // Restarts the interval from the given point, e.g. after the machine has
+    // been rewound to it.
+    pub fn mark(&mut self, frame: u64, cycles: u64) {
+        self.last_frame = frame;
+        self.last_cycles = cycles;
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+// The XOR of `older` against `newer`, as alternating runs: a varint count of
+// zero bytes, a varint count of literal bytes and then the literals.
+// `newer` is treated as zero past its end.
+pub fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = older
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| byte ^ newer.get(i).cloned().unwrap_or(0))
+        .collect();
+    let mut runs = Vec::new();
+    let mut position = 0;
+    while position < xor.len() {
+        let zeros = xor[position..]
+            .iter()
+            .take_while(|&&byte| byte == 0)
+            .count();
+        position += zeros;
+        // Trailing zeros are implied by the length.
+        if position == xor.len() {
+            break;
+        }
+        let literals = xor[position..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .count();
+        write_varint(&mut runs, zeros);
+        write_varint(&mut runs, literals);
+        runs.extend_from_slice(&xor[position..position + literals]);
+        position += literals;
+    }
+    runs
+}
+
+pub fn decode_delta(newer: &[u8], runs: &[u8], length: usize) -> Vec<u8> {
+    let mut older: Vec<u8> = (0..length)
+        .map(|i| newer.get(i).cloned().unwrap_or(0))
+        .collect();
+    let mut position = 0;
+    let mut input = runs;
+    while !input.is_empty() {
+        position += read_varint(&mut input);
+        let literals = read_varint(&mut input);
+        for (byte, &xor) in older[position..position + literals]
+            .iter_mut()
+            .zip(&input[..literals])
+        {
+            *byte ^= xor;
+        }
+        input = &input[literals..];
+        position += literals;
+    }
+    older
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = input.split_first() {
+        *input = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
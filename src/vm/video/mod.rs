@@ -0,0 +1,6 @@
+pub mod mode4;
+pub mod palette;
+pub mod sprites;
+pub mod timing;
+pub mod tms;
+pub mod vdp;
@@ -0,0 +1,86 @@
+use vm::video::vdp::{Vdp, SCREEN_WIDTH};
+
+pub const TILE_SIZE: usize = 32;
+pub const SPRITE_PALETTE: u8 = 0x10;
+
+const NAME_TABLE_ROWS: usize = 28;
+const ENTRY_TILE_HIGH: u8 = 0x01;
+const ENTRY_HORIZONTAL_FLIP: u8 = 0x02;
+const ENTRY_VERTICAL_FLIP: u8 = 0x04;
+const ENTRY_PALETTE: u8 = 0x08;
+const ENTRY_PRIORITY: u8 = 0x10;
+
+const REG0_LOCK_TOP_ROWS: u8 = 0x40;
+const REG0_LOCK_RIGHT_COLUMNS: u8 = 0x80;
+
+// Tiles are stored as four interleaved bitplanes, one byte per plane per
+// row, with the leftmost pixel in bit 7.
+pub fn tile_pixel(vram: &[u8], tile: usize, row: usize, column: usize) -> u8 {
+    let address = (tile * TILE_SIZE + row * 4) % vram.len();
+    let bit = 7 - column;
+    vram[address..address + 4]
+        .iter()
+        .enumerate()
+        .fold(0, |color, (plane, byte)| {
+            color | (((byte >> bit) & 0x01) << plane)
+        })
+}
+
+impl Vdp {
+    pub fn name_table_address(&self) -> usize {
+        (self.register(2) as usize & 0x0E) << 10
+    }
+
+    // Fills one scanline with CRAM indices and marks the pixels where the
+    // background is drawn in front of sprites.
+    pub(crate) fn render_background(
+        &self,
+        line: usize,
+        pixels: &mut [u8; SCREEN_WIDTH],
+        priority: &mut [bool; SCREEN_WIDTH],
+    ) {
+        let vram = self.vram();
+        let horizontal_scroll = if line < 16 && self.register(0) & REG0_LOCK_TOP_ROWS != 0 {
+            0
+        } else {
+            self.register(8) as usize
+        };
+        let vertical_scroll = self.register(9) as usize;
+        let name_table = self.name_table_address();
+
+        for screen_column in 0..SCREEN_WIDTH / 8 {
+            let map_line = if screen_column >= 24 && self.register(0) & REG0_LOCK_RIGHT_COLUMNS != 0
+            {
+                line
+            } else {
+                (line + vertical_scroll) % (NAME_TABLE_ROWS * 8)
+            };
+            for fine in 0..8 {
+                let x = screen_column * 8 + fine;
+                let map_x = (x + SCREEN_WIDTH - horizontal_scroll) % SCREEN_WIDTH;
+                let entry_address = name_table + ((map_line / 8) * 32 + map_x / 8) * 2;
+                let low = vram[entry_address];
+                let high = vram[entry_address + 1];
+                let tile = ((high & ENTRY_TILE_HIGH) as usize) << 8 | low as usize;
+                let row = if high & ENTRY_VERTICAL_FLIP != 0 {
+                    7 - map_line % 8
+                } else {
+                    map_line % 8
+                };
+                let column = if high & ENTRY_HORIZONTAL_FLIP != 0 {
+                    7 - map_x % 8
+                } else {
+                    map_x % 8
+                };
+                let color = tile_pixel(vram, tile, row, column);
+                let palette = if high & ENTRY_PALETTE != 0 {
+                    SPRITE_PALETTE
+                } else {
+                    0
+                };
+                pixels[x] = palette | color;
+                priority[x] = high & ENTRY_PRIORITY != 0 && color != 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+// CRAM entries are --BBGGRR; each two bit channel scales to a full byte.
+pub fn sms_color(entry: u8) -> u32 {
+    let channel = |shift: u8| ((entry >> shift) & 0x03) as u32 * 0x55;
+    (channel(0) << 16) | (channel(2) << 8) | channel(4)
+}
+
+// The SMS VDP has no TMS9918 palette of its own; these are the CRAM style
+// values it substitutes for the sixteen legacy colors.
+const TMS_PALETTE: [u8; 16] = [
+    0x00, 0x00, 0x08, 0x0C, 0x10, 0x30, 0x01, 0x3C, 0x02, 0x03, 0x05, 0x0F, 0x04, 0x33, 0x15, 0x3F,
+];
+
+pub fn tms_color(index: u8) -> u32 {
+    sms_color(TMS_PALETTE[index as usize & 0x0F])
+}
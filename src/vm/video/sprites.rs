@@ -0,0 +1,131 @@
+use vm::video::mode4::{tile_pixel, SPRITE_PALETTE};
+use vm::video::vdp::{Vdp, SCREEN_WIDTH, STATUS_SPRITE_COLLISION, STATUS_SPRITE_OVERFLOW};
+
+pub const SPRITE_COUNT: usize = 64;
+pub const SPRITES_PER_LINE: usize = 8;
+
+// In 192-line mode a Y of 0xD0 ends the table.
+const TERMINATOR: u8 = 0xD0;
+
+const REG0_SHIFT_LEFT: u8 = 0x08;
+const REG1_ZOOM: u8 = 0x01;
+const REG1_TALL: u8 = 0x02;
+const REG6_HIGH_TILES: u8 = 0x04;
+
+impl Vdp {
+    pub fn sprite_table_address(&self) -> usize {
+        (self.register(5) as usize & 0x7E) << 7
+    }
+
+    fn sprite_height(&self) -> usize {
+        if self.register(1) & REG1_TALL != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    fn sprite_scale(&self) -> usize {
+        if self.register(1) & REG1_ZOOM != 0 {
+            2
+        } else {
+            1
+        }
+    }
+
+    // Finds the sprites covering a line in table order. Only the first
+    // eight are returned; a ninth raises the overflow flag.
+    fn evaluate_sprites(&mut self, line: usize) -> Vec<usize> {
+        let table = self.sprite_table_address();
+        let height = self.sprite_height() * self.sprite_scale();
+        let mut visible = Vec::with_capacity(SPRITES_PER_LINE);
+        for sprite in 0..SPRITE_COUNT {
+            let y = self.vram()[table + sprite];
+            if y == TERMINATOR {
+                break;
+            }
+            let top = sprite_top(y);
+            if (line as i32) < top || (line as i32) >= top + height as i32 {
+                continue;
+            }
+            if visible.len() == SPRITES_PER_LINE {
+                self.set_status(STATUS_SPRITE_OVERFLOW);
+                break;
+            }
+            visible.push(sprite);
+        }
+        visible
+    }
+
+    // Draws over the background wherever it has no priority; earlier
+    // entries in the table win when sprites overlap.
+    pub(crate) fn render_sprites(
+        &mut self,
+        line: usize,
+        pixels: &mut [u8; SCREEN_WIDTH],
+        priority: &[bool; SCREEN_WIDTH],
+    ) {
+        let visible = self.evaluate_sprites(line);
+        let table = self.sprite_table_address();
+        let scale = self.sprite_scale();
+        let tall = self.sprite_height() == 16;
+        let tile_base = if self.register(6) & REG6_HIGH_TILES != 0 {
+            256
+        } else {
+            0
+        };
+        let shift = if self.register(0) & REG0_SHIFT_LEFT != 0 {
+            8
+        } else {
+            0
+        };
+
+        let mut drawn = [false; SCREEN_WIDTH];
+        let mut collision = false;
+        {
+            let vram = self.vram();
+            for &sprite in &visible {
+                let x = vram[table + 0x80 + sprite * 2] as i32 - shift;
+                let mut tile = vram[table + 0x81 + sprite * 2] as usize;
+                if tall {
+                    tile &= 0xFE;
+                }
+                let row = (line as i32 - sprite_top(vram[table + sprite])) as usize / scale;
+                let tile = tile_base + tile + row / 8;
+                for offset in 0..8 * scale {
+                    let screen_x = x + offset as i32;
+                    if screen_x < 0 || screen_x >= SCREEN_WIDTH as i32 {
+                        continue;
+                    }
+                    let screen_x = screen_x as usize;
+                    let color = tile_pixel(vram, tile, row % 8, offset / scale);
+                    if color == 0 {
+                        continue;
+                    }
+                    if drawn[screen_x] {
+                        collision = true;
+                        continue;
+                    }
+                    drawn[screen_x] = true;
+                    if !priority[screen_x] {
+                        pixels[screen_x] = SPRITE_PALETTE | color;
+                    }
+                }
+            }
+        }
+        if collision {
+            self.set_status(STATUS_SPRITE_COLLISION);
+        }
+    }
+}
+
+// Sprites start on the line below their Y coordinate, and the bottom of
+// the range wraps round so sprites can slide in from the top edge.
+fn sprite_top(y: u8) -> i32 {
+    let top = y as i32 + 1;
+    if top > 240 {
+        top - 256
+    } else {
+        top
+    }
+}
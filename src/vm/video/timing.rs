@@ -0,0 +1,41 @@
+pub const CYCLES_PER_LINE: u32 = 228;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+}
+
+impl TvSystem {
+    pub fn lines_per_frame(self) -> usize {
+        match self {
+            TvSystem::Ntsc => 262,
+            TvSystem::Pal => 313,
+        }
+    }
+
+    // The V counter is only eight bits wide, so partway through the
+    // vertical blank it jumps back to fit a whole frame into 256 values.
+    pub fn v_counter(self, line: usize) -> u8 {
+        let (last, jump) = match self {
+            TvSystem::Ntsc => (0xDA, 0xD5),
+            TvSystem::Pal => (0xF2, 0xBA),
+        };
+        if line <= last {
+            line as u8
+        } else {
+            (line - last - 1 + jump) as u8
+        }
+    }
+}
+
+// The H counter counts every other pixel of the 342 pixel line and skips
+// from 0x93 to 0xE9 during horizontal blanking.
+pub fn h_counter(line_cycles: u32) -> u8 {
+    let position = line_cycles * 3 / 4;
+    if position <= 0x93 {
+        position as u8
+    } else {
+        (position - 0x94 + 0xE9) as u8
+    }
+}
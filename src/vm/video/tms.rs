@@ -0,0 +1,220 @@
+use vm::video::vdp::{
+    DisplayMode, Vdp, SCREEN_WIDTH, STATUS_SPRITE_COLLISION, STATUS_SPRITE_OVERFLOW,
+};
+
+pub const TMS_SPRITE_COUNT: usize = 32;
+pub const TMS_SPRITES_PER_LINE: usize = 4;
+
+const TERMINATOR: u8 = 0xD0;
+const TEXT_BORDER: usize = 8;
+const TEXT_COLUMNS: usize = 40;
+
+const REG1_MAGNIFY: u8 = 0x01;
+const REG1_LARGE: u8 = 0x02;
+const SPRITE_EARLY_CLOCK: u8 = 0x80;
+
+impl Vdp {
+    fn tms_name_table(&self) -> usize {
+        (self.register(2) as usize & 0x0F) << 10
+    }
+
+    fn tms_color_table(&self) -> usize {
+        (self.register(3) as usize) << 6
+    }
+
+    fn tms_pattern_table(&self) -> usize {
+        (self.register(4) as usize & 0x07) << 11
+    }
+
+    fn tms_sprite_table(&self) -> usize {
+        (self.register(5) as usize & 0x7F) << 7
+    }
+
+    fn tms_sprite_patterns(&self) -> usize {
+        (self.register(6) as usize & 0x07) << 11
+    }
+
+    pub fn text_color(&self) -> u8 {
+        self.register(7) >> 4
+    }
+
+    // Fills one scanline with TMS palette indices; transparent pixels are
+    // left as 0 for the caller to replace with the backdrop.
+    pub(crate) fn render_tms_line(&mut self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        match self.display_mode() {
+            DisplayMode::Graphics1 => self.render_graphics1(line, pixels),
+            DisplayMode::Graphics2 => self.render_graphics2(line, pixels),
+            DisplayMode::Multicolor => self.render_multicolor(line, pixels),
+            DisplayMode::Text => {
+                self.render_text(line, pixels);
+                // Text mode has no sprites.
+                return;
+            }
+            DisplayMode::Mode4 => return,
+        }
+        self.render_tms_sprites(line, pixels);
+    }
+
+    fn render_graphics1(&self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        let vram = self.vram();
+        let names = self.tms_name_table() + (line / 8) * 32;
+        for column in 0..32 {
+            let name = vram[names + column] as usize;
+            let pattern = vram[self.tms_pattern_table() + name * 8 + line % 8];
+            let colors = vram[self.tms_color_table() + name / 8];
+            draw_pattern(&mut pixels[column * 8..column * 8 + 8], pattern, colors);
+        }
+    }
+
+    // The screen is split into thirds with their own patterns and colors;
+    // the low bits of registers 3 and 4 mask the table addresses, which
+    // games use to share tables between thirds.
+    fn render_graphics2(&self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        let vram = self.vram();
+        let names = self.tms_name_table() + (line / 8) * 32;
+        let third = line / 64;
+        let pattern_base = (self.register(4) as usize & 0x04) << 11;
+        let pattern_mask = (self.register(4) as usize & 0x03) << 11 | 0x07FF;
+        let color_base = (self.register(3) as usize & 0x80) << 6;
+        let color_mask = (self.register(3) as usize & 0x7F) << 6 | 0x003F;
+        for column in 0..32 {
+            let name = vram[names + column] as usize;
+            let offset = third << 11 | name << 3 | (line % 8);
+            let pattern = vram[pattern_base | (offset & pattern_mask)];
+            let colors = vram[color_base | (offset & color_mask)];
+            draw_pattern(&mut pixels[column * 8..column * 8 + 8], pattern, colors);
+        }
+    }
+
+    // Each name selects a block of 4x4 pixel cells, two per byte.
+    fn render_multicolor(&self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        let vram = self.vram();
+        let names = self.tms_name_table() + (line / 8) * 32;
+        for column in 0..32 {
+            let name = vram[names + column] as usize;
+            let address =
+                self.tms_pattern_table() + name * 8 + ((line / 8) & 0x03) * 2 + (line / 4) % 2;
+            let colors = vram[address];
+            for (x, pixel) in pixels[column * 8..column * 8 + 8].iter_mut().enumerate() {
+                *pixel = if x < 4 { colors >> 4 } else { colors & 0x0F };
+            }
+        }
+    }
+
+    // 40 columns of six pixel wide characters in two colors from register
+    // 7, centred between eight pixel borders.
+    fn render_text(&self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        let vram = self.vram();
+        let names = self.tms_name_table() + (line / 8) * TEXT_COLUMNS;
+        let colors = self.register(7);
+        for column in 0..TEXT_COLUMNS {
+            let name = vram[names + column] as usize;
+            let pattern = vram[self.tms_pattern_table() + name * 8 + line % 8];
+            let start = TEXT_BORDER + column * 6;
+            draw_pattern(&mut pixels[start..start + 6], pattern, colors);
+        }
+    }
+
+    fn render_tms_sprites(&mut self, line: usize, pixels: &mut [u8; SCREEN_WIDTH]) {
+        let table = self.tms_sprite_table();
+        let size = if self.register(1) & REG1_LARGE != 0 {
+            16
+        } else {
+            8
+        };
+        let scale = if self.register(1) & REG1_MAGNIFY != 0 {
+            2
+        } else {
+            1
+        };
+
+        let mut visible = Vec::with_capacity(TMS_SPRITES_PER_LINE);
+        let mut fifth = None;
+        for sprite in 0..TMS_SPRITE_COUNT {
+            let y = self.vram()[table + sprite * 4];
+            if y == TERMINATOR {
+                break;
+            }
+            let top = tms_sprite_top(y);
+            if (line as i32) < top || (line as i32) >= top + (size * scale) as i32 {
+                continue;
+            }
+            if visible.len() == TMS_SPRITES_PER_LINE {
+                fifth = Some(sprite as u8);
+                break;
+            }
+            visible.push(sprite);
+        }
+
+        let mut drawn = [false; SCREEN_WIDTH];
+        let mut collision = false;
+        {
+            let vram = self.vram();
+            for &sprite in &visible {
+                let attributes = &vram[table + sprite * 4..table + sprite * 4 + 4];
+                let row = (line as i32 - tms_sprite_top(attributes[0])) as usize / scale;
+                let mut x = attributes[1] as i32;
+                if attributes[3] & SPRITE_EARLY_CLOCK != 0 {
+                    x -= 32;
+                }
+                let color = attributes[3] & 0x0F;
+                let pattern = if size == 16 {
+                    attributes[2] as usize & 0xFC
+                } else {
+                    attributes[2] as usize
+                };
+                for offset in 0..size * scale {
+                    let screen_x = x + offset as i32;
+                    if screen_x < 0 || screen_x >= SCREEN_WIDTH as i32 {
+                        continue;
+                    }
+                    let column = offset / scale;
+                    // 16x16 sprites are four patterns: top left, bottom
+                    // left, top right, bottom right.
+                    let quadrant = (column / 8) * 2 + row / 8;
+                    let address = self.tms_sprite_patterns() + (pattern + quadrant) * 8 + row % 8;
+                    if vram[address] & (0x80 >> (column % 8)) == 0 {
+                        continue;
+                    }
+                    let screen_x = screen_x as usize;
+                    if drawn[screen_x] {
+                        collision = true;
+                        continue;
+                    }
+                    drawn[screen_x] = true;
+                    if color != 0 {
+                        pixels[screen_x] = color;
+                    }
+                }
+            }
+        }
+        if collision {
+            self.set_status(STATUS_SPRITE_COLLISION);
+        }
+        if let Some(sprite) = fifth {
+            self.set_fifth_sprite(sprite);
+            self.set_status(STATUS_SPRITE_OVERFLOW);
+        }
+    }
+}
+
+// Set pattern bits take the foreground color from the high nibble, clear
+// ones the background from the low nibble.
+fn draw_pattern(pixels: &mut [u8], pattern: u8, colors: u8) {
+    for (x, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = if pattern & (0x80 >> x) != 0 {
+            colors >> 4
+        } else {
+            colors & 0x0F
+        };
+    }
+}
+
+fn tms_sprite_top(y: u8) -> i32 {
+    let top = y as i32 + 1;
+    if top > 0xE0 {
+        top - 256
+    } else {
+        top
+    }
+}
@@ -0,0 +1,419 @@
+use vm::io::port::PortDevice;
+use vm::state::{Snapshot, StateError, StateReader, StateWriter};
+use vm::video::mode4::SPRITE_PALETTE;
+use vm::video::palette;
+use vm::video::timing::{self, TvSystem, CYCLES_PER_LINE};
+
+pub const VRAM_SIZE: usize = 0x4000;
+pub const CRAM_SIZE: usize = 32;
+pub const REGISTER_COUNT: usize = 11;
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 192;
+
+pub const STATUS_FRAME_INTERRUPT: u8 = 0x80;
+pub const STATUS_SPRITE_OVERFLOW: u8 = 0x40;
+pub const STATUS_SPRITE_COLLISION: u8 = 0x20;
+
+const REG0_MODE_2: u8 = 0x02;
+const REG0_MODE_4: u8 = 0x04;
+const REG0_LINE_INTERRUPT_ENABLE: u8 = 0x10;
+const REG0_BLANK_LEFT_COLUMN: u8 = 0x20;
+const REG1_FRAME_INTERRUPT_ENABLE: u8 = 0x20;
+const REG1_DISPLAY_ENABLE: u8 = 0x40;
+const REG1_MODE_3: u8 = 0x08;
+const REG1_MODE_1: u8 = 0x10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    Graphics1,
+    Graphics2,
+    Text,
+    Multicolor,
+    Mode4,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessCode {
+    VramRead,
+    VramWrite,
+    RegisterWrite,
+    CramWrite,
+}
+
+impl AccessCode {
+    pub fn from_bits(bits: u8) -> AccessCode {
+        match bits & 0x03 {
+            0 => AccessCode::VramRead,
+            1 => AccessCode::VramWrite,
+            2 => AccessCode::RegisterWrite,
+            _ => AccessCode::CramWrite,
+        }
+    }
+}
+
+pub struct Vdp {
+    vram: Vec<u8>,
+    cram: [u8; CRAM_SIZE],
+    registers: [u8; REGISTER_COUNT],
+    address: u16,
+    code: AccessCode,
+    // Low byte of a control word waiting for its second half.
+    latch: Option<u8>,
+    buffer: u8,
+    status: u8,
+    line_interrupt: bool,
+    tv_system: TvSystem,
+    line: usize,
+    line_cycles: u32,
+    line_counter: u8,
+    h_counter: u8,
+    frame: u64,
+    framebuffer: Vec<u32>,
+}
+
+impl Vdp {
+    pub fn new() -> Vdp {
+        Vdp {
+            vram: vec![0; VRAM_SIZE],
+            cram: [0; CRAM_SIZE],
+            registers: [0; REGISTER_COUNT],
+            address: 0x0000,
+            code: AccessCode::VramRead,
+            latch: None,
+            buffer: 0x00,
+            status: 0x00,
+            line_interrupt: false,
+            tv_system: TvSystem::Ntsc,
+            line: 0,
+            line_cycles: 0,
+            line_counter: 0x00,
+            h_counter: 0x00,
+            frame: 0,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    pub fn cram(&self) -> &[u8] {
+        &self.cram
+    }
+
+    pub fn register(&self, index: usize) -> u8 {
+        self.registers[index]
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn code(&self) -> AccessCode {
+        self.code
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.tv_system
+    }
+
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        self.tv_system = tv_system;
+        self.line %= tv_system.lines_per_frame();
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn v_counter(&self) -> u8 {
+        self.tv_system.v_counter(self.line)
+    }
+
+    // Port 0x7F returns the H counter as it was when last latched by the
+    // TH pins of the controller ports.
+    pub fn h_counter(&self) -> u8 {
+        self.h_counter
+    }
+
+    pub fn latch_h_counter(&mut self) {
+        self.h_counter = timing::h_counter(self.line_cycles);
+    }
+
+    pub fn interrupt_asserted(&self) -> bool {
+        let frame = self.status & STATUS_FRAME_INTERRUPT != 0
+            && self.register(1) & REG1_FRAME_INTERRUPT_ENABLE != 0;
+        let line = self.line_interrupt && self.register(0) & REG0_LINE_INTERRUPT_ENABLE != 0;
+        frame || line
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.line_cycles += cycles;
+        while self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            self.end_line();
+        }
+    }
+
+    // The line counter runs through the active display and the line after
+    // it, raising an interrupt each time it underflows; for the rest of
+    // the blanking period it keeps being reloaded from register 10.
+    fn end_line(&mut self) {
+        if self.line < SCREEN_HEIGHT {
+            self.render_line(self.line);
+        }
+        if self.line <= SCREEN_HEIGHT {
+            let (counter, underflow) = self.line_counter.overflowing_sub(1);
+            self.line_counter = counter;
+            if underflow {
+                self.line_counter = self.register(10);
+                self.line_interrupt = true;
+            }
+        } else {
+            self.line_counter = self.register(10);
+        }
+        if self.line == SCREEN_HEIGHT {
+            self.status |= STATUS_FRAME_INTERRUPT;
+        }
+        self.line += 1;
+        if self.line == self.tv_system.lines_per_frame() {
+            self.line = 0;
+            self.frame += 1;
+        }
+    }
+
+    // 0x00RRGGBB pixels, row by row.
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer
+    }
+
+    // The mode bits are spread over registers 0 and 1; M4 selects the SMS
+    // mode and overrides the TMS9918 ones.
+    pub fn display_mode(&self) -> DisplayMode {
+        if self.register(0) & REG0_MODE_4 != 0 {
+            DisplayMode::Mode4
+        } else if self.register(1) & REG1_MODE_1 != 0 {
+            DisplayMode::Text
+        } else if self.register(0) & REG0_MODE_2 != 0 {
+            DisplayMode::Graphics2
+        } else if self.register(1) & REG1_MODE_3 != 0 {
+            DisplayMode::Multicolor
+        } else {
+            DisplayMode::Graphics1
+        }
+    }
+
+    pub fn display_enabled(&self) -> bool {
+        self.register(1) & REG1_DISPLAY_ENABLE != 0
+    }
+
+    pub fn backdrop_color(&self) -> u8 {
+        SPRITE_PALETTE | (self.register(7) & 0x0F)
+    }
+
+    pub fn render_frame(&mut self) {
+        for line in 0..SCREEN_HEIGHT {
+            self.render_line(line);
+        }
+    }
+
+    pub fn render_line(&mut self, line: usize) {
+        if self.display_mode() == DisplayMode::Mode4 {
+            self.render_mode4_line(line);
+        } else {
+            self.render_legacy_line(line);
+        }
+    }
+
+    fn render_mode4_line(&mut self, line: usize) {
+        let backdrop = self.backdrop_color();
+        let mut pixels = [backdrop; SCREEN_WIDTH];
+        let mut priority = [false; SCREEN_WIDTH];
+        if self.display_enabled() {
+            self.render_background(line, &mut pixels, &mut priority);
+            self.render_sprites(line, &mut pixels, &priority);
+            if self.register(0) & REG0_BLANK_LEFT_COLUMN != 0 {
+                for pixel in pixels[..8].iter_mut() {
+                    *pixel = backdrop;
+                }
+            }
+        }
+        let row = &mut self.framebuffer[line * SCREEN_WIDTH..(line + 1) * SCREEN_WIDTH];
+        for (output, &index) in row.iter_mut().zip(pixels.iter()) {
+            *output = palette::sms_color(self.cram[index as usize]);
+        }
+    }
+
+    // TMS9918 modes use the fixed palette, with color 0 showing the
+    // backdrop from the low nibble of register 7.
+    fn render_legacy_line(&mut self, line: usize) {
+        let mut pixels = [0; SCREEN_WIDTH];
+        if self.display_enabled() {
+            self.render_tms_line(line, &mut pixels);
+        }
+        let backdrop = self.register(7) & 0x0F;
+        let row = &mut self.framebuffer[line * SCREEN_WIDTH..(line + 1) * SCREEN_WIDTH];
+        for (output, &index) in row.iter_mut().zip(pixels.iter()) {
+            let index = if index == 0 { backdrop } else { index };
+            *output = palette::tms_color(index);
+        }
+    }
+
+    pub(crate) fn set_status(&mut self, flags: u8) {
+        self.status |= flags;
+    }
+
+    // TMS9918 modes report which sprite was dropped in the low five bits.
+    pub(crate) fn set_fifth_sprite(&mut self, sprite: u8) {
+        if self.status & STATUS_SPRITE_OVERFLOW == 0 {
+            self.status = (self.status & 0xE0) | (sprite & 0x1F);
+        }
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        self.latch = None;
+        let value = self.buffer;
+        self.buffer = self.vram[self.address as usize];
+        self.increment_address();
+        value
+    }
+
+    // The data port goes to CRAM or VRAM depending on the last access code;
+    // either way the written value also replaces the read-ahead buffer.
+    pub fn write_data(&mut self, value: u8) {
+        self.latch = None;
+        if self.code == AccessCode::CramWrite {
+            self.cram[self.address as usize % CRAM_SIZE] = value;
+        } else {
+            self.vram[self.address as usize] = value;
+        }
+        self.buffer = value;
+        self.increment_address();
+    }
+
+    // Reading the status register acknowledges both interrupts and clears
+    // the sprite flags.
+    pub fn read_control(&mut self) -> u8 {
+        self.latch = None;
+        self.line_interrupt = false;
+        let status = self.status;
+        self.status &= !(STATUS_FRAME_INTERRUPT | STATUS_SPRITE_OVERFLOW | STATUS_SPRITE_COLLISION);
+        status
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        match self.latch.take() {
+            None => {
+                self.address = (self.address & 0x3F00) | value as u16;
+                self.latch = Some(value);
+            }
+            Some(low) => {
+                self.address = ((value as u16 & 0x3F) << 8) | low as u16;
+                self.code = AccessCode::from_bits(value >> 6);
+                match self.code {
+                    AccessCode::VramRead => {
+                        self.buffer = self.vram[self.address as usize];
+                        self.increment_address();
+                    }
+                    AccessCode::RegisterWrite => self.write_register(value & 0x0F, low),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn write_register(&mut self, index: u8, value: u8) {
+        if let Some(register) = self.registers.get_mut(index as usize) {
+            *register = value;
+        }
+    }
+
+    fn increment_address(&mut self) {
+        self.address = (self.address + 1) & 0x3FFF;
+    }
+}
+
+// The VDP decodes bits 7, 6 and 0 of the port number: reads below 0x80
+// return the counters, and from 0x80 on even ports are data and odd ports
+// are control.
+impl PortDevice for Vdp {
+    fn read(&mut self, port: u8) -> u8 {
+        match (port & 0x80 != 0, port & 0x01 != 0) {
+            (false, false) => self.v_counter(),
+            (false, true) => self.h_counter(),
+            (true, false) => self.read_data(),
+            (true, true) => self.read_control(),
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        if port & 0x01 == 0 {
+            self.write_data(value);
+        } else {
+            self.write_control(value);
+        }
+    }
+}
+
+impl Snapshot for Vdp {
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_bytes(&self.vram);
+        out.write_bytes(&self.cram);
+        out.write_bytes(&self.registers);
+        out.write_u16(self.address);
+        out.write_u8(self.code as u8);
+        out.write_bool(self.latch.is_some());
+        out.write_u8(self.latch.unwrap_or(0));
+        out.write_u8(self.buffer);
+        out.write_u8(self.status);
+        out.write_bool(self.line_interrupt);
+        out.write_bool(self.tv_system == TvSystem::Pal);
+        out.write_u16(self.line as u16);
+        out.write_u32(self.line_cycles);
+        out.write_u8(self.line_counter);
+        out.write_u8(self.h_counter);
+        out.write_u64(self.frame);
+        for &pixel in &self.framebuffer {
+            out.write_u32(pixel);
+        }
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), StateError> {
+        input.read_into(&mut self.vram)?;
+        input.read_into(&mut self.cram)?;
+        input.read_into(&mut self.registers)?;
+        self.address = input.read_u16()?;
+        self.code = AccessCode::from_bits(input.read_u8()?);
+        let latched = input.read_bool()?;
+        let latch = input.read_u8()?;
+        self.latch = if latched { Some(latch) } else { None };
+        self.buffer = input.read_u8()?;
+        self.status = input.read_u8()?;
+        self.line_interrupt = input.read_bool()?;
+        self.tv_system = if input.read_bool()? {
+            TvSystem::Pal
+        } else {
+            TvSystem::Ntsc
+        };
+        self.line = input.read_u16()? as usize;
+        if self.line >= self.tv_system.lines_per_frame() {
+            return Err(StateError::Invalid("VDP line"));
+        }
+        self.line_cycles = input.read_u32()?;
+        self.line_counter = input.read_u8()?;
+        self.h_counter = input.read_u8()?;
+        self.frame = input.read_u64()?;
+        for pixel in self.framebuffer.iter_mut() {
+            *pixel = input.read_u32()?;
+        }
+        Ok(())
+    }
+}